@@ -9,12 +9,15 @@ pub enum Expression {
     Member(Box<MemberExpression>),
     Nil,
     Bool(bool),
-    Number(f32),
+    Number(f64),
     String(String),
     Identifier(String),
     Object(Box<ObjectExpression>),
     Array(Box<ArrayExpression>),
-    Assigment(Box<AssignmentExpression>)
+    Assigment(Box<AssignmentExpression>),
+    Spawn(Box<CallExpression>),
+    Await(Box<AwaitExpression>),
+    Range(Box<RangeExpression>)
 }
 
 #[macro_export]
@@ -55,7 +58,7 @@ macro_rules! string {
 #[derive(Debug, Clone)]
 pub struct Identifier {
     pub name: String,
-    pub id: f32
+    pub id: f64
 }
 
 #[derive(Debug, Clone)]
@@ -122,6 +125,40 @@ macro_rules! call {
     };
 }
 
+#[macro_export]
+macro_rules! spawn {
+    { $($body:tt)* } => {
+        Expression::Spawn(Box::from(CallExpression { $($body)* }))
+    };
+}
+
+#[derive(Debug, Clone)]
+pub struct AwaitExpression {
+    pub expr: Expression
+}
+
+#[macro_export]
+macro_rules! await_ {
+    { $($body:tt)* } => {
+        Expression::Await(Box::from(AwaitExpression { $($body)* }))
+    };
+}
+
+#[derive(Debug, Clone)]
+pub struct RangeExpression {
+    pub start: Expression,
+    pub stop: Expression,
+    pub step: Option<Expression>,
+    pub inclusive: bool
+}
+
+#[macro_export]
+macro_rules! range {
+    { $($body:tt)* } => {
+        Expression::Range(Box::from(RangeExpression { $($body)* }))
+    };
+}
+
 #[derive(Debug, Clone)]
 pub struct BinaryExpression {
     pub left: Expression,
@@ -176,7 +213,8 @@ pub enum Operator {
     Pow,
     Mul,
     Mod,
-    Div
+    Div,
+    In
 }
 
 impl Expression {
@@ -187,7 +225,7 @@ impl Expression {
         }
     }
 
-    pub fn get_number(&self) -> Option<f32> {
+    pub fn get_number(&self) -> Option<f64> {
         match self {
             Expression::Number(val) => Some(*val),
             _ => None
@@ -1,4 +1,4 @@
-use crate::expression::{ArrayExpression, AssignmentExpression, BinaryExpression, CallExpression, Expression, GroupExpression, MemberExpression, ObjectExpression, UnaryExpression};
+use crate::expression::{ArrayExpression, AssignmentExpression, AwaitExpression, BinaryExpression, CallExpression, Expression, GroupExpression, MemberExpression, ObjectExpression, RangeExpression, UnaryExpression};
 
 pub trait ExpressionVisitor {
     fn visit_expression(&self, expression: &Expression) {
@@ -15,13 +15,16 @@ pub trait ExpressionVisitor {
             Expression::Group(ref value) => self.visit_group_expression(value),
             Expression::Member(ref value) => self.visit_member_expression(value),
             Expression::Object(ref value) => self.visit_object_expression(value),
-            Expression::Array(ref value) => self.visit_array_expression(value)
+            Expression::Array(ref value) => self.visit_array_expression(value),
+            Expression::Spawn(ref value) => self.visit_spawn_expression(value),
+            Expression::Await(ref value) => self.visit_await_expression(value),
+            Expression::Range(ref value) => self.visit_range_expression(value)
         }
     }
 
     fn visit_nil_expression(&self);
     fn visit_boolean_expression(&self, value: &bool);
-    fn visit_number_expression(&self, value: &f32);
+    fn visit_number_expression(&self, value: &f64);
     fn visit_string_expression(&self, value: &String);
     fn visit_identifier_expression(&self, value: &String);
     fn visit_call_expression(&self, value: &CallExpression);
@@ -32,6 +35,9 @@ pub trait ExpressionVisitor {
     fn visit_member_expression(&self, value: &MemberExpression);
     fn visit_object_expression(&self ,value: &ObjectExpression);
     fn visit_array_expression(&self, value: &ArrayExpression);
+    fn visit_spawn_expression(&self, value: &CallExpression);
+    fn visit_await_expression(&self, value: &AwaitExpression);
+    fn visit_range_expression(&self, value: &RangeExpression);
 
 }
 
@@ -51,12 +57,15 @@ pub trait ExpressionVisitorMut {
             Expression::Member(ref value) => self.visit_member_expression(value),
             Expression::Object(ref value) => self.visit_object_expression(value),
             Expression::Array(ref value) => self.visit_array_expression(value),
+            Expression::Spawn(ref value) => self.visit_spawn_expression(value),
+            Expression::Await(ref value) => self.visit_await_expression(value),
+            Expression::Range(ref value) => self.visit_range_expression(value),
         }
     }
 
     fn visit_nil_expression(&mut self);
     fn visit_boolean_expression(&mut self, value: &bool);
-    fn visit_number_expression(&mut self, value: &f32);
+    fn visit_number_expression(&mut self, value: &f64);
     fn visit_string_expression(&mut self, value: &String);
     fn visit_identifier_expression(&mut self, value: &String);
     fn visit_call_expression(&mut self, value: &CallExpression);
@@ -69,6 +78,9 @@ pub trait ExpressionVisitorMut {
     fn visit_member_expression(&mut self, value: &MemberExpression);
     fn visit_object_expression(&mut self ,value: &ObjectExpression);
     fn visit_array_expression(&mut self, value: &ArrayExpression);
+    fn visit_spawn_expression(&mut self, value: &CallExpression);
+    fn visit_await_expression(&mut self, value: &AwaitExpression);
+    fn visit_range_expression(&mut self, value: &RangeExpression);
 
 }
 
@@ -88,12 +100,15 @@ pub trait IntoExpressionVisitorMut {
             Expression::Member(value) => self.visit_member_expression(*value),
             Expression::Object(value) => self.visit_object_expression(*value),
             Expression::Array(value) => self.visit_array_expression(*value),
+            Expression::Spawn(value) => self.visit_spawn_expression(*value),
+            Expression::Await(value) => self.visit_await_expression(*value),
+            Expression::Range(value) => self.visit_range_expression(*value),
         }
     }
 
     fn visit_nil_expression(&mut self);
     fn visit_boolean_expression(&mut self, value: bool);
-    fn visit_number_expression(&mut self, value: f32);
+    fn visit_number_expression(&mut self, value: f64);
     fn visit_string_expression(&mut self, value: String);
     fn visit_identifier_expression(&mut self, value: String);
     fn visit_call_expression(&mut self, value: CallExpression);
@@ -104,5 +119,8 @@ pub trait IntoExpressionVisitorMut {
     fn visit_member_expression(&mut self, value: MemberExpression);
     fn visit_object_expression(&mut self ,value: ObjectExpression);
     fn visit_array_expression(&mut self, value: ArrayExpression);
+    fn visit_spawn_expression(&mut self, value: CallExpression);
+    fn visit_await_expression(&mut self, value: AwaitExpression);
+    fn visit_range_expression(&mut self, value: RangeExpression);
 
 }
\ No newline at end of file
@@ -1,3 +1,4 @@
+use std::fmt::{Display, Formatter};
 use crate::expression::{Expression, Identifier};
 use crate::statement_visitor::{IntoStatementVisitorMut, StatementVisitorMut};
 
@@ -15,10 +16,13 @@ pub enum Statement {
     Block(Box<BlockStatement>),
     Expression(Box<ExpressionStatement>),
     Return(Box<ReturnStatement>),
+    Yield(Box<YieldStatement>),
     If(Box<IfStatement>),
     Declaration(DeclarationStatement),
     MultiDeclaration(MultiDeclarationStatement),
-    Loop(Box<LoopStatement>)
+    Loop(Box<LoopStatement>),
+    Iterate(Box<IterateStatement>),
+    Try(Box<TryStatement>)
 }
 
 #[macro_export]
@@ -83,6 +87,18 @@ macro_rules! return_ {
     };
 }
 
+#[derive(Debug)]
+pub struct YieldStatement {
+    pub value: Option<Expression>
+}
+
+#[macro_export]
+macro_rules! yield_ {
+    { $($body:tt)* } => {
+        Statement::Yield(Box::from(YieldStatement { $($body)* }))
+    };
+}
+
 #[derive(Debug)]
 pub struct IfStatement {
     pub condition: Expression,
@@ -113,6 +129,7 @@ macro_rules! multi_declaration {
 pub struct DeclarationStatement {
     pub kind: DeclarationKind,
     pub variable: String,
+    pub typ: Option<TypeAnnotation>,
     pub value: Option<Expression>
 }
 
@@ -129,6 +146,35 @@ pub enum DeclarationKind {
     Const
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub enum TypeAnnotation {
+    Number,
+    String,
+    Bool,
+    Null,
+    Object,
+    Array,
+    Function,
+    Range,
+    Any
+}
+
+impl Display for TypeAnnotation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TypeAnnotation::Number => f.write_str("number"),
+            TypeAnnotation::String => f.write_str("string"),
+            TypeAnnotation::Bool => f.write_str("bool"),
+            TypeAnnotation::Null => f.write_str("null"),
+            TypeAnnotation::Object => f.write_str("object"),
+            TypeAnnotation::Array => f.write_str("array"),
+            TypeAnnotation::Function => f.write_str("function"),
+            TypeAnnotation::Range => f.write_str("range"),
+            TypeAnnotation::Any => f.write_str("any")
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct LoopStatement {
     pub body: Statement,
@@ -142,19 +188,54 @@ macro_rules! loop_ {
     };
  }
 
-#[derive(Debug, Clone)]
-pub struct Range {
-    pub start: Expression,
-    pub stop: Option<Expression>
+/// Loop over a collection, a generator or any object exposing a `next` function
+#[derive(Debug)]
+pub struct IterateStatement {
+    pub iterable: Expression,
+    pub alias: String,
+    pub reverse: bool,
+    pub body: Statement
+}
+
+#[macro_export]
+macro_rules! iterate_ {
+     { $($body:tt)* } => {
+        Statement::Iterate(Box::from(IterateStatement { $($body)* }))
+    };
+ }
+
+/// Runs `body`; a runtime error raised inside it unwinds to `handler`, with the
+/// error message bound to `alias`
+#[derive(Debug)]
+pub struct TryStatement {
+    pub body: Statement,
+    pub alias: String,
+    pub handler: Statement
 }
 
+#[macro_export]
+macro_rules! try_ {
+     { $($body:tt)* } => {
+        Statement::Try(Box::from(TryStatement { $($body)* }))
+    };
+ }
+
 #[derive(Debug)]
 pub struct FunctionDeclarationStatement {
     pub name: Identifier,
-    pub params: Vec<Identifier>,
+    pub params: Vec<Parameter>,
+    pub return_type: Option<TypeAnnotation>,
+    pub is_generator: bool,
     pub body: Statement
 }
 
+/// A formal parameter of a function declaration, with its optional type annotation
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    pub name: String,
+    pub typ: Option<TypeAnnotation>
+}
+
 #[macro_export]
 macro_rules! fun_declaration {
     { $($body:tt)* } => {
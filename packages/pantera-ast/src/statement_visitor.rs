@@ -1,4 +1,4 @@
-use crate::statement::{BlockStatement, DeclarationStatement, ExpressionStatement, FunctionDeclarationStatement, GlobalStatement, IfStatement, LoopStatement, MultiDeclarationStatement, PrintStatement, ReturnStatement, Statement};
+use crate::statement::{BlockStatement, DeclarationStatement, ExpressionStatement, FunctionDeclarationStatement, GlobalStatement, IfStatement, IterateStatement, LoopStatement, MultiDeclarationStatement, PrintStatement, ReturnStatement, Statement, TryStatement, YieldStatement};
 
 pub trait StatementVisitor {
     fn visit_statement(&self, stmt: &GlobalStatement) {
@@ -15,10 +15,13 @@ pub trait StatementVisitor {
             Statement::Block(ref value) => self.visit_block_statement(value),
             Statement::Expression(ref value) => self.visit_expression_statement(value),
             Statement::Return(ref value) => self.visit_return_statement(value),
+            Statement::Yield(ref value) => self.visit_yield_statement(value),
             Statement::If(ref value) => self.visit_if_statement(value),
             Statement::Declaration(ref value) => self.visit_declaration_statement(value),
             Statement::MultiDeclaration(ref value ) => self.visit_multi_declaration(value),
             Statement::Loop(ref value) => self.visit_loop_statement(value),
+            Statement::Iterate(ref value) => self.visit_iterate_statement(value),
+            Statement::Try(ref value) => self.visit_try_statement(value),
             Statement::FunctionBody(ref value) => self.visit_function_body(value)
         }
     }
@@ -30,8 +33,11 @@ pub trait StatementVisitor {
     fn visit_block_statement(&self, stmt: &BlockStatement);
     fn visit_expression_statement(&self, stmt: &ExpressionStatement);
     fn visit_return_statement(&self, stmt: &ReturnStatement);
+    fn visit_yield_statement(&self, stmt: &YieldStatement);
     fn visit_if_statement(&self, stmt: &IfStatement);
     fn visit_loop_statement(&self, stmt: &LoopStatement);
+    fn visit_iterate_statement(&self, stmt: &IterateStatement);
+    fn visit_try_statement(&self, stmt: &TryStatement);
     fn visit_declaration_statement(&self, stmt: &DeclarationStatement);
 }
 
@@ -50,10 +56,13 @@ pub trait IntoStatementVisitorMut {
             Statement::Block(value) => self.visit_block_statement(*value),
             Statement::Expression(value) => self.visit_expression_statement(*value),
             Statement::Return(value) => self.visit_return_statement(*value),
+            Statement::Yield(value) => self.visit_yield_statement(*value),
             Statement::If(value) => self.visit_if_statement(*value),
             Statement::Declaration(value) => self.visit_declaration_statement(value),
             Statement::MultiDeclaration(value ) => self.visit_multi_declaration(value),
             Statement::Loop(value) => self.visit_loop_statement(*value),
+            Statement::Iterate(value) => self.visit_iterate_statement(*value),
+            Statement::Try(value) => self.visit_try_statement(*value),
             Statement::FunctionBody(value) => self.visit_function_body(*value)
         }
     }
@@ -65,8 +74,11 @@ pub trait IntoStatementVisitorMut {
     fn visit_block_statement(&mut self, stmt: BlockStatement);
     fn visit_expression_statement(&mut self, stmt: ExpressionStatement);
     fn visit_return_statement(&mut self, stmt: ReturnStatement);
+    fn visit_yield_statement(&mut self, stmt: YieldStatement);
     fn visit_if_statement(&mut self, stmt: IfStatement);
     fn visit_loop_statement(&mut self, stmt: LoopStatement);
+    fn visit_iterate_statement(&mut self, stmt: IterateStatement);
+    fn visit_try_statement(&mut self, stmt: TryStatement);
     fn visit_declaration_statement(&mut self, stmt: DeclarationStatement);
     fn visit_multi_declaration(&mut self, stmt: MultiDeclarationStatement);
 }
@@ -86,10 +98,13 @@ pub trait StatementVisitorMut {
             Statement::Block(ref value) => self.visit_block_statement(value),
             Statement::Expression(ref value) => self.visit_expression_statement(value),
             Statement::Return(ref value) => self.visit_return_statement(value),
+            Statement::Yield(ref value) => self.visit_yield_statement(value),
             Statement::If(ref value) => self.visit_if_statement(value),
             Statement::Declaration(ref value) => self.visit_declaration_statement(value),
             Statement::MultiDeclaration(ref value ) => self.visit_multi_declaration(value),
             Statement::Loop(ref value) => self.visit_loop_statement(value),
+            Statement::Iterate(ref value) => self.visit_iterate_statement(value),
+            Statement::Try(ref value) => self.visit_try_statement(value),
             Statement::FunctionBody(ref value) => self.visit_function_body(value)
         }
     }
@@ -107,8 +122,14 @@ pub trait StatementVisitorMut {
     }
     fn visit_expression_statement(&mut self, stmt: &ExpressionStatement);
     fn visit_return_statement(&mut self, stmt: &ReturnStatement);
+    fn visit_yield_statement(&mut self, stmt: &YieldStatement);
     fn visit_if_statement(&mut self, stmt: &IfStatement);
     fn visit_loop_statement(&mut self, stmt: &LoopStatement);
+    fn visit_iterate_statement(&mut self, stmt: &IterateStatement);
+    fn visit_try_statement(&mut self, stmt: &TryStatement) {
+        self.visit_local_statement(&stmt.body);
+        self.visit_local_statement(&stmt.handler);
+    }
     fn visit_declaration_statement(&mut self, stmt: &DeclarationStatement);
     fn visit_multi_declaration(&mut self, stmt: &MultiDeclarationStatement) {
         for decl in &stmt.declarations {
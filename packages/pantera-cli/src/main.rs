@@ -1,38 +1,95 @@
 use std::fs::File;
-use std::io;
-use std::io::{Read, Write};
+use std::{io, process};
+use std::io::{BufReader, Read, Write};
 use clap::Parser;
 use clap_derive::Parser;
-use pantera_vm::{execute_with_options, Options};
+use pantera_vm::{execute_with_options, Capability, GcMode, Options};
+use pantera_vm::clock::{Clock, SystemClock, VirtualClock};
+use pantera_vm::input::{Input, Reader, StdInput};
+use pantera_vm::output::StdOutput;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Cli {
     /// Optional name to operate on
     file_name: Option<String>,
-    code: Option<String>,
+    /// Arguments passed to the script as the `args` global
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    args: Vec<String>,
     #[arg(short, long, default_value_t = 8)]
     max_heap_size: usize,
+    /// KB allocated between two collections of the young values; 0 collects the whole heap every time
+    #[arg(long, default_value_t = 2)]
+    nursery_size: usize,
+    /// Collect the heap incrementally, tracing at most STEP values per allocation instead of pausing for whole collections
+    #[arg(long, value_name = "STEP")]
+    incremental_gc: Option<usize>,
+    /// Print the heap's allocation and collection counters to stderr when the script ends
+    #[arg(long)]
+    gc_stats: bool,
+    /// Run `sleep` against a virtual clock that never actually waits
+    #[arg(long)]
+    virtual_clock: bool,
+    /// Reject scripts that use the `fs` std module
+    #[arg(long)]
+    no_fs: bool,
+    /// Read the script's `input()` lines from a file instead of stdin
+    #[arg(long)]
+    stdin_file: Option<String>,
 }
 
-pub fn execute_cli(string: &str) {
-    let max_heap_size = 10 * 1024;
-    execute_cli_with_options(string, Options { max_heap_size });
+/// The VM's defaults, printing straight to stdout instead of collecting the output
+fn cli_options() -> Options {
+    Options { output: Some(Box::new(StdOutput)), ..Options::default() }
 }
 
-pub fn execute_cli_with_options(string: &str, options: Options) {
+/// The options given on the command line, shared by script files and the REPL
+fn options_from_cli(cli: &Cli) -> Options {
+    let max_heap_size = cli.max_heap_size * 1024; // KB
+    let nursery_size = cli.nursery_size * 1024;
+    let gc_mode = match cli.incremental_gc {
+        Some(step) => GcMode::Incremental { step },
+        None => GcMode::StopTheWorld
+    };
+
+    let clock: Box<dyn Clock> = if cli.virtual_clock { Box::new(VirtualClock::new()) } else { Box::new(SystemClock::new()) };
+    let input: Box<dyn Input> = match cli.stdin_file.as_deref() {
+        Some(path) => match File::open(path) {
+            Ok(file) => Box::new(Reader(BufReader::new(file))),
+            Err(why) => panic!("Couldn't open {}: {}", path, why)
+        },
+        None => Box::new(StdInput::new())
+    };
+    let capabilities = cli.no_fs.then(|| Capability::ALL.into_iter().filter(|capability| *capability != Capability::Fs).collect());
+
+    Options { max_heap_size, nursery_size, gc_mode, clock, input, args: cli.args.clone(), capabilities, ..cli_options() }
+}
+
+pub fn execute_cli_with_options(string: &str, options: Options, print_gc_stats: bool) -> ExitCode {
     match execute_with_options(string, options) {
-        Ok(string) => {
-            if !string.is_empty() {
-                println!("{}", string.join("\n"));
+        Ok(result) => {
+            if print_gc_stats {
+                eprintln!("GC stats:\n{}", result.gc_stats);
+            }
+            match result.exit_code {
+                Some(code) => ExitCode::Exit(code),
+                None => ExitCode::Finished
             }
         },
         Err(err) => {
-            println!("{err}");
+            eprintln!("{err}");
+            ExitCode::Failed
         }
     }
 }
 
+/// How a script run ended
+pub enum ExitCode {
+    Finished,
+    Failed,
+    Exit(i32)
+}
+
 pub fn run_pantera() {
     let cli = Cli::parse();
 
@@ -49,9 +106,11 @@ pub fn run_pantera() {
         match file.read_to_string(&mut s) {
             Err(why) => panic!("couldn't read {}: {}", name, why),
             Ok(_) => {
-                let max_heap_size = cli.max_heap_size * 1024; // KB
-
-                execute_cli_with_options(&s, Options {max_heap_size});
+                match execute_cli_with_options(&s, options_from_cli(&cli), cli.gc_stats) {
+                    ExitCode::Finished => {},
+                    ExitCode::Failed => process::exit(1),
+                    ExitCode::Exit(code) => process::exit(code)
+                }
             }
         }
     } else {
@@ -77,7 +136,9 @@ pub fn run_pantera() {
                         continue;
                     }
 
-                    execute_cli(line);
+                    if let ExitCode::Exit(code) = execute_cli_with_options(line, options_from_cli(&cli), cli.gc_stats) {
+                        process::exit(code);
+                    }
                 }
                 Err(err) => {
                     println!("{err}");
@@ -90,4 +151,22 @@ pub fn run_pantera() {
 
 fn main() {
     run_pantera();
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exit_code(source: &str) -> ExitCode {
+        execute_cli_with_options(source, Options::default(), false)
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        assert!(matches!(exit_code("print 1;"), ExitCode::Finished));
+        assert!(matches!(exit_code("exit(4);"), ExitCode::Exit(4)));
+        assert!(matches!(exit_code("exit(0);"), ExitCode::Exit(0)));
+        assert!(matches!(exit_code("var a = 1; print a - true;"), ExitCode::Failed));
+        assert!(matches!(exit_code("exit();"), ExitCode::Failed));
+        assert!(matches!(exit_code("print \"unterminated;"), ExitCode::Failed));
+    }
+}
@@ -54,7 +54,18 @@ generate_bytecode! (
     OP_ALLOCATE,
     OP_ALLOCATE_ARRAY,
     OP_ACCESS,
-    OP_SET_PROPERTY
+    OP_SET_PROPERTY,
+    OP_GENERATOR,
+    OP_YIELD,
+    OP_ITERATOR,
+    OP_ITER_NEXT,
+    OP_ITER_CHECK,
+    OP_SPAWN,
+    OP_AWAIT,
+    OP_RANGE,
+    OP_IN,
+    OP_TRY,
+    OP_END_TRY
 );
 
 #[cfg(debug_assertions)]
@@ -106,5 +117,16 @@ debug_bytecode!(
     OP_ALLOCATE,
     OP_ALLOCATE_ARRAY,
     OP_ACCESS,
-    OP_SET_PROPERTY
+    OP_SET_PROPERTY,
+    OP_GENERATOR,
+    OP_YIELD,
+    OP_ITERATOR,
+    OP_ITER_NEXT,
+    OP_ITER_CHECK,
+    OP_SPAWN,
+    OP_AWAIT,
+    OP_RANGE,
+    OP_IN,
+    OP_TRY,
+    OP_END_TRY
 );
\ No newline at end of file
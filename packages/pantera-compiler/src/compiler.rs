@@ -1,16 +1,16 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
-use pantera_ast::expression::{ArrayExpression, AssignmentExpression, BinaryExpression, CallExpression, Expression, GroupExpression, MemberExpression, ObjectExpression, Operator, UnaryExpression};
+use pantera_ast::expression::{ArrayExpression, AssignmentExpression, AwaitExpression, BinaryExpression, CallExpression, Expression, GroupExpression, MemberExpression, ObjectExpression, Operator, RangeExpression, UnaryExpression};
 use pantera_ast::expression_visitor::{IntoExpressionVisitorMut};
-use pantera_ast::statement::{BlockStatement, DeclarationKind, DeclarationStatement, ExpressionStatement, FunctionDeclarationStatement, IfStatement, LoopStatement, MultiDeclarationStatement, PrintStatement, ReturnStatement};
+use pantera_ast::statement::{BlockStatement, DeclarationKind, DeclarationStatement, ExpressionStatement, FunctionDeclarationStatement, IfStatement, IterateStatement, LoopStatement, MultiDeclarationStatement, PrintStatement, ReturnStatement, TryStatement, YieldStatement};
 use pantera_ast::statement_visitor::{IntoStatementVisitorMut};
 use pantera_parser::parser::Parser;
-use crate::bytecode::{Bytecode, OP_ADD, OP_DIV, OP_PUSH, OP_MUL, OP_POW, OP_PRINT, OP_SUB, OP_EQ, OP_NE, OP_AND, OP_OR, OP_GE, OP_LE, OP_GR, OP_LS, OP_UNARY_SUB, OP_UNARY_NOT, OP_POP, OP_DECLARE, OP_GET, OP_SET, OP_JUMP_IF_FALSE, OP_JUMP, OP_DECLARE_GLOBAL, OP_GET_GLOBAL, OP_SET_GLOBAL, OP_END_FUNCTION, OP_CALL, OP_RETURN, OP_ALLOCATE, OP_ACCESS, OP_SET_PROPERTY, OP_ALLOCATE_ARRAY, OP_MOD};
+use crate::bytecode::{Bytecode, OP_ADD, OP_DIV, OP_PUSH, OP_MUL, OP_POW, OP_PRINT, OP_SUB, OP_EQ, OP_NE, OP_AND, OP_OR, OP_GE, OP_LE, OP_GR, OP_LS, OP_UNARY_SUB, OP_UNARY_NOT, OP_POP, OP_DECLARE, OP_GET, OP_SET, OP_JUMP_IF_FALSE, OP_JUMP, OP_DECLARE_GLOBAL, OP_GET_GLOBAL, OP_SET_GLOBAL, OP_END_FUNCTION, OP_CALL, OP_RETURN, OP_ALLOCATE, OP_ACCESS, OP_SET_PROPERTY, OP_ALLOCATE_ARRAY, OP_MOD, OP_GENERATOR, OP_YIELD, OP_ITERATOR, OP_ITER_NEXT, OP_ITER_CHECK, OP_SPAWN, OP_AWAIT, OP_RANGE, OP_IN, OP_TRY, OP_END_TRY};
 use crate::env::Env;
 use pantera_heap::heap::HeapManager;
 use pantera_heap::types::Type;
-use pantera_std::init_compiler_globals;
+use pantera_std::{init_compiler_globals, is_std_module, restricted_globals, Capability};
 use crate::semantic::run_all_semantic_checks;
 
 #[derive(Debug, Clone)]
@@ -26,9 +26,14 @@ pub struct Compiler {
     pub code: Vec<Bytecode>,
     pub env: Box<Env>,
     pub break_stmt: Vec<Vec<usize>>,
+    pub loop_locals: Vec<usize>,
+    pub loop_tries: Vec<usize>,
+    pub tries: usize,
     pub context: Context,
     pub globals: HashMap<String, u16>,
     pub active_func_args: HashMap<String, Vec<String>>,
+    /// Std entries the script may not reference, see `allow_capabilities`
+    pub restricted: HashMap<String, Capability>,
 }
 
 impl Compiler {
@@ -36,21 +41,42 @@ impl Compiler {
         let std_lib = init_compiler_globals();
         Compiler {
             break_stmt: vec![],
+            loop_locals: vec![],
+            loop_tries: vec![],
+            tries: 0,
             code: vec![],
             env: Box::new(Env::new()),
             context: Context::Global,
             globals: std_lib,
             active_func_args: HashMap::new(),
+            restricted: HashMap::new(),
             heap_manager
         }
     }
-    pub fn compile(mut self, mut parser: Parser) -> Result<Vec<Bytecode>, String> {
+
+    /// Limits the script to the std entries that need no capability or one of `allowed`.
+    /// Everything is allowed unless this is called.
+    pub fn allow_capabilities(&mut self, allowed: &[Capability]) {
+        self.restricted = restricted_globals(allowed);
+    }
+
+    /// Makes a global provided by the host known to scripts, alongside the std lib, and
+    /// returns the key the VM expects its value under. Declaring a std name again keeps its
+    /// key and lifts any capability restriction on it.
+    pub fn declare_global(&mut self, name: &str) -> u16 {
+        self.restricted.remove(name);
+        let next = self.globals.len() as u16;
+        *self.globals.entry(name.to_string()).or_insert(next)
+    }
+
+    /// Afterwards `globals` holds the keys of every global the program declared
+    pub fn compile(&mut self, mut parser: Parser) -> Result<Vec<Bytecode>, String> {
         match parser.parse_program() {
            Ok(program) => {
-               run_all_semantic_checks(&program)?;
-               program.into_iter().for_each(|p| p.visit_g(&mut self));
+               run_all_semantic_checks(&program, &self.globals, &self.restricted)?;
+               program.into_iter().for_each(|p| p.visit_g(self));
 
-               Ok(self.code)
+               Ok(std::mem::take(&mut self.code))
            }
             Err(e) => {
                 Err(e.get_message())
@@ -70,10 +96,10 @@ impl Compiler {
         self.code.push(byte_code2);
     }
 
-    pub(crate)fn emit_number(&mut self, number: f32) {
+    pub(crate)fn emit_number(&mut self, number: f64) {
         self.emit_byte(OP_PUSH);
         self.emit_byte(Type::Number as Bytecode);
-        Self::convert_number_to_bytes(number).into_iter().for_each(|bc| self.emit_byte(bc));
+        number.to_le_bytes().into_iter().for_each(|bc| self.emit_byte(bc));
     }
 
     pub(crate)fn emit_boolean(&mut self, val: bool) {
@@ -151,15 +177,15 @@ impl IntoExpressionVisitorMut for Compiler {
         self.emit_boolean(value);
     }
 
-    fn visit_number_expression(&mut self, value: f32) {
+    fn visit_number_expression(&mut self, value: f64) {
         self.emit_number(value);
     }
 
     fn visit_string_expression(&mut self, value: String) {
         self.emit_bytes(OP_PUSH, Type::String as Bytecode);
 
-        let ptr = self.heap_manager.borrow_mut().allocate_compiled_string(value).unwrap();
-        (ptr as u64).to_le_bytes().into_iter().for_each(|bt| self.emit_byte(bt));
+        let ptr = self.heap_manager.borrow_mut().allocate_compiled_string(value);
+        ptr.to_bytes().into_iter().for_each(|bt| self.emit_byte(bt));
     }
 
     fn visit_identifier_expression(&mut self, value: String) {
@@ -176,9 +202,10 @@ impl IntoExpressionVisitorMut for Compiler {
     }
 
     fn visit_call_expression(&mut self, value: CallExpression) {
+        let arg_count = value.args.len() as Bytecode;
         value.args.into_iter().for_each(|arg| self.visit_expression(arg));
         self.visit_expression(value.callee);
-        self.emit_byte(OP_CALL);
+        self.emit_bytes(OP_CALL, arg_count);
     }
 
     fn visit_assignment_expression(&mut self, value: AssignmentExpression) {
@@ -224,7 +251,8 @@ impl IntoExpressionVisitorMut for Compiler {
             Operator::Le => self.emit_byte(OP_LE),
             Operator::Greater => self.emit_byte(OP_GR),
             Operator::Less => self.emit_byte(OP_LS),
-            Operator::Mod => self.emit_byte(OP_MOD)
+            Operator::Mod => self.emit_byte(OP_MOD),
+            Operator::In => self.emit_byte(OP_IN)
         }
     }
 
@@ -242,13 +270,22 @@ impl IntoExpressionVisitorMut for Compiler {
     }
 
     fn visit_member_expression(&mut self, value: MemberExpression) {
+        if let (Expression::Identifier(module), Expression::String(member)) = (&value.callee, &value.property) {
+            if self.env.get_variable(module).is_none() && is_std_module(module) {
+                self.emit_byte(OP_PUSH);
+                self.emit_byte(OP_GET_GLOBAL);
+                self.emit_hash(format!("{module}.{member}"));
+                return;
+            }
+        }
+
         self.visit_expression(value.property);
         self.visit_expression(value.callee);
         self.emit_byte(OP_ACCESS);
     }
 
     fn visit_object_expression(&mut self, value: ObjectExpression) {
-        let obj_len = value.properties.len() as f32;
+        let obj_len = value.properties.len() as f64;
         value.properties.into_iter().for_each(|prop| self.visit_expression(prop));
         value.values.into_iter().for_each(|val| self.visit_expression(val));
         self.emit_number(obj_len);
@@ -256,16 +293,37 @@ impl IntoExpressionVisitorMut for Compiler {
     }
 
     fn visit_array_expression(&mut self, value: ArrayExpression) {
-        let arr_len =value.values.len() as f32;
+        let arr_len =value.values.len() as f64;
         value.values.into_iter().for_each(|val| self.visit_expression(val));
         self.emit_number(arr_len);
         self.emit_byte(OP_ALLOCATE_ARRAY);
     }
+
+    fn visit_spawn_expression(&mut self, value: CallExpression) {
+        value.args.into_iter().for_each(|arg| self.visit_expression(arg));
+        self.visit_expression(value.callee);
+        self.emit_byte(OP_SPAWN);
+    }
+
+    fn visit_await_expression(&mut self, value: AwaitExpression) {
+        self.visit_expression(value.expr);
+        self.emit_byte(OP_AWAIT);
+    }
+
+    fn visit_range_expression(&mut self, value: RangeExpression) {
+        self.visit_expression(value.start);
+        self.visit_expression(value.stop);
+        match value.step {
+            Some(step) => self.visit_expression(step),
+            None => self.emit_number(1f64)
+        }
+        self.emit_bytes(OP_RANGE, value.inclusive as Bytecode);
+    }
 }
 
 impl IntoStatementVisitorMut for Compiler {
     fn visit_function_body(&mut self, stmt: BlockStatement) {
-        self.env = Box::new(Env::new_frame(self.env.clone()));
+        *self.env = Env::new_frame(self.env.clone());
         self.env.set_variable("__offset__".to_string());
         let Context::Function(func_name) = &self.context else {panic!("Something went wrong when compiling")};
         self.active_func_args.get(func_name).unwrap().iter().for_each(|param| self.env.set_variable(param.clone()));
@@ -291,6 +349,9 @@ impl IntoStatementVisitorMut for Compiler {
 
         let loc = self.emit_jump();
         self.back_patch(addr);
+        if func_dec.is_generator {
+            self.emit_byte(OP_GENERATOR);
+        }
         self.visit_local_statement(func_dec.body);
         self.emit_byte(OP_END_FUNCTION);
 
@@ -300,6 +361,13 @@ impl IntoStatementVisitorMut for Compiler {
     }
 
     fn visit_break_statement(&mut self) {
+        let loop_locals = *self.loop_locals.last().unwrap();
+        for _ in loop_locals..self.env.compute_var_key() {
+            self.emit_byte(OP_POP);
+        }
+        for _ in *self.loop_tries.last().unwrap()..self.tries {
+            self.emit_byte(OP_END_TRY);
+        }
         self.emit_byte(OP_JUMP);
         let cont_ind = self.break_stmt.len() - 1;
         if let Some(cont) = self.break_stmt.get_mut(cont_ind) {
@@ -317,7 +385,7 @@ impl IntoStatementVisitorMut for Compiler {
         let old_context = self.context.clone();
         self.context = Context::Block;
 
-        self.env = Box::new(Env::new_local(self.env.clone()));
+        *self.env = Env::new_local(self.env.clone());
 
         stmt.statements.into_iter().for_each(|stmt| self.visit_local_statement(stmt));
 
@@ -341,6 +409,15 @@ impl IntoStatementVisitorMut for Compiler {
         self.emit_byte(OP_END_FUNCTION);
     }
 
+    fn visit_yield_statement(&mut self, stmt: YieldStatement) {
+        if let Some(value) = stmt.value {
+            self.visit_expression(value);
+        } else {
+            self.emit_null();
+        }
+        self.emit_byte(OP_YIELD);
+    }
+
     fn visit_if_statement(&mut self, stmt: IfStatement) {
         self.visit_expression(stmt.condition);
         self.emit_byte(OP_JUMP_IF_FALSE);
@@ -364,17 +441,90 @@ impl IntoStatementVisitorMut for Compiler {
 
     fn visit_loop_statement(&mut self, stmt: LoopStatement) {
         self.break_stmt.push(vec![]);
+        self.loop_locals.push(self.env.compute_var_key());
+        self.loop_tries.push(self.tries);
 
         let loc = self.code.len();
         self.visit_local_statement(stmt.body);
         self.emit_byte(OP_JUMP);
-        let beg = Self::convert_number_to_bytes(loc as f32);
-        for index in 0..4 {
-            self.emit_byte(beg[index]);
-        }
+        Self::convert_number_to_bytes(loc as f32).into_iter().for_each(|bc| self.emit_byte(bc));
+
+        self.loop_locals.pop();
+        self.loop_tries.pop();
+        let cont = self.break_stmt.pop().unwrap();
+        cont.into_iter().for_each(|break_location| self.back_patch(break_location));
+    }
 
+    fn visit_iterate_statement(&mut self, stmt: IterateStatement) {
+        let old_context = self.context.clone();
+        self.context = Context::Block;
+        *self.env = Env::new_local(self.env.clone());
+
+        // the iterable and its cursor live in two hidden locals for the whole loop
+        let iterator = self.env.compute_var_key() as Bytecode;
+        self.visit_expression(stmt.iterable);
+        self.emit_bytes(OP_ITERATOR, Self::convert_bool_to_byte(stmt.reverse));
+        self.env.set_variable(format!("__{}", stmt.alias));
+        self.env.set_variable(format!("__{}_index", stmt.alias));
+
+        self.break_stmt.push(vec![]);
+        self.loop_locals.push(self.env.compute_var_key());
+        self.loop_tries.push(self.tries);
+
+        let loc = self.code.len();
+        self.emit_bytes(OP_ITER_NEXT, iterator);
+        self.emit_byte(Self::convert_bool_to_byte(stmt.reverse));
+        let exhausted = self.code.len();
+        self.emit_temp_byte();
+        self.emit_bytes(OP_ITER_CHECK, iterator);
+        let finished = self.code.len();
+        self.emit_temp_byte();
+
+        *self.env = Env::new_local(self.env.clone());
+        self.env.set_variable(stmt.alias);
+        self.visit_local_statement(stmt.body);
+        self.emit_byte(OP_POP);
+        self.env = self.env.enclosing.clone().unwrap();
+
+        self.emit_byte(OP_JUMP);
+        Self::convert_number_to_bytes(loc as f32).into_iter().for_each(|bc| self.emit_byte(bc));
+
+        self.back_patch(exhausted);
+        self.back_patch(finished);
+        self.loop_locals.pop();
+        self.loop_tries.pop();
         let cont = self.break_stmt.pop().unwrap();
         cont.into_iter().for_each(|break_location| self.back_patch(break_location));
+
+        self.emit_byte(OP_POP);
+        self.emit_byte(OP_POP);
+        self.env = self.env.enclosing.clone().unwrap();
+        self.context = old_context;
+    }
+
+    fn visit_try_statement(&mut self, stmt: TryStatement) {
+        self.emit_byte(OP_TRY);
+        let handler = self.code.len();
+        self.emit_temp_byte();
+
+        self.tries += 1;
+        self.visit_local_statement(stmt.body);
+        self.tries -= 1;
+        self.emit_byte(OP_END_TRY);
+        let end = self.emit_jump();
+        self.back_patch(handler);
+
+        // the VM unwinds to the locals alive at OP_TRY and pushes the error message on top
+        let old_context = self.context.clone();
+        self.context = Context::Block;
+        *self.env = Env::new_local(self.env.clone());
+        self.env.set_variable(stmt.alias);
+        self.visit_local_statement(stmt.handler);
+        self.emit_byte(OP_POP);
+        self.env = self.env.enclosing.clone().unwrap();
+        self.context = old_context;
+
+        self.back_patch(end);
     }
 
     fn visit_declaration_statement(&mut self, stmt: DeclarationStatement) {
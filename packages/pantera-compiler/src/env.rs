@@ -60,7 +60,7 @@ impl Env {
         var
     }
 
-    fn compute_var_key(&self) -> usize {
+    pub fn compute_var_key(&self) -> usize {
         if self.frame_beginning {
             return self.variables.len();
         }
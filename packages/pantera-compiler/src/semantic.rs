@@ -1,18 +1,21 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::thread;
 use pantera_ast::statement::GlobalStatement;
 use pantera_ast::statement_visitor::StatementVisitorMut;
-use pantera_std::init_compiler_globals;
+use pantera_std::Capability;
 use crate::errors::CompilerError;
 use crate::semantic::break_statement_check::BreakStatementCheck;
 use crate::semantic::check::Check;
 use crate::semantic::declaration_check::DeclarationCheck;
 use crate::semantic::return_statement_check::ReturnStatementCheck;
+use crate::semantic::type_check::TypeCheck;
 
 mod declaration_check;
 mod check;
 mod break_statement_check;
 mod return_statement_check;
+mod type_check;
 
 fn run_semantic_check<T: StatementVisitorMut + Check>(stmts: &Vec<GlobalStatement>, mut check: T) -> Vec<CompilerError> {
     stmts.iter().for_each(|stmt|{
@@ -22,18 +25,23 @@ fn run_semantic_check<T: StatementVisitorMut + Check>(stmts: &Vec<GlobalStatemen
     check.get_errors()
 }
 
-pub fn run_all_semantic_checks(stmts: &Vec<GlobalStatement>) -> Result<(), String> {
+/// `globals` are the names scripts cannot redeclare: the std lib and the host's globals.
+/// Referencing one of the `restricted` std entries is an error.
+pub fn run_all_semantic_checks(stmts: &Vec<GlobalStatement>, globals: &HashMap<String, u16>, restricted: &HashMap<String, Capability>) -> Result<(), String> {
     let mut results = Vec::new();
 
     thread::scope(|s| {
-        let std_lid = Arc::new(init_compiler_globals());
-        let h1 = s.spawn(move || run_semantic_check(stmts, DeclarationCheck::new(Arc::clone(&std_lid))));
+        let std_lid = Arc::new(globals.clone());
+        let restricted = Arc::new(restricted.clone());
+        let h1 = s.spawn(move || run_semantic_check(stmts, DeclarationCheck::new(Arc::clone(&std_lid), restricted)));
         let h2 = s.spawn(move || run_semantic_check(stmts, BreakStatementCheck::new()));
         let h3 = s.spawn(move || run_semantic_check(stmts, ReturnStatementCheck::new()));
+        let h4 = s.spawn(move || run_semantic_check(stmts, TypeCheck::new(stmts)));
 
         results.push(h1.join().unwrap());
         results.push(h2.join().unwrap());
         results.push(h3.join().unwrap());
+        results.push(h4.join().unwrap());
     });
 
     let errors =
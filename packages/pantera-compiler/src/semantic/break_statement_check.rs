@@ -1,4 +1,4 @@
-use pantera_ast::statement::{DeclarationStatement, ExpressionStatement, FunctionDeclarationStatement, IfStatement, LoopStatement, PrintStatement, ReturnStatement};
+use pantera_ast::statement::{DeclarationStatement, ExpressionStatement, FunctionDeclarationStatement, IfStatement, IterateStatement, LoopStatement, PrintStatement, ReturnStatement, YieldStatement};
 use pantera_ast::statement_visitor::StatementVisitorMut;
 use crate::errors::CompilerError;
 use crate::semantic::check::Check;
@@ -41,6 +41,8 @@ impl StatementVisitorMut for BreakStatementCheck {
 
     fn visit_return_statement(&mut self, _stmt: &ReturnStatement) {}
 
+    fn visit_yield_statement(&mut self, _stmt: &YieldStatement) {}
+
     fn visit_if_statement(&mut self, stmt: &IfStatement) {
         self.visit_local_statement(&stmt.body);
     }
@@ -52,5 +54,12 @@ impl StatementVisitorMut for BreakStatementCheck {
         self.is_loop = prev_is_loop;
     }
 
+    fn visit_iterate_statement(&mut self, stmt: &IterateStatement) {
+        let prev_is_loop = self.is_loop;
+        self.is_loop = true;
+        self.visit_local_statement(&stmt.body);
+        self.is_loop = prev_is_loop;
+    }
+
     fn visit_declaration_statement(&mut self, _stmt: &DeclarationStatement) {}
 }
\ No newline at end of file
@@ -1,22 +1,32 @@
 use std::collections::HashMap;
 use std::sync::Arc;
-use pantera_ast::expression::{ArrayExpression, AssignmentExpression, BinaryExpression, CallExpression, Expression, MemberExpression, ObjectExpression, UnaryExpression};
+use pantera_ast::expression::{ArrayExpression, AssignmentExpression, AwaitExpression, BinaryExpression, CallExpression, Expression, MemberExpression, ObjectExpression, RangeExpression, UnaryExpression};
 use pantera_ast::expression_visitor::ExpressionVisitorMut;
-use pantera_ast::statement::{DeclarationStatement, ExpressionStatement, FunctionDeclarationStatement, IfStatement, LoopStatement, PrintStatement, ReturnStatement};
+use pantera_ast::statement::{DeclarationStatement, ExpressionStatement, FunctionDeclarationStatement, IfStatement, IterateStatement, LoopStatement, PrintStatement, ReturnStatement, TryStatement, YieldStatement};
 use pantera_ast::statement_visitor::StatementVisitorMut;
+use pantera_std::{is_std_module, Capability};
 use crate::errors::CompilerError;
 use crate::semantic::check::Check;
 
 pub struct DeclarationCheck {
     pub errors: Vec<CompilerError>,
     std_lib: Arc<HashMap<String, u16>>,
+    /// Std entries needing a capability the script wasn't given
+    restricted: Arc<HashMap<String, Capability>>
 }
 
 impl DeclarationCheck {
-    pub fn new(std_lib: Arc<HashMap<String, u16>>) -> Self {
+    pub fn new(std_lib: Arc<HashMap<String, u16>>, restricted: Arc<HashMap<String, Capability>>) -> Self {
         Self {
             errors: vec![],
-            std_lib
+            std_lib,
+            restricted
+        }
+    }
+
+    fn check_capability(&mut self, name: &str) {
+        if let Some(capability) = self.restricted.get(name) {
+            self.errors.push(CompilerError{ message: format!("{name} needs the {capability} capability, which this script doesn't have") });
         }
     }
 }
@@ -30,6 +40,9 @@ impl Check for DeclarationCheck {
 impl StatementVisitorMut for DeclarationCheck {
 
     fn visit_function_declaration(&mut self, func_dec: &FunctionDeclarationStatement) {
+        if func_dec.params.iter().any(|param| is_std_module(&param.name)) {
+            self.errors.push(CompilerError{ message: "Cannot declare a variable with a name from std lib".to_string() });
+        }
         self.visit_local_statement(&func_dec.body);
     }
 
@@ -49,6 +62,12 @@ impl StatementVisitorMut for DeclarationCheck {
         }
     }
 
+    fn visit_yield_statement(&mut self, stmt: &YieldStatement) {
+        if let Some(val) = &stmt.value {
+            self.visit_expression(val);
+        }
+    }
+
     fn visit_if_statement(&mut self, stmt: &IfStatement) {
         self.visit_expression(&stmt.condition);
         self.visit_local_statement(&stmt.body);
@@ -58,10 +77,29 @@ impl StatementVisitorMut for DeclarationCheck {
         self.visit_local_statement(&stmt.body);
     }
 
+    fn visit_iterate_statement(&mut self, stmt: &IterateStatement) {
+        if self.std_lib.contains_key(&stmt.alias) || is_std_module(&stmt.alias) {
+            self.errors.push(CompilerError{ message: "Cannot declare a variable with a name from std lib".to_string() });
+        }
+        self.visit_expression(&stmt.iterable);
+        self.visit_local_statement(&stmt.body);
+    }
+
+    fn visit_try_statement(&mut self, stmt: &TryStatement) {
+        if self.std_lib.contains_key(&stmt.alias) || is_std_module(&stmt.alias) {
+            self.errors.push(CompilerError{ message: "Cannot declare a variable with a name from std lib".to_string() });
+        }
+        self.visit_local_statement(&stmt.body);
+        self.visit_local_statement(&stmt.handler);
+    }
+
     fn visit_declaration_statement(&mut self, stmt: &DeclarationStatement) {
-        if self.std_lib.contains_key(&stmt.variable) {
+        if self.std_lib.contains_key(&stmt.variable) || is_std_module(&stmt.variable) {
             self.errors.push(CompilerError{ message: "Cannot declare a variable with a name from std lib".to_string() });
         }
+        if let Some(value) = &stmt.value {
+            self.visit_expression(value);
+        }
     }
 }
 
@@ -70,11 +108,16 @@ impl ExpressionVisitorMut for DeclarationCheck {
 
     fn visit_boolean_expression(&mut self, _value: &bool) {}
 
-    fn visit_number_expression(&mut self, _value: &f32) {}
+    fn visit_number_expression(&mut self, _value: &f64) {}
 
     fn visit_string_expression(&mut self, _value: &String) {}
 
-    fn visit_identifier_expression(&mut self, _value: &String) {}
+    fn visit_identifier_expression(&mut self, value: &String) {
+        if is_std_module(value) {
+            self.errors.push(CompilerError{ message: format!("Module {value} can only be used to access its members") });
+        }
+        self.check_capability(value);
+    }
 
     fn visit_call_expression(&mut self, value: &CallExpression) {
         self.visit_expression(&value.callee);
@@ -84,16 +127,12 @@ impl ExpressionVisitorMut for DeclarationCheck {
     }
 
     fn visit_assignment_expression(&mut self, value: &AssignmentExpression) {
-        match &value.assignee {
-            Expression::Identifier(ident) => {
-                if self.std_lib.contains_key(ident) {
-                    self.errors.push(CompilerError{ message: "Cannot reassign a variable with name from std lib".to_string() });
-                }
-            }
-            _ => {
-
+        if let Expression::Identifier(ident) = &value.assignee {
+            if self.std_lib.contains_key(ident) || is_std_module(ident) {
+                self.errors.push(CompilerError{ message: "Cannot reassign a variable with name from std lib".to_string() });
             }
         }
+        self.visit_expression(&value.value);
     }
 
     fn visit_binary_expression(&mut self, value: &BinaryExpression) {
@@ -106,6 +145,17 @@ impl ExpressionVisitorMut for DeclarationCheck {
     }
 
     fn visit_member_expression(&mut self, value: &MemberExpression) {
+        if let (Expression::Identifier(module), Expression::String(member)) = (&value.callee, &value.property) {
+            if is_std_module(module) {
+                let name = format!("{module}.{member}");
+                if !self.std_lib.contains_key(&name) {
+                    self.errors.push(CompilerError{ message: format!("Module {module} has no member {member}") });
+                }
+                self.check_capability(&name);
+                return;
+            }
+        }
+
         self.visit_expression(&value.callee);
         self.visit_expression(&value.property);
     }
@@ -124,4 +174,20 @@ impl ExpressionVisitorMut for DeclarationCheck {
             self.visit_expression(val);
         })
     }
+
+    fn visit_spawn_expression(&mut self, value: &CallExpression) {
+        self.visit_call_expression(value);
+    }
+
+    fn visit_await_expression(&mut self, value: &AwaitExpression) {
+        self.visit_expression(&value.expr);
+    }
+
+    fn visit_range_expression(&mut self, value: &RangeExpression) {
+        self.visit_expression(&value.start);
+        self.visit_expression(&value.stop);
+        if let Some(step) = &value.step {
+            self.visit_expression(step);
+        }
+    }
 }
\ No newline at end of file
@@ -1,11 +1,12 @@
-use pantera_ast::statement::{DeclarationStatement, ExpressionStatement, FunctionDeclarationStatement, IfStatement, LoopStatement, PrintStatement, ReturnStatement};
+use pantera_ast::statement::{DeclarationStatement, ExpressionStatement, FunctionDeclarationStatement, IfStatement, IterateStatement, LoopStatement, PrintStatement, ReturnStatement, TryStatement, YieldStatement};
 use pantera_ast::statement_visitor::StatementVisitorMut;
 use crate::errors::CompilerError;
 use crate::semantic::check::Check;
 
 pub struct ReturnStatementCheck {
     pub errors: Vec<CompilerError>,
-    pub is_function: bool
+    pub is_function: bool,
+    pub is_try: bool
 }
 
 impl ReturnStatementCheck {
@@ -13,6 +14,7 @@ impl ReturnStatementCheck {
         Self {
             errors: vec![],
             is_function: false,
+            is_try: false
         }
     }
 }
@@ -43,6 +45,15 @@ impl StatementVisitorMut for ReturnStatementCheck {
         }
     }
 
+    fn visit_yield_statement(&mut self, _stmt: &YieldStatement) {
+        if !self.is_function{
+            self.errors.push(CompilerError {message: "Cannot yield outside function".to_string()})
+        }
+        if self.is_try {
+            self.errors.push(CompilerError {message: "Cannot yield inside a try block".to_string()})
+        }
+    }
+
     fn visit_if_statement(&mut self, stmt: &IfStatement) {
         self.visit_local_statement(&stmt.body);
     }
@@ -51,5 +62,17 @@ impl StatementVisitorMut for ReturnStatementCheck {
         self.visit_local_statement(&stmt.body);
     }
 
+    fn visit_iterate_statement(&mut self, stmt: &IterateStatement) {
+        self.visit_local_statement(&stmt.body);
+    }
+
+    fn visit_try_statement(&mut self, stmt: &TryStatement) {
+        let prev_is_try = self.is_try;
+        self.is_try = true;
+        self.visit_local_statement(&stmt.body);
+        self.is_try = prev_is_try;
+        self.visit_local_statement(&stmt.handler);
+    }
+
     fn visit_declaration_statement(&mut self, _stmt: &DeclarationStatement) {}
 }
\ No newline at end of file
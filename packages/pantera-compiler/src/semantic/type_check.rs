@@ -0,0 +1,420 @@
+use std::collections::HashMap;
+use pantera_ast::expression::{ArrayExpression, AssignmentExpression, AwaitExpression, BinaryExpression, CallExpression, Expression, MemberExpression, ObjectExpression, Operator, RangeExpression, UnaryExpression};
+use pantera_ast::expression_visitor::ExpressionVisitorMut;
+use pantera_ast::statement::{BlockStatement, DeclarationStatement, ExpressionStatement, FunctionDeclarationStatement, GlobalStatement, IfStatement, IterateStatement, LoopStatement, PrintStatement, ReturnStatement, TryStatement, TypeAnnotation, YieldStatement};
+use pantera_ast::statement_visitor::StatementVisitorMut;
+use crate::errors::CompilerError;
+use crate::semantic::check::Check;
+
+struct FunctionSignature {
+    params: Vec<Option<TypeAnnotation>>,
+    return_type: Option<TypeAnnotation>
+}
+
+/// Checks optional type annotations. Values whose type cannot be inferred locally
+/// (un-annotated variables, member accesses, builtin calls) are treated as `any`,
+/// so un-annotated code stays dynamically typed.
+pub struct TypeCheck {
+    pub errors: Vec<CompilerError>,
+    functions: HashMap<String, FunctionSignature>,
+    scopes: Vec<HashMap<String, TypeAnnotation>>,
+    return_type: Option<Option<TypeAnnotation>>
+}
+
+impl TypeCheck {
+    pub fn new(stmts: &[GlobalStatement]) -> Self {
+        let mut functions = HashMap::new();
+        stmts.iter().for_each(|stmt| {
+            if let GlobalStatement::FunctionDeclaration(func_dec) = stmt {
+                functions.insert(func_dec.name.name.clone(), FunctionSignature {
+                    params: func_dec.params.iter().map(|param| param.typ.clone()).collect(),
+                    return_type: if func_dec.is_generator { None } else { func_dec.return_type.clone() }
+                });
+            }
+        });
+
+        Self {
+            errors: vec![],
+            functions,
+            scopes: vec![HashMap::new()],
+            return_type: None
+        }
+    }
+
+    fn declare(&mut self, name: &str, typ: TypeAnnotation) {
+        self.scopes.last_mut().unwrap().insert(name.to_string(), typ);
+    }
+
+    fn lookup(&self, name: &str) -> TypeAnnotation {
+        for scope in self.scopes.iter().rev() {
+            if let Some(typ) = scope.get(name) {
+                return typ.clone();
+            }
+        }
+
+        TypeAnnotation::Any
+    }
+
+    fn is_assignable(expected: &TypeAnnotation, actual: &TypeAnnotation) -> bool {
+        matches!(expected, TypeAnnotation::Any)
+            || matches!(actual, TypeAnnotation::Any | TypeAnnotation::Null)
+            || expected == actual
+    }
+
+    fn expect(&mut self, expected: &TypeAnnotation, actual: &TypeAnnotation, what: String) {
+        if !Self::is_assignable(expected, actual) {
+            self.errors.push(CompilerError { message: format!("Type mismatch: {what} expects {expected} but got {actual}") });
+        }
+    }
+
+    fn expect_operand(&mut self, operator: &str, expected: TypeAnnotation, actual: &TypeAnnotation) {
+        if !matches!(actual, TypeAnnotation::Any) && *actual != expected {
+            self.errors.push(CompilerError { message: format!("Type mismatch: operator {operator} expects {expected} operands but got {actual}") });
+        }
+    }
+
+    fn infer(&mut self, expr: &Expression) -> TypeAnnotation {
+        match expr {
+            Expression::Nil => TypeAnnotation::Null,
+            Expression::Bool(_) => TypeAnnotation::Bool,
+            Expression::Number(_) => TypeAnnotation::Number,
+            Expression::String(_) => TypeAnnotation::String,
+            Expression::Identifier(name) => {
+                let typ = self.lookup(name);
+                if matches!(typ, TypeAnnotation::Any) && self.functions.contains_key(name) {
+                    return TypeAnnotation::Function;
+                }
+                typ
+            },
+            Expression::Object(value) => {
+                self.visit_object_expression(value);
+                TypeAnnotation::Object
+            },
+            Expression::Array(value) => {
+                self.visit_array_expression(value);
+                TypeAnnotation::Array
+            },
+            Expression::Group(value) => self.infer(&value.expr),
+            Expression::Member(value) => {
+                self.visit_member_expression(value);
+                TypeAnnotation::Any
+            },
+            Expression::Assigment(value) => self.infer_assignment(value),
+            Expression::Call(value) => {
+                self.visit_call_expression(value);
+                if let Expression::Identifier(name) = &value.callee {
+                    if let Some(signature) = self.functions.get(name) {
+                        return signature.return_type.clone().unwrap_or(TypeAnnotation::Any);
+                    }
+                }
+                TypeAnnotation::Any
+            },
+            Expression::Unary(value) => {
+                let typ = self.infer(&value.expr);
+                match value.operator {
+                    Operator::Minus => {
+                        self.expect_operand("-", TypeAnnotation::Number, &typ);
+                        TypeAnnotation::Number
+                    },
+                    _ => {
+                        self.expect_operand("not", TypeAnnotation::Bool, &typ);
+                        TypeAnnotation::Bool
+                    }
+                }
+            },
+            Expression::Binary(value) => {
+                let left = self.infer(&value.left);
+                let right = self.infer(&value.right);
+                self.infer_binary(&value.operator, left, right)
+            },
+            Expression::Spawn(value) => {
+                self.visit_call_expression(value);
+                TypeAnnotation::Any
+            },
+            Expression::Await(value) => {
+                self.infer(&value.expr);
+                TypeAnnotation::Any
+            },
+            Expression::Range(value) => {
+                self.visit_range_expression(value);
+                TypeAnnotation::Range
+            }
+        }
+    }
+
+    fn infer_assignment(&mut self, value: &AssignmentExpression) -> TypeAnnotation {
+        let actual = self.infer(&value.value);
+        match &value.assignee {
+            Expression::Identifier(name) => {
+                let expected = self.lookup(name);
+                self.expect(&expected, &actual, format!("variable {name}"));
+            },
+            assignee => {
+                self.infer(assignee);
+            }
+        }
+
+        actual
+    }
+
+    fn infer_binary(&mut self, operator: &Operator, left: TypeAnnotation, right: TypeAnnotation) -> TypeAnnotation {
+        match operator {
+            Operator::Plus => {
+                if matches!(left, TypeAnnotation::Any) || matches!(right, TypeAnnotation::Any) {
+                    return if matches!(left, TypeAnnotation::Any) { right } else { left };
+                }
+                if left != right || !matches!(left, TypeAnnotation::Number | TypeAnnotation::String | TypeAnnotation::Object) {
+                    self.errors.push(CompilerError { message: format!("Type mismatch: cannot add {right} to {left}") });
+                    return TypeAnnotation::Any;
+                }
+                left
+            },
+            Operator::Minus | Operator::Mul | Operator::Div | Operator::Mod | Operator::Pow => {
+                self.expect_operand("arithmetic", TypeAnnotation::Number, &left);
+                self.expect_operand("arithmetic", TypeAnnotation::Number, &right);
+                TypeAnnotation::Number
+            },
+            Operator::Greater | Operator::Less | Operator::Ge | Operator::Le => {
+                self.expect_operand("comparison", TypeAnnotation::Number, &left);
+                self.expect_operand("comparison", TypeAnnotation::Number, &right);
+                TypeAnnotation::Bool
+            },
+            Operator::And | Operator::Or => {
+                self.expect_operand("and/or", TypeAnnotation::Bool, &left);
+                self.expect_operand("and/or", TypeAnnotation::Bool, &right);
+                TypeAnnotation::Bool
+            },
+            Operator::In => {
+                if !matches!(right, TypeAnnotation::Any | TypeAnnotation::Range | TypeAnnotation::Array | TypeAnnotation::Object) {
+                    self.errors.push(CompilerError { message: format!("Type mismatch: cannot test membership in {right}") });
+                }
+                TypeAnnotation::Bool
+            },
+            Operator::Eq | Operator::NE => TypeAnnotation::Bool
+        }
+    }
+}
+
+impl Check for TypeCheck {
+    fn get_errors(self) -> Vec<CompilerError> {
+        self.errors
+    }
+}
+
+impl StatementVisitorMut for TypeCheck {
+    fn visit_function_declaration(&mut self, func_dec: &FunctionDeclarationStatement) {
+        self.scopes.push(HashMap::new());
+        func_dec.params.iter().for_each(|param| {
+            self.declare(&param.name, param.typ.clone().unwrap_or(TypeAnnotation::Any));
+        });
+        let return_type = if func_dec.is_generator { None } else { func_dec.return_type.clone() };
+        let old_return_type = self.return_type.replace(return_type);
+
+        self.visit_local_statement(&func_dec.body);
+
+        self.return_type = old_return_type;
+        self.scopes.pop();
+    }
+
+    fn visit_break_statement(&mut self) {}
+
+    fn visit_print_statement(&mut self, stmt: &PrintStatement) {
+        self.infer(&stmt.expr);
+    }
+
+    fn visit_block_statement(&mut self, stmt: &BlockStatement) {
+        self.scopes.push(HashMap::new());
+        stmt.statements.iter().for_each(|stm| self.visit_local_statement(stm));
+        self.scopes.pop();
+    }
+
+    fn visit_expression_statement(&mut self, stmt: &ExpressionStatement) {
+        self.infer(&stmt.expr);
+    }
+
+    fn visit_return_statement(&mut self, stmt: &ReturnStatement) {
+        let actual = match &stmt.value {
+            Some(value) => self.infer(value),
+            None => TypeAnnotation::Null
+        };
+        if let Some(Some(expected)) = self.return_type.clone() {
+            self.expect(&expected, &actual, "return value".to_string());
+        }
+    }
+
+    fn visit_yield_statement(&mut self, stmt: &YieldStatement) {
+        if let Some(value) = &stmt.value {
+            self.infer(value);
+        }
+    }
+
+    fn visit_if_statement(&mut self, stmt: &IfStatement) {
+        self.infer(&stmt.condition);
+        self.visit_local_statement(&stmt.body);
+        if let Some(alternative) = &stmt.alternative {
+            self.visit_local_statement(alternative);
+        }
+    }
+
+    fn visit_loop_statement(&mut self, stmt: &LoopStatement) {
+        self.visit_local_statement(&stmt.body);
+    }
+
+    fn visit_iterate_statement(&mut self, stmt: &IterateStatement) {
+        self.infer(&stmt.iterable);
+        self.scopes.push(HashMap::new());
+        self.declare(&stmt.alias, TypeAnnotation::Any);
+        self.visit_local_statement(&stmt.body);
+        self.scopes.pop();
+    }
+
+    fn visit_try_statement(&mut self, stmt: &TryStatement) {
+        self.visit_local_statement(&stmt.body);
+        self.scopes.push(HashMap::new());
+        self.declare(&stmt.alias, TypeAnnotation::String);
+        self.visit_local_statement(&stmt.handler);
+        self.scopes.pop();
+    }
+
+    fn visit_declaration_statement(&mut self, stmt: &DeclarationStatement) {
+        let actual = match &stmt.value {
+            Some(value) => self.infer(value),
+            None => TypeAnnotation::Null
+        };
+        if let Some(expected) = &stmt.typ {
+            self.expect(expected, &actual, format!("variable {}", stmt.variable));
+        }
+        self.declare(&stmt.variable, stmt.typ.clone().unwrap_or(TypeAnnotation::Any));
+    }
+}
+
+impl ExpressionVisitorMut for TypeCheck {
+    fn visit_nil_expression(&mut self) {}
+
+    fn visit_boolean_expression(&mut self, _value: &bool) {}
+
+    fn visit_number_expression(&mut self, _value: &f64) {}
+
+    fn visit_string_expression(&mut self, _value: &String) {}
+
+    fn visit_identifier_expression(&mut self, _value: &String) {}
+
+    fn visit_call_expression(&mut self, value: &CallExpression) {
+        let args = value.args.iter().map(|arg| self.infer(arg)).collect::<Vec<TypeAnnotation>>();
+        let Expression::Identifier(name) = &value.callee else {
+            self.infer(&value.callee);
+            return;
+        };
+        let Some(signature) = self.functions.get(name) else { return; };
+        let expected = signature.params.clone();
+
+        if expected.len() != args.len() {
+            self.errors.push(CompilerError { message: format!("Function {name} expects {} arguments but got {}", expected.len(), args.len()) });
+            return;
+        }
+        expected.into_iter().zip(args).enumerate().for_each(|(index, (expected, actual))| {
+            if let Some(expected) = expected {
+                self.expect(&expected, &actual, format!("argument {} of {name}", index + 1));
+            }
+        });
+    }
+
+    fn visit_assignment_expression(&mut self, value: &AssignmentExpression) {
+        self.infer_assignment(value);
+    }
+
+    fn visit_binary_expression(&mut self, value: &BinaryExpression) {
+        self.infer(&value.left);
+        self.infer(&value.right);
+    }
+
+    fn visit_unary_expression(&mut self, value: &UnaryExpression) {
+        self.infer(&value.expr);
+    }
+
+    fn visit_member_expression(&mut self, value: &MemberExpression) {
+        let callee = self.infer(&value.callee);
+        if !matches!(callee, TypeAnnotation::Any | TypeAnnotation::Object | TypeAnnotation::Array | TypeAnnotation::Range | TypeAnnotation::String) {
+            self.errors.push(CompilerError { message: format!("Type mismatch: cannot access a property of {callee}") });
+        }
+        self.infer(&value.property);
+    }
+
+    fn visit_object_expression(&mut self, value: &ObjectExpression) {
+        value.values.iter().for_each(|val| {
+            self.infer(val);
+        });
+    }
+
+    fn visit_array_expression(&mut self, value: &ArrayExpression) {
+        value.values.iter().for_each(|val| {
+            self.infer(val);
+        });
+    }
+
+    fn visit_spawn_expression(&mut self, value: &CallExpression) {
+        self.visit_call_expression(value);
+    }
+
+    fn visit_await_expression(&mut self, value: &AwaitExpression) {
+        self.infer(&value.expr);
+    }
+
+    fn visit_range_expression(&mut self, value: &RangeExpression) {
+        let start = self.infer(&value.start);
+        self.expect_operand("..", TypeAnnotation::Number, &start);
+        let stop = self.infer(&value.stop);
+        self.expect_operand("..", TypeAnnotation::Number, &stop);
+        if let Some(step) = &value.step {
+            let step = self.infer(step);
+            self.expect_operand("by", TypeAnnotation::Number, &step);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pantera_parser::lexer::Lexer;
+    use pantera_parser::parser::Parser;
+    use super::*;
+
+    fn type_errors(source: &str) -> Vec<String> {
+        let stmts = Parser::new(Lexer::new(source).scan_tokens().unwrap()).parse_program().unwrap();
+        let mut check = TypeCheck::new(&stmts);
+        stmts.iter().for_each(|stmt| stmt.visit(&mut check));
+        check.get_errors().into_iter().map(|err| err.message).collect()
+    }
+
+    #[test]
+    fn test_annotated_mismatches() {
+        assert_eq!(type_errors("var count: number = \"zero\";"), vec!["Type mismatch: variable count expects number but got string"]);
+        assert_eq!(type_errors("var done: bool = false; done = 1;"), vec!["Type mismatch: variable done expects bool but got number"]);
+        assert_eq!(type_errors("fun half(n: number) : number { return n / 2; } half(\"four\");").len(), 1);
+        assert_eq!(type_errors("fun name(n) : string { return 1; }").len(), 1);
+        assert_eq!(type_errors("fun name(n) : string { return \"pantera\"; } var size: number = name(1);").len(), 1);
+    }
+
+    #[test]
+    fn test_matching_annotations() {
+        assert!(type_errors("var count: number = 0; count = count + 1; var label: string = \"a\" + \"b\";").is_empty());
+        assert!(type_errors("var items: array = [1, 2]; var maybe: object = null;").is_empty());
+        assert!(type_errors("fun half(n: number) : number { return n / 2; } var size: number = half(4);").is_empty());
+    }
+
+    #[test]
+    fn test_unannotated_code_stays_dynamic() {
+        assert!(type_errors("var a = 1; a = \"one\"; a = [a]; print a + a;").is_empty());
+        assert!(type_errors("fun id(x) { return x; } var count: number = id(\"text\");").is_empty());
+        assert!(type_errors("var count: any = 1; count = \"one\"; var size: number = len(\"abc\");").is_empty());
+        assert!(type_errors("var obj = {}; var size: number = obj's size; print not obj; print -obj;").is_empty());
+    }
+
+    #[test]
+    fn test_mismatched_literals() {
+        assert_eq!(type_errors("print \"a\" + 1;"), vec!["Type mismatch: cannot add number to string"]);
+        assert_eq!(type_errors("print not 1;"), vec!["Type mismatch: operator not expects bool operands but got number"]);
+        assert_eq!(type_errors("print -\"a\";"), vec!["Type mismatch: operator - expects number operands but got string"]);
+        assert_eq!(type_errors("print 1 and true; print 2 < \"b\";").len(), 2);
+        assert_eq!(type_errors("print true + false;").len(), 1);
+    }
+}
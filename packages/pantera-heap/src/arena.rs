@@ -0,0 +1,224 @@
+use std::fmt::{Debug, Formatter};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+/// The size of a handle in bytecode
+pub const HANDLE_BYTES: usize = 12;
+
+/// A reference to a `T` on a heap: the heap it was allocated on, its slot in that heap's arena
+/// for `T` and the generation the slot had at the time. Freeing a value bumps the generation
+/// of its slot, so handles that outlive their value stop resolving instead of reading
+/// whatever was allocated there next.
+pub struct Handle<T> {
+    heap: u32,
+    index: u32,
+    generation: u32,
+    // handles are only meaningful on the thread of their heap
+    marker: PhantomData<*const T>
+}
+
+impl<T> Handle<T> {
+    pub(crate) fn new(heap: u32, index: u32, generation: u32) -> Self {
+        Self { heap, index, generation, marker: PhantomData }
+    }
+
+    pub(crate) fn heap(&self) -> u32 {
+        self.heap
+    }
+
+    pub fn to_bytes(self) -> [u8; HANDLE_BYTES] {
+        let mut bytes = [0u8; HANDLE_BYTES];
+        bytes[0..4].copy_from_slice(&self.heap.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.index.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.generation.to_le_bytes());
+        bytes
+    }
+
+    /// Reads a handle written by `to_bytes`. Bytes that don't describe a live value give a
+    /// handle that fails to resolve, never one to another value.
+    pub fn from_bytes(bytes: [u8; HANDLE_BYTES]) -> Self {
+        let read = |start: usize| u32::from_le_bytes([bytes[start], bytes[start + 1], bytes[start + 2], bytes[start + 3]]);
+        Self::new(read(0), read(4), read(8))
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.heap, self.index, self.generation) == (other.heap, other.index, other.generation)
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.heap, self.index, self.generation).hash(state);
+    }
+}
+
+impl<T> Debug for Handle<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Handle({}:{}#{})", self.heap, self.index, self.generation)
+    }
+}
+
+#[derive(Debug)]
+struct Slot<T> {
+    generation: u32,
+    value: Option<T>,
+    /// Reached by the collection in progress
+    marked: bool,
+    /// Never collected, like the strings baked into bytecode
+    pinned: bool,
+    /// Survived a collection, so minor collections take it as live
+    old: bool,
+    /// An old value written to since the last minor collection, which may point to young ones
+    remembered: bool
+}
+
+/// Values of one type, stored in slots that are reused once freed
+#[derive(Debug)]
+pub(crate) struct Arena<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>,
+    /// The slots allocated since the last collection, i.e. the nursery
+    young: Vec<u32>,
+    remembered: Vec<u32>
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Self { slots: vec![], free: vec![], young: vec![], remembered: vec![] }
+    }
+}
+
+impl<T> Arena<T> {
+    pub fn insert(&mut self, heap: u32, value: T) -> Handle<T> {
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.slots.push(Slot { generation: 0, value: None, marked: false, pinned: false, old: false, remembered: false });
+                (self.slots.len() - 1) as u32
+            }
+        };
+        let slot = &mut self.slots[index as usize];
+        slot.value = Some(value);
+        slot.old = false;
+        self.young.push(index);
+
+        Handle::new(heap, index, slot.generation)
+    }
+
+    fn slot(&self, handle: Handle<T>) -> Option<&Slot<T>> {
+        self.slots.get(handle.index as usize).filter(|slot| slot.generation == handle.generation && slot.value.is_some())
+    }
+
+    fn slot_mut(&mut self, handle: Handle<T>) -> Option<&mut Slot<T>> {
+        self.slots.get_mut(handle.index as usize).filter(|slot| slot.generation == handle.generation && slot.value.is_some())
+    }
+
+    pub fn get(&self, handle: Handle<T>) -> Option<&T> {
+        self.slot(handle).and_then(|slot| slot.value.as_ref())
+    }
+
+    pub fn get_mut(&mut self, handle: Handle<T>) -> Option<&mut T> {
+        self.slot_mut(handle).and_then(|slot| slot.value.as_mut())
+    }
+
+    pub fn pin(&mut self, handle: Handle<T>) {
+        if let Some(slot) = self.slot_mut(handle) {
+            slot.pinned = true;
+        }
+    }
+
+    /// Marks a live value as reached. Returns false when it already was, or isn't live, or is
+    /// old while only the young values are being collected.
+    pub fn mark(&mut self, handle: Handle<T>, young_only: bool) -> bool {
+        match self.slot_mut(handle) {
+            Some(slot) if !slot.marked && (!young_only || !slot.old) => {
+                slot.marked = true;
+                true
+            },
+            _ => false
+        }
+    }
+
+    pub fn is_marked(&self, handle: Handle<T>) -> bool {
+        self.slot(handle).is_some_and(|slot| slot.marked)
+    }
+
+    /// The write barrier: records that an old value was written to
+    pub fn remember(&mut self, handle: Handle<T>) {
+        if let Some(slot) = self.slot_mut(handle) {
+            if slot.old && !slot.remembered {
+                slot.remembered = true;
+                self.remembered.push(handle.index);
+            }
+        }
+    }
+
+    /// The old values written to since the last collection, forgetting them
+    pub fn take_remembered(&mut self, heap: u32) -> Vec<Handle<T>> {
+        let mut handles = vec![];
+        for index in self.remembered.drain(..) {
+            let slot = &mut self.slots[index as usize];
+            slot.remembered = false;
+            if slot.value.is_some() {
+                handles.push(Handle::new(heap, index, slot.generation));
+            }
+        }
+
+        handles
+    }
+
+    /// Frees the young values that weren't marked and promotes the others, returning the
+    /// freed values
+    pub fn sweep_young(&mut self) -> Vec<T> {
+        let mut freed = vec![];
+        for index in std::mem::take(&mut self.young) {
+            let slot = &mut self.slots[index as usize];
+            if slot.marked || slot.pinned {
+                slot.marked = false;
+                slot.old = true;
+                continue;
+            }
+            freed.push(slot.value.take().unwrap());
+            slot.generation = slot.generation.wrapping_add(1);
+            self.free.push(index);
+        }
+
+        freed
+    }
+
+    /// Frees every value that isn't marked or pinned and clears the marks, returning the
+    /// freed values. The survivors all become old.
+    pub fn sweep(&mut self) -> Vec<T> {
+        let mut freed = vec![];
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            slot.remembered = false;
+            if slot.value.is_none() {
+                continue;
+            }
+            if slot.marked || slot.pinned {
+                slot.marked = false;
+                slot.old = true;
+                continue;
+            }
+            freed.push(slot.value.take().unwrap());
+            slot.generation = slot.generation.wrapping_add(1);
+            self.free.push(index as u32);
+        }
+        self.young.clear();
+        self.remembered.clear();
+
+        freed
+    }
+}
@@ -0,0 +1,17 @@
+use crate::value::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeneratorState {
+    Suspended,
+    Running,
+    Done
+}
+
+/// A suspended generator call: the instruction to resume from and a copy of the
+/// frame's stack segment (arguments and locals) taken at the last `yield`.
+#[derive(Debug)]
+pub struct Generator {
+    pub ip: usize,
+    pub locals: Vec<Value>,
+    pub state: GeneratorState
+}
@@ -1,20 +1,231 @@
-use std::alloc::{alloc, dealloc, Layout, LayoutError};
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
-use crate::array::{Array, ARRAY_BYTES_SIZE};
-use crate::bytes::{read_bytes_until_null, read_string, write_byte, write_string};
-use crate::hash_table::{HashTable, BYTES_SIZE};
-use crate::types::Type;
-use crate::value::{FunctionValue, Value};
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+use crate::arena::{Arena, Handle};
+use crate::array::Array;
+use crate::generator::{Generator, GeneratorState};
+use crate::range::RangeValue;
+use crate::hash_table::HashTable;
+use crate::stats::{Allocations, HeapStats};
+use crate::value::Value;
+
+/// The arenas of one heap
+#[derive(Debug, Default)]
+struct Arenas {
+    strings: Arena<String>,
+    objects: Arena<HashTable>,
+    arrays: Arena<Array>,
+    generators: Arena<Generator>,
+    ranges: Arena<RangeValue>,
+    /// Whether an incremental collection is marking the heap
+    marking: bool,
+    /// The marked values whose children are still to be traced
+    grey: Vec<Value>
+}
+
+/// Why the heap couldn't serve a request
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HeapError {
+    /// The live values take up `max_heap_size` or more
+    OutOfMemory,
+    /// The handle's value was collected, or it belongs to a heap that was dropped or lives on
+    /// another thread. Names the type of value.
+    UseAfterFree(&'static str)
+}
+
+impl Display for HeapError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::OutOfMemory => f.write_str("Out of memory: the max heap size has been reached"),
+            Self::UseAfterFree(name) => write!(f, "Use after free: the {name} was already collected")
+        }
+    }
+}
+
+impl std::error::Error for HeapError {}
+
+/// Builtins report errors as strings, so heap errors pass through them with `?`
+impl From<HeapError> for String {
+    fn from(err: HeapError) -> Self {
+        err.to_string()
+    }
+}
+
+/// A type of value that lives in its own arena
+trait Stored: Sized {
+    /// How use-after-free errors refer to the value
+    const NAME: &'static str;
+
+    fn arena(arenas: &mut Arenas) -> &mut Arena<Self>;
+
+    fn value(handle: Handle<Self>) -> Value;
+
+    fn count(allocations: &mut Allocations) -> &mut usize;
+
+    fn size(&self) -> usize;
+}
+
+impl Stored for String {
+    const NAME: &'static str = "string";
+
+    fn arena(arenas: &mut Arenas) -> &mut Arena<Self> {
+        &mut arenas.strings
+    }
+
+    fn count(allocations: &mut Allocations) -> &mut usize {
+        &mut allocations.strings
+    }
+
+    fn value(handle: Handle<Self>) -> Value {
+        Value::String(handle)
+    }
 
-pub type Ptr = *mut u8;
+    fn size(&self) -> usize {
+        self.len() + 1 + 1
+    }
+}
+
+impl Stored for HashTable {
+    const NAME: &'static str = "object";
+
+    fn arena(arenas: &mut Arenas) -> &mut Arena<Self> {
+        &mut arenas.objects
+    }
+
+    fn count(allocations: &mut Allocations) -> &mut usize {
+        &mut allocations.objects
+    }
+
+    fn value(handle: Handle<Self>) -> Value {
+        Value::Object(handle)
+    }
+
+    fn size(&self) -> usize {
+        HashTable::size(self)
+    }
+}
+
+impl Stored for Array {
+    const NAME: &'static str = "array";
+
+    fn arena(arenas: &mut Arenas) -> &mut Arena<Self> {
+        &mut arenas.arrays
+    }
+
+    fn count(allocations: &mut Allocations) -> &mut usize {
+        &mut allocations.arrays
+    }
+
+    fn value(handle: Handle<Self>) -> Value {
+        Value::Array(handle)
+    }
+
+    fn size(&self) -> usize {
+        Array::size(self)
+    }
+}
+
+impl Stored for Generator {
+    const NAME: &'static str = "generator";
+
+    fn arena(arenas: &mut Arenas) -> &mut Arena<Self> {
+        &mut arenas.generators
+    }
+
+    fn count(allocations: &mut Allocations) -> &mut usize {
+        &mut allocations.generators
+    }
+
+    fn value(handle: Handle<Self>) -> Value {
+        Value::Generator(handle)
+    }
+
+    fn size(&self) -> usize {
+        size_of::<Generator>()
+    }
+}
+
+impl Stored for RangeValue {
+    const NAME: &'static str = "range";
+
+    fn arena(arenas: &mut Arenas) -> &mut Arena<Self> {
+        &mut arenas.ranges
+    }
+
+    fn count(allocations: &mut Allocations) -> &mut usize {
+        &mut allocations.ranges
+    }
+
+    fn value(handle: Handle<Self>) -> Value {
+        Value::Range(handle)
+    }
+
+    fn size(&self) -> usize {
+        size_of::<RangeValue>()
+    }
+}
+
+thread_local! {
+    /// The arenas of every heap on this thread, by heap id. Values only carry handles, so
+    /// readers without a `HeapManager` at hand (like `Display`) find their heap here.
+    static HEAPS: RefCell<HashMap<u32, Arenas>> = RefCell::new(HashMap::new());
+    static NEXT_HEAP_ID: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Runs `f` on the value behind `handle`, or fails with a use-after-free error when the value
+/// was collected, or the handle belongs to a heap that was dropped or lives on another thread
+fn with_value<T: Stored, R>(handle: Handle<T>, f: impl FnOnce(&mut T) -> R) -> Result<R, HeapError> {
+    HEAPS.with_borrow_mut(|heaps| {
+        let value = heaps.get_mut(&handle.heap()).and_then(|arenas| T::arena(arenas).get_mut(handle));
+        value.map(f).ok_or(HeapError::UseAfterFree(T::NAME))
+    })
+}
+
+fn is_live<T: Stored>(handle: Handle<T>) -> bool {
+    HEAPS.with_borrow_mut(|heaps| heaps.get_mut(&handle.heap()).is_some_and(|arenas| T::arena(arenas).get(handle).is_some()))
+}
+
+/// Like `with_value`, for `f`s that write to the value. Old values written to are remembered,
+/// since they may now point to young ones, and values an incremental collection already marked
+/// turn grey again, since they may now point to unmarked ones.
+fn write_value<T: Stored, R>(handle: Handle<T>, f: impl FnOnce(&mut T) -> R) -> Result<R, HeapError> {
+    let result = with_value(handle, f)?;
+    HEAPS.with_borrow_mut(|heaps| {
+        let arenas = heaps.get_mut(&handle.heap()).unwrap();
+        T::arena(arenas).remember(handle);
+        if arenas.marking && T::arena(arenas).is_marked(handle) {
+            arenas.grey.push(T::value(handle));
+        }
+    });
+
+    Ok(result)
+}
+
+fn mark<T: Stored>(handle: Handle<T>, collection: Collection) -> bool {
+    HEAPS.with_borrow_mut(|heaps| heaps.get_mut(&handle.heap()).is_some_and(|arenas| T::arena(arenas).mark(handle, collection == Collection::Minor)))
+}
+
+/// Which values a collection frees
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Collection {
+    /// Only the young values, allocated since the last collection. Old values count as live,
+    /// so the ones written to since (see `take_remembered`) are roots too.
+    Minor,
+    /// Every value
+    Major
+}
 
 #[derive(Debug)]
 pub struct HeapManager {
-    pub interned_strings: HashMap<Ptr, bool>,
-    pub objects: HashMap<Ptr, bool>,
-    heap_layout: HashMap<Ptr, Layout>,
+    id: u32,
+    /// Every live string by its text, since strings are interned
+    interned_strings: HashMap<String, Handle<String>>,
     pub allocated_memory: usize,
+    /// The bytes allocated since the last collection
+    pub nursery_memory: usize,
     pub max_heap_size: usize,
+    stats: HeapStats
 }
 
 impl Default for HeapManager {
@@ -23,302 +234,570 @@ impl Default for HeapManager {
     }
 }
 
+impl Drop for HeapManager {
+    fn drop(&mut self) {
+        // the registry may already be gone when the thread is exiting
+        let _ = HEAPS.try_with(|heaps| heaps.borrow_mut().remove(&self.id));
+    }
+}
+
 impl HeapManager {
 
     pub fn new(max_heap_size: usize) ->Self {
+        let id = NEXT_HEAP_ID.replace(NEXT_HEAP_ID.get() + 1);
+        HEAPS.with_borrow_mut(|heaps| heaps.insert(id, Arenas::default()));
+
         Self {
+            id,
             interned_strings: HashMap::new(),
-            objects: HashMap::new(),
-            heap_layout: HashMap::new(),
             allocated_memory: 0,
-            max_heap_size
+            nursery_memory: 0,
+            max_heap_size,
+            stats: HeapStats::default()
         }
     }
 
-    pub fn check_oom(&self) {
+    /// Fails when the heap holds `max_heap_size` bytes or more. Allocations always succeed, so
+    /// the VM checks this once the collector had its chance to free some.
+    pub fn check_oom(&self) -> Result<(), HeapError> {
         if self.allocated_memory >= self.max_heap_size {
-            panic!("OOM: Max heap size has been reached");
+            return Err(HeapError::OutOfMemory);
         }
+        Ok(())
     }
 
-    pub fn free(&mut self, ptr: Ptr) {
-        let layout = self.heap_layout.remove(&ptr).unwrap();
-        unsafe {
-            dealloc(ptr, layout);
+    fn allocate<T: Stored>(&mut self, value: T) -> Handle<T> {
+        *T::count(&mut self.stats.allocations) += 1;
+        self.grow(value.size());
+        let handle = HEAPS.with_borrow_mut(|heaps| {
+            let arenas = heaps.get_mut(&self.id).unwrap();
+            let handle = T::arena(arenas).insert(self.id, value);
+            // values allocated while marking survive it, and so do the ones they were given
+            if arenas.marking {
+                T::arena(arenas).mark(handle, false);
+                arenas.grey.push(T::value(handle));
+            }
+            handle
+        });
+
+        handle
+    }
+
+    /// Writes to the value behind `handle` with `f`, accounting for the bytes it gains
+    fn update<T: Stored, R>(&mut self, handle: Handle<T>, f: impl FnOnce(&mut T) -> R) -> Result<R, HeapError> {
+        let (result, before, after) = write_value(handle, |value| {
+            let before = value.size();
+            let result = f(value);
+            (result, before, value.size())
+        })?;
+        if after > before {
+            self.grow(after - before);
+        } else {
+            self.allocated_memory -= before - after;
+            self.nursery_memory = self.nursery_memory.saturating_sub(before - after);
         }
+
+        Ok(result)
     }
 
-    // > Object
-    pub fn allocate_object(&mut self, val: HashMap<Ptr, Value>) -> Result<Ptr, LayoutError> {
-        unsafe {
-            let mut map = HashTable::new();
+    fn grow(&mut self, bytes: usize) {
+        self.allocated_memory += bytes;
+        self.nursery_memory += bytes;
+        self.stats.allocated_bytes += bytes;
+        self.stats.peak_bytes = self.stats.peak_bytes.max(self.allocated_memory);
+    }
 
-            for (key, val) in val.into_iter() {
-                map.set(key, val);
-            }
+    pub fn stats(&self) -> HeapStats {
+        HeapStats { live_bytes: self.allocated_memory, heap_size: self.max_heap_size, ..self.stats.clone() }
+    }
 
-            self.objects.insert(map.entries, false);
-            self.heap_layout.insert(map.entries, map.layout.unwrap());
-            self.allocated_memory = self.allocated_memory + BYTES_SIZE;
+    /// Counts a pause of the script for collecting this heap
+    pub fn record_pause(&mut self, pause: Duration) {
+        self.stats.pauses += 1;
+        self.stats.total_pause += pause;
+        self.stats.max_pause = self.stats.max_pause.max(pause);
+    }
 
-            self.check_oom();
+    /// Whether the heap value `value` refers to is still allocated. Hosts that keep values
+    /// around between calls can check them before reading.
+    pub fn is_live(value: &Value) -> bool {
+        match value {
+            Value::String(ptr) => is_live(*ptr),
+            Value::Object(ptr) => is_live(*ptr),
+            Value::Array(ptr) => is_live(*ptr),
+            Value::Generator(ptr) => is_live(*ptr),
+            Value::Range(ptr) => is_live(*ptr),
+            _ => true
+        }
+    }
 
-            Ok(map.entries)
+    // > Collection
+
+    /// Marks the heap value `value` refers to as reachable by `collection`. Returns false when
+    /// it already was, or when a minor collection reaches an old value, so that tracing can stop
+    /// there.
+    pub fn mark(&mut self, value: &Value, collection: Collection) -> bool {
+        match value {
+            Value::String(ptr) => mark(*ptr, collection),
+            Value::Object(ptr) => mark(*ptr, collection),
+            Value::Array(ptr) => mark(*ptr, collection),
+            Value::Generator(ptr) => mark(*ptr, collection),
+            Value::Range(ptr) => mark(*ptr, collection),
+            _ => false
         }
     }
 
-    pub fn free_object(&mut self, ptr: Ptr) {
-        self.objects.remove(&ptr);
-        self.free(ptr);
+    /// The values `value` points to, which the collector traces after marking it. Only live
+    /// values get marked, so a freed one has none.
+    pub fn get_children(value: &Value) -> Vec<Value> {
+        match value {
+            Value::Object(ptr) => with_value(*ptr, |map| {
+                map.get_all().iter().flat_map(|entry| [Value::String(entry.key), entry.value.clone()]).collect()
+            }).unwrap_or_default(),
+            Value::Array(ptr) => HeapManager::get_array(*ptr).unwrap_or_default(),
+            Value::Generator(ptr) => with_value(*ptr, |generator| generator.locals.clone()).unwrap_or_default(),
+            _ => vec![]
+        }
     }
 
-    pub fn get_object(obj_ptr: Ptr) -> HashMap<Ptr, Box<Value>> {
-        unsafe {
-            let map = HashTable::from(obj_ptr);
-            let elems = map.get_all();
+    /// Starts an incremental collection: from now on, new values are marked and writes to
+    /// marked values turn them grey
+    pub fn start_marking(&mut self) {
+        HEAPS.with_borrow_mut(|heaps| heaps.get_mut(&self.id).unwrap().marking = true);
+    }
 
-            let mut rez = HashMap::new();
-            elems.into_iter().for_each(|entry| {
-                let entr = map.get(&entry.key).unwrap();
-                rez.insert(entry.key, Box::from(entr));
-            });
+    pub fn is_marking(&self) -> bool {
+        HEAPS.with_borrow(|heaps| heaps[&self.id].marking)
+    }
 
-            rez
+    /// Marks `value` for the incremental collection, queueing it to have its children traced
+    pub fn shade(&mut self, value: &Value) {
+        if self.mark(value, Collection::Major) {
+            HEAPS.with_borrow_mut(|heaps| heaps.get_mut(&self.id).unwrap().grey.push(value.clone()));
         }
     }
 
-    pub const fn get_object_entry_size() -> usize {
-        size_of::<Ptr>()
-    }
-
-    pub fn get_value_from_bytes(value_bytes: Vec<u8>, typ: Type) -> Value {
-        match typ {
-            Type::String => {
-                let mut bytes :[u8;Self::get_object_entry_size()] = [0u8;Self::get_object_entry_size()];
-                for i in 0..Self::get_object_entry_size() {
-                    bytes[i] = value_bytes[i];
-                }
-                let ptr = u64::from_le_bytes(bytes) as Ptr;
-                Value::String(ptr)
-            },
-            Type::Object => {
-                let mut bytes :[u8;Self::get_object_entry_size()] = [0u8;Self::get_object_entry_size()];
-                for i in 0..Self::get_object_entry_size() {
-                    bytes[i] = value_bytes[i];
-                }
-                let ptr = u64::from_le_bytes(bytes) as Ptr;
-                Value::Object(ptr)
-            },
-            Type::Null => Value::Null,
-            Type::Number => {
-                let mut arr: [u8; 8] = [0u8; 8];
-                for i in 0..8 {
-                    arr[i] = value_bytes[i];
-                }
-
-                Value::Number(f64::from_le_bytes(arr) as f32)
-            },
-            Type::Boolean => {
-                Value::Bool(value_bytes.first().is_some_and(|val| *val == 1))
-            },
-            Type::Function => {
-                let arity = u8::from_le(*value_bytes.first().unwrap_or_else(|| {panic!("Wrong architecture");}));
-                let mut arr: [u8; 4] = [0u8; 4];
-                for i in 1..5 {
-                    arr[i - 1] = value_bytes[i];
-                }
-                Value::Function(FunctionValue::UserDefined(u32::from_le_bytes(arr) as usize, arity))
-            },
-            _ => panic!("")
-        }
+    /// The next grey value, whose children are to be shaded. `None` once marking is done.
+    pub fn take_grey(&mut self) -> Option<Value> {
+        HEAPS.with_borrow_mut(|heaps| heaps.get_mut(&self.id).unwrap().grey.pop())
     }
 
-    pub fn get_property_from_object(&self, obj_ptr: Ptr, name: &Ptr) -> Value {
-        unsafe {
-            let map = HashTable::from(obj_ptr);
-            let elem = map.get(name);
+    /// The old objects, arrays and generators written to since the last collection. A minor
+    /// collection traces them like roots, as they may be the only way to reach young values.
+    pub fn take_remembered(&mut self) -> Vec<Value> {
+        HEAPS.with_borrow_mut(|heaps| {
+            let arenas = heaps.get_mut(&self.id).unwrap();
+            let objects = arenas.objects.take_remembered(self.id).into_iter().map(Value::Object);
+            let arrays = arenas.arrays.take_remembered(self.id).into_iter().map(Value::Array);
+            let generators = arenas.generators.take_remembered(self.id).into_iter().map(Value::Generator);
+            objects.chain(arrays).chain(generators).collect()
+        })
+    }
 
-            if elem.is_none() {
-                return Value::Null;
+    /// Frees the values `collection` covers that weren't marked since the last sweep, except
+    /// compiled strings. The ones that were marked become old. A major sweep ends the incremental
+    /// collection in progress, if any.
+    pub fn sweep(&mut self, collection: Collection) {
+        fn sweep<T: Stored>(arena: &mut Arena<T>, collection: Collection) -> Vec<T> {
+            match collection {
+                Collection::Minor => arena.sweep_young(),
+                Collection::Major => arena.sweep()
             }
+        }
 
-            elem.unwrap()
+        let freed = HEAPS.with_borrow_mut(|heaps| {
+            let arenas = heaps.get_mut(&self.id).unwrap();
+            if collection == Collection::Major {
+                arenas.marking = false;
+                arenas.grey.clear();
+            }
+            let strings = sweep(&mut arenas.strings, collection);
+            let mut freed = strings.iter().map(|string| string.size()).sum::<usize>();
+            strings.into_iter().for_each(|string| { self.interned_strings.remove(&string); });
+            freed += sweep(&mut arenas.objects, collection).iter().map(|object| object.size()).sum::<usize>();
+            freed += sweep(&mut arenas.arrays, collection).iter().map(|array| array.size()).sum::<usize>();
+            freed += sweep(&mut arenas.generators, collection).iter().map(|generator| generator.size()).sum::<usize>();
+            freed += sweep(&mut arenas.ranges, collection).iter().map(|range| range.size()).sum::<usize>();
+            freed
+        });
+
+        self.allocated_memory -= freed;
+        self.nursery_memory = 0;
+        self.stats.reclaimed_bytes += freed;
+        match collection {
+            Collection::Minor => self.stats.minor_collections += 1,
+            Collection::Major => self.stats.major_collections += 1
         }
     }
 
-    pub fn set_property_for_object(&self, obj_ptr: Ptr, key: Ptr, val: Value) {
-        unsafe {
-            let mut map = HashTable::from(obj_ptr);
+    // < Collection
+
+    // > Object
+    pub fn allocate_object(&mut self, val: HashMap<Handle<String>, Value>) -> Handle<HashTable> {
+        let mut map = HashTable::new();
+
+        for (key, val) in val.into_iter() {
             map.set(key, val);
         }
+
+        self.allocate(map)
     }
 
-    pub fn compare_objects(obj1: Ptr, obj2: Ptr) -> bool {
-        obj1 == obj2
+    pub fn get_object(obj_ptr: Handle<HashTable>) -> Result<HashMap<Handle<String>, Box<Value>>, HeapError> {
+        with_value(obj_ptr, |map| {
+            map.get_all().iter().map(|entry| (entry.key, Box::from(entry.value.clone()))).collect()
+        })
+    }
+
+    pub fn get_object_len(obj_ptr: Handle<HashTable>) -> Result<usize, HeapError> {
+        with_value(obj_ptr, |map| map.get_count())
+    }
+
+    pub fn get_object_entry(obj_ptr: Handle<HashTable>, index: usize) -> Result<(Handle<String>, Value), HeapError> {
+        with_value(obj_ptr, |map| {
+            let entry = map.get_entry(index).unwrap_or_else(|| panic!("Object has no entry {index}"));
+
+            (entry.key, entry.value.clone())
+        })
+    }
+
+    pub fn get_property_from_object(&self, obj_ptr: Handle<HashTable>, name: &Handle<String>) -> Result<Value, HeapError> {
+        with_value(obj_ptr, |map| map.get(name).unwrap_or(Value::Null))
     }
 
-    pub fn concatenate_objects(&mut self, obj1: Ptr, obj2: Ptr) -> Ptr {
-        unsafe {
-            let mut obj_main = HashTable::from(obj1);
-            let obj_sec = HashTable::from(obj2);
+    pub fn set_property_for_object(&mut self, obj_ptr: Handle<HashTable>, key: Handle<String>, val: Value) -> Result<(), HeapError> {
+        self.update(obj_ptr, |map| map.set(key, val))
+    }
+
+    pub fn compare_objects(obj1: Handle<HashTable>, obj2: Handle<HashTable>) -> bool {
+        obj1 == obj2
+    }
 
-            obj_sec.get_all().into_iter().for_each(|en| {
+    pub fn concatenate_objects(&mut self, obj1: Handle<HashTable>, obj2: Handle<HashTable>) -> Result<Handle<HashTable>, HeapError> {
+        let entries = with_value(obj2, |obj_sec| obj_sec.get_all().to_vec())?;
+        self.update(obj1, |obj_main| {
+            entries.into_iter().for_each(|en| {
                 obj_main.set(en.key, en.value);
             });
+        })?;
 
-            obj_main.entries
-        }
+        Ok(obj1)
     }
 
     // < Object
 
     // > Arrays
 
-    pub fn allocate_array(&mut self, val: Vec<Value>) -> Result<Ptr, LayoutError> {
-        unsafe {
-            let len = val.len();
-            let mut arr = Array::of(len);
+    pub fn allocate_array(&mut self, val: Vec<Value>) -> Handle<Array> {
+        let len = val.len();
+        let mut arr = Array::of(len);
 
-            for (index, val) in val.into_iter().enumerate() {
-                arr.set(len - 1 - index, val);
-            }
+        for (index, val) in val.into_iter().enumerate() {
+            arr.set(len - 1 - index, val);
+        }
 
-            self.objects.insert(arr.entries, false);
-            self.heap_layout.insert(arr.entries, arr.layout.unwrap());
-            self.allocated_memory = self.allocated_memory + ARRAY_BYTES_SIZE;
+        self.allocate(arr)
+    }
 
-            self.check_oom();
+    pub fn get_array(obj_ptr: Handle<Array>) -> Result<Vec<Value>, HeapError> {
+        with_value(obj_ptr, |arr| arr.get_all())
+    }
 
-            Ok(arr.entries)
-        }
+    pub fn get_array_len(arr_ptr: Handle<Array>) -> Result<usize, HeapError> {
+        with_value(arr_ptr, |arr| arr.get_count())
     }
 
-    pub fn get_array(obj_ptr: Ptr) -> Vec<Value> {
-        unsafe {
-            let arr = Array::from(obj_ptr);
+    /// The item at `index`, or `None` past the end of the array
+    pub fn get_array_item(arr_ptr: Handle<Array>, index: usize) -> Result<Option<Value>, HeapError> {
+        with_value(arr_ptr, |arr| arr.get(index))
+    }
 
-            arr.get_all()
-        }
+    pub fn compare_arrays(arr1: Handle<Array>, arr2: Handle<Array>) -> bool {
+        arr1 == arr2
     }
 
-    pub fn get_property_from_array(&self, arr_ptr: Ptr, key: Ptr) -> Value {
-        unsafe {
-            let arr = Array::from(arr_ptr);
-            let ind = HeapManager::get_string(key).parse::<usize>().unwrap();
-            let elem = arr.get(ind);
+    pub fn get_property_from_array(&self, arr_ptr: Handle<Array>, key: Handle<String>) -> Result<Value, HeapError> {
+        let ind = HeapManager::get_string(key)?.parse::<usize>().unwrap();
+        self.get_property_from_array_num(arr_ptr, ind)
+    }
 
-            if elem.is_none() {
-                return Value::Null;
-            }
+    pub fn get_property_from_array_num(&self, arr_ptr: Handle<Array>, key: usize) -> Result<Value, HeapError> {
+        with_value(arr_ptr, |arr| arr.get(key).unwrap_or(Value::Null))
+    }
 
-            elem.unwrap()
-        }
+    pub fn set_property_for_array(&mut self, arr_ptr: Handle<Array>, ind_ptr: Handle<String>, val: Value) -> Result<(), HeapError> {
+        let ind = HeapManager::get_string(ind_ptr)?.parse::<usize>().unwrap();
+        self.set_property_for_array_num(arr_ptr, ind, val)
     }
 
-    pub fn get_property_from_array_num(&self, arr_ptr: Ptr, key: usize) -> Value {
-        unsafe {
-            let arr = Array::from(arr_ptr);
-            let elem = arr.get(key);
+    pub fn set_property_for_array_num(&mut self, arr_ptr: Handle<Array>, ind: usize, val: Value) -> Result<(), HeapError> {
+        self.update(arr_ptr, |arr| arr.set(ind, val))
+    }
 
-            if elem.is_none() {
-                return Value::Null;
-            }
+    // < Arrays
 
-            elem.unwrap()
-        }
+    // > Generators
+
+    pub fn allocate_generator(&mut self, ip: usize, locals: Vec<Value>) -> Handle<Generator> {
+        self.allocate(Generator { ip, locals, state: GeneratorState::Suspended })
     }
 
-    pub fn set_property_for_array(&self, arr_ptr: Ptr, ind_ptr: Ptr, val: Value) {
-        unsafe {
-            let ind = HeapManager::get_string(ind_ptr).parse::<usize>().unwrap();
-            let mut arr = Array::from(arr_ptr);
-            arr.set(ind, val);
-        }
+    /// Runs `f` on the generator. It must not read the heap, which is busy meanwhile.
+    pub fn with_generator<R>(ptr: Handle<Generator>, f: impl FnOnce(&Generator) -> R) -> Result<R, HeapError> {
+        with_value(ptr, |generator| f(generator))
     }
 
-    pub fn set_property_for_array_num(&self, arr_ptr: Ptr, ind: usize, val: Value) {
-        unsafe {
-            let mut arr = Array::from(arr_ptr);
-            arr.set(ind, val);
-        }
+    /// Like `with_generator`, for `f`s that change the generator
+    pub fn update_generator<R>(ptr: Handle<Generator>, f: impl FnOnce(&mut Generator) -> R) -> Result<R, HeapError> {
+        write_value(ptr, f)
     }
 
-    pub fn free_array(&mut self, ptr: Ptr) {
-        self.free_object(ptr);
+    // < Generators
+
+    // > Ranges
+
+    pub fn allocate_range(&mut self, range: RangeValue) -> Handle<RangeValue> {
+        self.allocate(range)
     }
 
-    // < Arrays
+    pub fn get_range(ptr: Handle<RangeValue>) -> Result<RangeValue, HeapError> {
+        with_value(ptr, |range| range.clone())
+    }
+
+    // < Ranges
 
     // > Strings
 
-    pub fn get_string(str_ptr: Ptr) -> String {
-        unsafe {
-            read_string(str_ptr.add(1))
-        }
+    pub fn get_string(str_ptr: Handle<String>) -> Result<String, HeapError> {
+        with_value(str_ptr, |string| string.clone())
     }
 
-    pub fn concatenate_strings(&mut self, string1: Ptr, string2: Ptr) -> Ptr {
-        unsafe {
-            let mut bytes1 = read_bytes_until_null(string1);
-            let bytes2 = read_bytes_until_null(string2);
-
-            bytes2.into_iter().for_each(|bt| bytes1.push(bt));
+    pub fn concatenate_strings(&mut self, string1: Handle<String>, string2: Handle<String>) -> Result<Handle<String>, HeapError> {
+        let mut string = HeapManager::get_string(string1)?;
+        string.push_str(&HeapManager::get_string(string2)?);
 
-            self.allocate_string(String::from_utf8(bytes1).unwrap()).unwrap()
-        }
+        Ok(self.allocate_string(string))
     }
 
-    pub fn compare_strings(string1: Ptr, string2: Ptr) -> bool {
+    pub fn compare_strings(string1: Handle<String>, string2: Handle<String>) -> bool {
         string1 == string2
     }
 
-    fn check_string_is_interned(&self, string: &str) -> Option<Ptr> {
-        for st in self.interned_strings.keys() {
-            unsafe {
-                let interned_str = read_string(st.add(1));
-                if interned_str == string {
-                    return Some(*st);
-                }
+    /// Allocates a string that bytecode refers to, which is never collected
+    pub fn allocate_compiled_string(&mut self, string: String) -> Handle<String> {
+        let ptr = self.allocate_string(string);
+        HEAPS.with_borrow_mut(|heaps| heaps.get_mut(&self.id).unwrap().strings.pin(ptr));
+
+        ptr
+    }
+
+    pub fn allocate_string(&mut self, string: String) -> Handle<String> {
+        if let Some(existing_str) = self.interned_strings.get(&string).copied() {
+            // handing out a string that marking hasn't reached could store it in values that
+            // were already traced, and the sweep would free it
+            if self.is_marking() {
+                self.shade(&Value::String(existing_str));
             }
+            return existing_str;
         }
 
-        None
+        let ptr = self.allocate(string.clone());
+        self.interned_strings.insert(string, ptr);
+
+        ptr
     }
 
-    pub fn allocate_compiled_string(&mut self, string: String) -> Result<Ptr, LayoutError> {
-        self.allocate_string_internal(string, true)
+    // > Strings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strings_are_interned() {
+        let mut heap = HeapManager::new(1024);
+        let str1 = heap.allocate_string("test".to_string());
+        let str2 = heap.allocate_string("test".to_string());
+
+        assert_eq!(str1, str2);
+        assert_eq!(HeapManager::get_string(str1).unwrap(), "test");
     }
 
-    pub fn allocate_string(&mut self, string: String) -> Result<Ptr, LayoutError> {
-        self.allocate_string_internal(string, false)
+    #[test]
+    fn test_sweep_frees_unmarked_values() {
+        let mut heap = HeapManager::new(1024);
+        let kept = heap.allocate_array(vec![Value::Number(2f64), Value::Number(1f64)]);
+        let freed = heap.allocate_array(vec![Value::Number(3f64)]);
+
+        heap.mark(&Value::Array(kept), Collection::Major);
+        heap.sweep(Collection::Major);
+
+        assert!(HeapManager::is_live(&Value::Array(kept)));
+        assert!(!HeapManager::is_live(&Value::Array(freed)));
+        assert_eq!(heap.allocated_memory, Array::of(2).size());
     }
 
-    fn allocate_string_internal(&mut self, string: String, is_from_compilation: bool) -> Result<Ptr, LayoutError> {
-        if let Some(existing_str) = self.check_string_is_interned(&string) {
-            return Ok(existing_str);
-        }
-        let internal_string_len = string.len() + 1 + 1;
-        unsafe {
-            let layout = Layout::array::<u8>(internal_string_len)?;
-            let ptr = alloc(layout);
+    #[test]
+    fn test_compiled_strings_are_never_freed() {
+        let mut heap = HeapManager::new(1024);
+        let compiled = heap.allocate_compiled_string("compiled".to_string());
+        let runtime = heap.allocate_string("runtime".to_string());
 
-            write_byte(ptr, Type::String as u8);
-            write_string(ptr.add(1), string);
+        heap.sweep(Collection::Major);
 
-            self.interned_strings.insert(ptr, is_from_compilation);
-            self.heap_layout.insert(ptr, layout);
-            self.allocated_memory = self.allocated_memory + internal_string_len;
+        assert!(HeapManager::is_live(&Value::String(compiled)));
+        assert!(!HeapManager::is_live(&Value::String(runtime)));
+    }
 
-            self.check_oom();
+    #[test]
+    fn test_reused_slot_does_not_resolve_stale_handle() {
+        let mut heap = HeapManager::new(1024);
+        let stale = heap.allocate_range(RangeValue::new(0f64, 3f64, 1f64, false));
+        heap.sweep(Collection::Major);
 
-            Ok(ptr)
-        }
+        let fresh = heap.allocate_range(RangeValue::new(5f64, 6f64, 1f64, false));
+        assert_ne!(stale, fresh);
+        assert!(!HeapManager::is_live(&Value::Range(stale)));
+        assert_eq!(HeapManager::get_range(fresh).unwrap(), RangeValue::new(5f64, 6f64, 1f64, false));
     }
 
-    pub fn free_string(&mut self, ptr: Ptr) {
-        self.interned_strings.remove(&ptr);
-        self.free(ptr);
+    #[test]
+    fn test_use_after_free_is_detected() {
+        let mut heap = HeapManager::new(1024);
+        let ptr = heap.allocate_string("gone".to_string());
+        let arr_ptr = heap.allocate_array(vec![Value::Number(1f64)]);
+        heap.sweep(Collection::Major);
+
+        assert_eq!(HeapManager::get_string(ptr), Err(HeapError::UseAfterFree("string")));
+        assert_eq!(heap.set_property_for_array_num(arr_ptr, 0, Value::Null), Err(HeapError::UseAfterFree("array")));
+        assert_eq!(Value::String(ptr).to_string(), "[Use after free: the string was already collected]");
     }
 
-    // > Strings
-}
\ No newline at end of file
+    #[test]
+    fn test_out_of_memory_is_an_error() {
+        let mut heap = HeapManager::new(16);
+        heap.allocate_string("short".to_string());
+        assert_eq!(heap.check_oom(), Ok(()));
+
+        // the allocation goes through, and the check reports it once it's over the size
+        let ptr = heap.allocate_string("longer than the heap".to_string());
+        assert_eq!(HeapManager::get_string(ptr).unwrap(), "longer than the heap");
+        assert_eq!(heap.check_oom(), Err(HeapError::OutOfMemory));
+    }
+
+    #[test]
+    fn test_minor_collection_promotes_survivors() {
+        let mut heap = HeapManager::new(1024);
+        let survivor = heap.allocate_string("survivor".to_string());
+        heap.mark(&Value::String(survivor), Collection::Minor);
+        heap.sweep(Collection::Minor);
+
+        // old values are live as far as minor collections go, marked or not
+        let young = heap.allocate_string("young".to_string());
+        heap.sweep(Collection::Minor);
+
+        assert!(HeapManager::is_live(&Value::String(survivor)));
+        assert!(!HeapManager::is_live(&Value::String(young)));
+        assert!(!heap.mark(&Value::String(survivor), Collection::Minor));
+    }
+
+    #[test]
+    fn test_writes_to_old_values_are_remembered() {
+        let mut heap = HeapManager::new(1024);
+        let arr = heap.allocate_array(vec![Value::Null]);
+        heap.mark(&Value::Array(arr), Collection::Minor);
+        heap.sweep(Collection::Minor);
+
+        let young = heap.allocate_string("young".to_string());
+        heap.set_property_for_array_num(arr, 0, Value::String(young)).unwrap();
+
+        let remembered = heap.take_remembered();
+        assert!(matches!(remembered[..], [Value::Array(ptr)] if ptr == arr));
+        assert!(heap.take_remembered().is_empty());
+    }
+
+    #[test]
+    fn test_values_allocated_while_marking_survive() {
+        let mut heap = HeapManager::new(1024);
+        heap.start_marking();
+        let allocated = heap.allocate_string("allocated".to_string());
+
+        assert!(matches!(heap.take_grey(), Some(Value::String(ptr)) if ptr == allocated));
+        heap.sweep(Collection::Major);
+        assert!(HeapManager::is_live(&Value::String(allocated)));
+        assert!(!heap.is_marking());
+    }
+
+    #[test]
+    fn test_writes_to_marked_values_turn_them_grey() {
+        let mut heap = HeapManager::new(1024);
+        let arr = heap.allocate_array(vec![Value::Null]);
+        let unmarked = heap.allocate_string("unmarked".to_string());
+        heap.start_marking();
+        heap.shade(&Value::Array(arr));
+        assert!(heap.take_grey().is_some());
+
+        heap.set_property_for_array_num(arr, 0, Value::String(unmarked)).unwrap();
+
+        assert!(matches!(heap.take_grey(), Some(Value::Array(ptr)) if ptr == arr));
+        assert!(heap.take_grey().is_none());
+    }
+
+    #[test]
+    fn test_interned_strings_are_shaded_while_marking() {
+        let mut heap = HeapManager::new(1024);
+        let interned = heap.allocate_string("interned".to_string());
+        heap.start_marking();
+
+        assert_eq!(heap.allocate_string("interned".to_string()), interned);
+        assert!(matches!(heap.take_grey(), Some(Value::String(ptr)) if ptr == interned));
+        // shaded once, it isn't queued again
+        heap.allocate_string("interned".to_string());
+        assert!(heap.take_grey().is_none());
+        heap.sweep(Collection::Major);
+        assert!(HeapManager::is_live(&Value::String(interned)));
+    }
+
+    #[test]
+    fn test_shrinking_values_frees_nursery_memory() {
+        let mut heap = HeapManager::new(1024);
+        let key = heap.allocate_string("key".to_string());
+        let obj = heap.allocate_object(HashMap::new());
+        heap.mark(&Value::String(key), Collection::Minor);
+        heap.mark(&Value::Object(obj), Collection::Minor);
+        heap.sweep(Collection::Minor);
+        let allocated = heap.allocated_memory;
+
+        heap.set_property_for_object(obj, key, Value::Null).unwrap();
+        assert!(heap.nursery_memory > 0);
+        heap.update(obj, |map| map.delete(key)).unwrap();
+        assert_eq!(heap.nursery_memory, 0);
+        assert_eq!(heap.allocated_memory, allocated);
+    }
+
+    #[test]
+    fn test_stats_count_allocations_and_collections() {
+        let mut heap = HeapManager::new(1024);
+        let kept = heap.allocate_string("kept".to_string());
+        heap.allocate_string("kept".to_string());
+        heap.allocate_array(vec![Value::String(kept)]);
+        heap.mark(&Value::String(kept), Collection::Major);
+        heap.sweep(Collection::Major);
+
+        let stats = heap.stats();
+        assert_eq!((stats.allocations.strings, stats.allocations.arrays), (1, 1));
+        assert_eq!(stats.major_collections, 1);
+        assert_eq!(stats.reclaimed_bytes, Array::of(1).size());
+        assert_eq!(stats.live_bytes, "kept".to_string().size());
+        assert_eq!(stats.peak_bytes, stats.allocated_bytes);
+    }
+
+    #[test]
+    fn test_handles_survive_encoding() {
+        let mut heap = HeapManager::new(1024);
+        let ptr = heap.allocate_string("encoded".to_string());
+
+        assert_eq!(Handle::<String>::from_bytes(ptr.to_bytes()), ptr);
+    }
+}
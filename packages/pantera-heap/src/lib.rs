@@ -1,8 +1,11 @@
 pub mod value;
+pub mod arena;
 pub mod heap;
 pub mod types;
 pub mod stack;
 pub mod hash_table;
-mod bytes;
-mod utils;
 pub mod array;
+pub mod generator;
+pub mod range;
+pub mod runtime;
+pub mod stats;
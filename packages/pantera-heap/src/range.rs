@@ -0,0 +1,81 @@
+/// A lazily evaluated arithmetic sequence. Elements are computed from the bounds,
+/// so iterating or indexing a range never allocates an array.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeValue {
+    pub start: f64,
+    pub end: f64,
+    pub step: f64,
+    pub inclusive: bool
+}
+
+impl RangeValue {
+    pub fn new(start: f64, end: f64, step: f64, inclusive: bool) -> Self {
+        Self { start, end, step, inclusive }
+    }
+
+    pub fn len(&self) -> usize {
+        let steps = (self.end - self.start) / self.step;
+        if steps < 0f64 {
+            return 0;
+        }
+
+        if self.inclusive {
+            steps.floor() as usize + 1
+        } else {
+            steps.ceil() as usize
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<f64> {
+        if index >= self.len() {
+            return None;
+        }
+
+        Some(self.start + index as f64 * self.step)
+    }
+
+    pub fn contains(&self, value: f64) -> bool {
+        let index = (value - self.start) / self.step;
+        index >= 0f64 && index.fract() == 0f64 && (index as usize) < self.len()
+    }
+
+    /// Resolves negative bounds against a collection of `len` elements, so `-1` is the last one.
+    pub fn normalized(&self, len: usize) -> RangeValue {
+        let resolve = |bound: f64| if bound < 0f64 { bound + len as f64 } else { bound };
+        RangeValue::new(resolve(self.start), resolve(self.end), self.step, self.inclusive)
+    }
+
+    /// Returns the elements of `self` found at the positions described by `indexes`.
+    /// Positions past the end are dropped, so the result is always a valid range.
+    pub fn slice(&self, indexes: &RangeValue) -> RangeValue {
+        let len = self.len() as f64;
+        let first = indexes.start;
+        let mut count = indexes.len() as f64;
+        if first < 0f64 || first >= len {
+            count = 0f64;
+        } else if indexes.step > 0f64 {
+            count = count.min(((len - first) / indexes.step).ceil());
+        } else {
+            count = count.min((first / -indexes.step).floor() + 1f64);
+        }
+
+        let start = self.start + first * self.step;
+        let step = self.step * indexes.step;
+        RangeValue::new(start, start + count * step, step, false)
+    }
+}
+
+impl std::fmt::Display for RangeValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let operator = if self.inclusive { "..=" } else { ".." };
+        if self.step == 1f64 {
+            write!(f, "{}{operator}{}", self.start, self.end)
+        } else {
+            write!(f, "{}{operator}{} by {}", self.start, self.end, self.step)
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::heap::HeapManager;
+use crate::stack::Stack;
+use crate::value::Value;
+
+/// The parts of a running VM that builtins can reach
+pub trait Runtime {
+    fn stack(&mut self) -> &mut Stack;
+    fn heap(&self) -> Rc<RefCell<HeapManager>>;
+    fn extensions(&mut self) -> &mut Extensions;
+    /// How many arguments the running builtin was called with, for optional parameters
+    fn arg_count(&self) -> usize;
+    /// Calls a Pantera function with `args` and runs it to completion. The builtin's own
+    /// arguments stay on the stack meanwhile, so it should leave them there (and push any
+    /// values it allocates) until it's done calling, or the GC may collect them.
+    fn call(&mut self, callee: Value, args: Vec<Value>) -> Result<Value, String>;
+    /// Adds text to the program's output without ending the line, as `print` would
+    fn write(&mut self, text: &str);
+    fn write_error(&mut self, text: &str);
+    /// Reads a line from the script's input, blocking until there is one. `None` is the
+    /// end of the input.
+    fn read_line(&mut self) -> Option<String>;
+}
+
+/// State that std modules keep between calls, such as compiled regex patterns. It lives
+/// as long as the VM, with one value per type.
+#[derive(Default)]
+pub struct Extensions {
+    values: HashMap<TypeId, Box<dyn Any>>
+}
+
+impl Extensions {
+    pub fn get_or_default<T: Any + Default>(&mut self) -> &mut T {
+        self.values.entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(T::default()))
+            .downcast_mut::<T>()
+            .unwrap()
+    }
+}
@@ -28,6 +28,11 @@ impl Stack {
         self.elements.get(index + self.offset)
     }
 
+    /// The value `distance` slots below the top of the stack
+    pub fn peek(&self, distance: usize) -> Option<&Value> {
+        self.top.checked_sub(distance + 1).filter(|index| *index >= self.offset).map(|index| &self.elements[index])
+    }
+
     pub fn debug(&self) {
         for i in 0..self.top {
             print!("{:?}, ", self.elements.get(i).unwrap())
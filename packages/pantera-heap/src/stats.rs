@@ -0,0 +1,44 @@
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+/// How many values of each type a heap allocated
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Allocations {
+    pub strings: usize,
+    pub objects: usize,
+    pub arrays: usize,
+    pub generators: usize,
+    pub ranges: usize
+}
+
+/// Counters about a heap and its collections, for tuning the heap and nursery sizes
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HeapStats {
+    pub allocations: Allocations,
+    /// Every byte allocated so far, including what values grew by
+    pub allocated_bytes: usize,
+    /// The bytes taken by the values allocated now
+    pub live_bytes: usize,
+    /// The most bytes that were allocated at once
+    pub peak_bytes: usize,
+    /// The most bytes the heap may hold
+    pub heap_size: usize,
+    pub minor_collections: usize,
+    pub major_collections: usize,
+    /// The bytes freed by collections
+    pub reclaimed_bytes: usize,
+    /// How many times the collector stopped the script, incremental steps included
+    pub pauses: usize,
+    pub total_pause: Duration,
+    pub max_pause: Duration
+}
+
+impl Display for HeapStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let allocations = &self.allocations;
+        writeln!(f, "allocations: {} strings, {} objects, {} arrays, {} generators, {} ranges", allocations.strings, allocations.objects, allocations.arrays, allocations.generators, allocations.ranges)?;
+        writeln!(f, "bytes: {} allocated, {} live, {} at peak of {}", self.allocated_bytes, self.live_bytes, self.peak_bytes, self.heap_size)?;
+        writeln!(f, "collections: {} minor, {} major, {} bytes reclaimed", self.minor_collections, self.major_collections, self.reclaimed_bytes)?;
+        write!(f, "pauses: {}, {:?} in total, {:?} at most", self.pauses, self.total_pause, self.max_pause)
+    }
+}
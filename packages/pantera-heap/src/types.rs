@@ -6,7 +6,13 @@ pub enum Type {
     String = 4,
     Object = 5,
     Array = 6,
-    Null = 7
+    Null = 7,
+    Generator = 8,
+    Task = 9,
+    Channel = 10,
+    Range = 11,
+    Builtin = 12,
+    Native = 13
 }
 
 impl From<Type> for u8 {
@@ -19,7 +25,13 @@ impl From<Type> for u8 {
             Type::String => 4u8,
             Type::Object => 5u8,
             Type::Array => 6u8,
-            Type::Null => 7u8
+            Type::Null => 7u8,
+            Type::Generator => 8u8,
+            Type::Task => 9u8,
+            Type::Channel => 10u8,
+            Type::Range => 11u8,
+            Type::Builtin => 12u8,
+            Type::Native => 13u8
         }
     }
 }
@@ -34,6 +46,12 @@ impl From<u8> for Type {
             5 => Type::Object,
             6 => Type::Array,
             7 => Type::Null,
+            8 => Type::Generator,
+            9 => Type::Task,
+            10 => Type::Channel,
+            11 => Type::Range,
+            12 => Type::Builtin,
+            13 => Type::Native,
             0 => Type::Empty,
             _ => panic!("Type doesn't exist")
         }
@@ -1,25 +1,71 @@
-use std::cell::RefCell;
 use std::fmt::{Display, Formatter};
 use std::ops::Add;
-use std::rc::Rc;
-use crate::heap::{HeapManager, Ptr};
-use crate::stack::Stack;
+use crate::arena::Handle;
+use crate::array::Array;
+use crate::generator::Generator;
+use crate::hash_table::HashTable;
+use crate::heap::{HeapError, HeapManager};
+use crate::range::RangeValue;
+use crate::runtime::Runtime;
+
+/// Builtins that may suspend the calling task, stop the program or read the scheduler's clock.
+/// They are implemented by the VM's scheduler instead of running against the stack directly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SchedulerCall {
+    Sleep,
+    Input,
+    Channel,
+    Send,
+    Receive,
+    Exit,
+    Now,
+    Clock
+}
+
+/// Builtins report failures as `Err` so that scripts can catch them with `try`
+pub type BuiltinResult = Result<(), String>;
+
+pub type Builtin = fn(&mut dyn Runtime) -> BuiltinResult;
 
 #[derive(Debug, Clone)]
 pub enum FunctionValue {
-    Builtin(fn(&mut Stack, Rc<RefCell<HeapManager>>)),
-    UserDefined(usize, u8)
+    Builtin(Builtin),
+    Scheduler(SchedulerCall),
+    UserDefined(usize, u8),
+    /// A host function registered by the embedder, by its index in the VM's table
+    Native(usize)
 }
 
 #[derive(Debug, Clone)]
 pub enum Value {
-    Number(f32),
+    Number(f64),
     Bool(bool),
     Null,
     Function(FunctionValue),
-    String(Ptr),
-    Object(Ptr),
-    Array(Ptr)
+    String(Handle<String>),
+    Object(Handle<HashTable>),
+    Array(Handle<Array>),
+    Generator(Handle<Generator>),
+    Task(usize),
+    Channel(usize),
+    Range(Handle<RangeValue>)
+}
+
+impl Value {
+    /// Equality as `is` and `in` see it: by value for primitives, strings and ranges, by
+    /// identity for objects and arrays
+    pub fn is_same(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Number(num1), Value::Number(num2)) => num1 == num2,
+            (Value::Bool(bl1), Value::Bool(bl2)) => bl1 == bl2,
+            (Value::Null, Value::Null) => true,
+            (Value::String(str1), Value::String(str2)) => HeapManager::compare_strings(*str1, *str2),
+            (Value::Object(ptr1), Value::Object(ptr2)) => ptr1 == ptr2,
+            (Value::Array(ptr1), Value::Array(ptr2)) => ptr1 == ptr2,
+            (Value::Range(ptr1), Value::Range(ptr2)) => matches!((HeapManager::get_range(*ptr1), HeapManager::get_range(*ptr2)), (Ok(range1), Ok(range2)) if range1 == range2),
+            _ => false
+        }
+    }
 }
 
 impl Display for Value {
@@ -29,18 +75,33 @@ impl Display for Value {
             Self::Null => f.write_str("null"),
             Self::Bool(val) => f.write_str(&val.to_string()),
             Self::Function(_) => f.write_str("[function]"),
-            Self::String(ptr) => {
-                let str = HeapManager::get_string(*ptr);
-                f.write_str(&str.to_string())
-            },
+            Self::Generator(_) => f.write_str("[generator]"),
+            Self::Task(_) => f.write_str("[task]"),
+            Self::Channel(_) => f.write_str("[channel]"),
+            Self::String(_) | Self::Object(_) | Self::Array(_) | Self::Range(_) => match self.heap_text() {
+                Ok(text) => f.write_str(&text),
+                // values are only collected once the script drops them, so hosts are the
+                // ones that can get here, with a value they kept
+                Err(err) => write!(f, "[{err}]")
+            }
+        }
+    }
+}
+
+impl Value {
+    /// The text of a value whose contents are on the heap
+    fn heap_text(&self) -> Result<String, HeapError> {
+        match self {
+            Self::Range(ptr) => Ok(HeapManager::get_range(*ptr)?.to_string()),
+            Self::String(ptr) => HeapManager::get_string(*ptr),
             Self::Object(obj_ptr) => {
-                let obj = HeapManager::get_object(*obj_ptr);
+                let obj = HeapManager::get_object(*obj_ptr)?;
                 let mut str = String::new();
                 str = str.add("{ ");
                 let mut pairs = vec![];
                 for (key, val) in obj {
                     let mut pair = String::new();
-                    let key_string = HeapManager::get_string(key);
+                    let key_string = HeapManager::get_string(key)?;
                     pair = pair.add(&key_string);
                     pair = pair.add(": ");
                     pair = pair.add(&format!("{}", val));
@@ -49,10 +110,10 @@ impl Display for Value {
                 str = str.add(pairs.join(", ").as_str());
                 str = str.add(" }");
 
-                f.write_str(&str)
+                Ok(str)
             },
             Self::Array(arr_ptr) => {
-                let arr = HeapManager::get_array(*arr_ptr);
+                let arr = HeapManager::get_array(*arr_ptr)?;
                 let mut str = String::new();
                 str = str.add("[ ");
                 let mut pairs = vec![];
@@ -64,8 +125,9 @@ impl Display for Value {
                 str = str.add(pairs.join(", ").as_str());
                 str = str.add(" ]");
 
-                f.write_str(&str)
-            }
+                Ok(str)
+            },
+            _ => Ok(self.to_string())
         }
     }
 }
\ No newline at end of file
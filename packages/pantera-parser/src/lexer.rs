@@ -77,8 +77,9 @@ impl Lexer {
             }),
             '.' => {
                 if self.match_char('.') {
+                    let typ = if self.match_char('=') { TokenType::DoubleDotEqual } else { TokenType::DoubleDot };
                     self.tokens.push(Token {
-                        typ: TokenType::DoubleDot,
+                        typ,
                         line: self.line,
                     });
                 } else {
@@ -162,7 +163,7 @@ impl Lexer {
                     }
                 }
                 self.tokens.push(Token {
-                    typ: TokenType::Number(number_literal.parse::<f32>().unwrap()),
+                    typ: TokenType::Number(number_literal.parse::<f64>().unwrap()),
                     line: self.line,
                 })
             }
@@ -319,6 +320,34 @@ impl Lexer {
                 typ: TokenType::Const,
                 line
             }),
+            "yield" => Some(Token {
+                typ: TokenType::Yield,
+                line
+            }),
+            "try" => Some(Token {
+                typ: TokenType::Try,
+                line
+            }),
+            "catch" => Some(Token {
+                typ: TokenType::Catch,
+                line
+            }),
+            "spawn" => Some(Token {
+                typ: TokenType::Spawn,
+                line
+            }),
+            "await" => Some(Token {
+                typ: TokenType::Await,
+                line
+            }),
+            "in" => Some(Token {
+                typ: TokenType::In,
+                line
+            }),
+            "by" => Some(Token {
+                typ: TokenType::By,
+                line
+            }),
             _ => None,
         }
     }
@@ -511,6 +540,13 @@ mod tests {
             Pair {key: "false", value: TokenType::False},
             Pair {key: "break", value: TokenType::Break},
             Pair {key: "return", value: TokenType::Return},
+            Pair {key: "yield", value: TokenType::Yield},
+            Pair {key: "spawn", value: TokenType::Spawn},
+            Pair {key: "await", value: TokenType::Await},
+            Pair {key: "in", value: TokenType::In},
+            Pair {key: "by", value: TokenType::By},
+            Pair {key: "try", value: TokenType::Try},
+            Pair {key: "catch", value: TokenType::Catch},
 
         ];
 
@@ -2,13 +2,14 @@ use std::iter::Peekable;
 use std::string::ToString;
 use std::vec::IntoIter;
 use pantera_ast::expression::*;
-use pantera_ast::{array, assignment, binary, block, bool_, break_, call, declaration, expression, fun_body, fun_declaration, group, identifier, if_, loop_, member, multi_declaration, nil, number, object, print_, return_, string, unary};
+use pantera_ast::{array, assignment, await_, binary, block, bool_, break_, call, expression, fun_body, fun_declaration, group, identifier, if_, iterate_, loop_, try_, member, multi_declaration, nil, number, object, print_, range, return_, spawn, string, unary, yield_};
 use crate::token::{Token, TokenType};
 use pantera_ast::statement::*;
 use crate::errors::ParseError;
 
 pub struct Parser {
     pub source: Peekable<IntoIter<Token>>,
+    function_yields: bool,
 }
 
 const FUNCTION_NAME_SEPARATOR: &str = "_";
@@ -43,6 +44,9 @@ impl Parser{
             TokenType::Return => {
                 self.parse_return_stmt()
             }
+            TokenType::Yield => {
+                self.parse_yield_stmt()
+            }
             TokenType::LeftParen => {
                 self.parse_block_stmt(false)
             },
@@ -52,6 +56,9 @@ impl Parser{
             TokenType::Loop => {
                 self.parse_loop_stmt()
             },
+            TokenType::Try => {
+                self.parse_try_stmt()
+            },
             TokenType::Const | TokenType::Var => {
                 self.parse_decl_statement()
             }
@@ -63,6 +70,7 @@ impl Parser{
 
     pub fn parse_function_declaration(&mut self) -> ParserResult<GlobalStatement> {
         self.advance();
+        self.function_yields = false;
         let mut id_parts = vec![];
         let mut params = vec![];
         if self.peek().typ == TokenType::LeftParen {
@@ -71,9 +79,19 @@ impl Parser{
                 line: 1
             });
         }
+        let mut return_type = None;
         while self.peek().typ != TokenType::LeftParen {
             let token = self.peek();
-            if let TokenType::Identifier(_ident) = &token.typ {
+            if token.typ == TokenType::Colon {
+                self.advance();
+                return_type = Some(self.parse_type_annotation()?);
+                if self.peek().typ != TokenType::LeftParen {
+                    return Err(ParseError{
+                        message: "Expected function body after return type".to_string(),
+                        line: 1
+                    });
+                }
+            } else if let TokenType::Identifier(_ident) = &token.typ {
                 let TokenType::Identifier(ident) = self.advance().unwrap().typ else { unreachable!(); };
                 id_parts.push(ident);
                 if self.peek().typ == TokenType::LeftBrace {
@@ -89,19 +107,24 @@ impl Parser{
             }
         }
 
+        let body = self.parse_block_stmt(true)?;
+
         Ok(fun_declaration!{
             name: Identifier{name: id_parts.join(FUNCTION_NAME_SEPARATOR), id: 1.0},
             params,
-            body: self.parse_block_stmt(true)?,
+            return_type,
+            is_generator: self.function_yields,
+            body,
         })
     }
 
-    pub fn parse_function_params(&mut self) -> ParserResult<Vec<Identifier>> {
+    pub fn parse_function_params(&mut self) -> ParserResult<Vec<Parameter>> {
         self.advance();
         let mut ids = vec![];
         if let TokenType::Identifier(_ident) = &self.peek().typ {
             let TokenType::Identifier(ident) = self.advance().unwrap().typ else { unreachable!(); };
-            ids.push(Identifier{name: ident, id: 1.0});
+            let typ = self.parse_optional_type_annotation()?;
+            ids.push(Parameter{name: ident, typ});
         } else {
             return Err(ParseError{
                 message: "Expected formal function parameter definition".to_string(),
@@ -112,7 +135,8 @@ impl Parser{
             self.consume(TokenType::Comma, "Expected comma to separate function parameter")?;
             if let TokenType::Identifier(_ident) = &self.peek().typ {
                 let TokenType::Identifier(ident) = self.advance().unwrap().typ else { unreachable!(); };
-                ids.push(Identifier{name: ident, id: 1.0});
+                let typ = self.parse_optional_type_annotation()?;
+                ids.push(Parameter{name: ident, typ});
             } else {
                 return Err(ParseError{
                     message: "Expected formal function parameter definition".to_string(),
@@ -123,6 +147,39 @@ impl Parser{
         Ok(ids)
     }
 
+    pub fn parse_optional_type_annotation(&mut self) -> ParserResult<Option<TypeAnnotation>> {
+        if self.peek().typ != TokenType::Colon {
+            return Ok(None);
+        }
+        self.advance();
+        Ok(Some(self.parse_type_annotation()?))
+    }
+
+    pub fn parse_type_annotation(&mut self) -> ParserResult<TypeAnnotation> {
+        let token = self.advance().unwrap();
+        match &token.typ {
+            TokenType::Nil => Ok(TypeAnnotation::Null),
+            TokenType::Identifier(name) => match name.as_str() {
+                "number" => Ok(TypeAnnotation::Number),
+                "string" => Ok(TypeAnnotation::String),
+                "bool" => Ok(TypeAnnotation::Bool),
+                "object" => Ok(TypeAnnotation::Object),
+                "array" => Ok(TypeAnnotation::Array),
+                "function" => Ok(TypeAnnotation::Function),
+                "range" => Ok(TypeAnnotation::Range),
+                "any" => Ok(TypeAnnotation::Any),
+                _ => Err(ParseError {
+                    message: format!("Unknown type {name}"),
+                    line: token.line
+                })
+            },
+            _ => Err(ParseError {
+                message: "Expected type name after :".to_string(),
+                line: token.line
+            })
+        }
+    }
+
     pub fn parse_decl_statement(&mut self) -> ParserResult<Statement> {
         let token = self.advance().unwrap();
         let declaration_kind =
@@ -134,18 +191,21 @@ impl Parser{
         let mut declarations = vec![];
         loop {
             let TokenType::Identifier(assignee) = self.advance().unwrap().typ else {panic!("Assignee has to be a variable")};
+            let typ = self.parse_optional_type_annotation()?;
             if self.peek().typ == TokenType::Equal {
                 self.advance();
                 let value = self.parse_expression()?;
                 declarations.push(DeclarationStatement {
                     kind: declaration_kind.clone(),
                     variable: assignee,
+                    typ,
                     value: Some(value),
                 })
             } else {
                 declarations.push(DeclarationStatement {
                     kind: declaration_kind.clone(),
                     variable: assignee,
+                    typ,
                     value: None,
                 })
             }
@@ -178,7 +238,7 @@ impl Parser{
                 iterate_reverse = true;
                 self.advance();
             }
-            let range = self.parse_range()?;
+            let iterable = self.parse_expression()?;
             if self.peek().typ == TokenType::As {
                 self.advance();
                 let identifier = self.parse_expression()?;
@@ -193,126 +253,14 @@ impl Parser{
 
             }
             if self.peek().typ == TokenType::LeftParen {
-                if let Some(stop) = range.stop {
-                    let body = self.parse_statement()?;
-                    let Statement::Block(stmts) = body else { panic!("Not a block statement"); };
-
-                    let [start, stop]: [Expression; 2] = if iterate_reverse { [stop, range.start] } else { [range.start, stop] };
-
-                    let mut statements = vec![];
-                    let init_clause = declaration! {
-                            kind: DeclarationKind::Var,
-                            variable: alias.clone(),
-                            value: Some(start),
-                        };
-                    let mut loop_stmts = vec![];
-                    stmts.statements.into_iter().for_each(|st| loop_stmts.push(st));
-
-                    statements.push(block! {
-                        statements: loop_stmts,
-                    });
+                let body = self.parse_statement()?;
 
-                    statements.push(expression! {
-                        expr: assignment! {
-                            assignee: identifier!(alias.clone()),
-                            value: binary! {
-                                left: identifier!(alias.clone()),
-                                operator: if iterate_reverse { Operator::Minus } else { Operator::Plus },
-                                right: number!(1f32),
-                            },
-                        }
-                    });
-
-                    statements.push(if_! {
-                        condition: binary!{
-                            left: identifier!(alias.clone()),
-                            operator: if iterate_reverse { Operator::Le } else { Operator::Ge },
-                            right: stop,
-                        },
-                        body: break_!(),
-                        alternative: None,
-                    });
-
-                    Ok(block! {
-                        statements: vec![init_clause, loop_! {
-                            body: block! {
-                                statements
-                            },
-                            alias
-                        }],
-                    })
-                } else {
-                    let body = self.parse_statement()?;
-                    let Statement::Block(stmts) = body else { panic!("Not a block statement"); };
-
-                    let iterable_collection = range.start;
-                    let alias_index = "__".to_string() + &alias;
-
-                    let mut statements = vec![];
-                    let init_clause = declaration! {
-                            kind: DeclarationKind::Var,
-                            variable: alias_index.clone(),
-                            value: if iterate_reverse {Some(binary! {left: call! {
-                                callee: identifier!("len".to_string()),
-                                args: vec![
-                                    iterable_collection.clone()
-                                ],
-                            }, operator: Operator::Minus, right: number!(1f32) })} else {Some(number!(0f32))},
-                        };
-                    let mut loop_stmts = vec![];
-                    stmts.statements.into_iter().for_each(|st| loop_stmts.push(st));
-
-                    statements.push(if_! {
-                        condition: binary! {
-                            left: identifier!(alias_index.clone()),
-                            operator: if iterate_reverse { Operator::Less } else { Operator::Ge },
-                            right: if iterate_reverse {number!(0f32)} else {call! {
-                                callee: identifier!("len".to_string()),
-                                args: vec![
-                                    iterable_collection.clone()
-                                ],
-                            }},
-                        },
-                        body: break_!(),
-                        alternative: None,
-                    });
-
-                    statements.push(declaration! {
-                        kind: DeclarationKind::Var,
-                        variable: alias.clone(),
-                        value: Some(call! {
-                            callee: identifier!("internal_iterable_get".to_string()),
-                            args: vec![
-                                iterable_collection,
-                                identifier!(alias_index.clone())
-                            ],
-                        }
-                    )});
-
-                    statements.push(block! {
-                        statements: loop_stmts,
-                    });
-
-                    statements.push(expression! {
-                        expr: assignment! {
-                            assignee: identifier!(alias_index.clone()),
-                            value: binary! {
-                                left: identifier!(alias_index),
-                                operator: if iterate_reverse { Operator::Minus } else { Operator::Plus },
-                                right: number!(1f32),
-                            },
-                        }
-                    });
-
-                    Ok(block! {
-                        statements: vec![init_clause, loop_! {
-                            body: block! {
-                                statements
-                            },
-                            alias
-                        }],
-                    })
-                }
+                Ok(iterate_! {
+                    iterable,
+                    alias,
+                    reverse: iterate_reverse,
+                    body
+                })
             } else {
                 Err(ParseError {
                     line: 1,
@@ -322,23 +270,6 @@ impl Parser{
         }
     }
 
-    pub fn parse_range(&mut self) -> ParserResult<Range> {
-        let start = self.parse_expression()?;
-        if self.peek().typ == TokenType::DoubleDot {
-            self.advance();
-            let stop = self.parse_expression()?;
-            Ok(Range {
-                start,
-                stop: Some(stop)
-            })
-        } else {
-            Ok(Range{
-                start,
-                stop: None
-            })
-        }
-    }
-
     pub fn parse_if_stmt(&mut self) -> ParserResult<Statement> {
         self.advance();
         let expr = self.parse_expression()?;
@@ -411,6 +342,53 @@ impl Parser{
         })
     }
 
+    pub fn parse_try_stmt(&mut self) -> ParserResult<Statement> {
+        self.advance();
+        if self.peek().typ != TokenType::LeftParen {
+            return Err(ParseError {
+                message: "Expected block after try".to_string(),
+                line: self.peek().line
+            });
+        }
+        let body = self.parse_block_stmt(false)?;
+        self.consume(TokenType::Catch, "Expected catch after try block")?;
+
+        let mut alias = "error".to_string();
+        if let TokenType::Identifier(_ident) = &self.peek().typ {
+            let TokenType::Identifier(ident) = self.advance().unwrap().typ else { unreachable!(); };
+            alias = ident;
+        }
+        if self.peek().typ != TokenType::LeftParen {
+            return Err(ParseError {
+                message: "Expected block after catch".to_string(),
+                line: self.peek().line
+            });
+        }
+        let handler = self.parse_block_stmt(false)?;
+
+        Ok(try_! {
+            body,
+            alias,
+            handler
+        })
+    }
+
+    pub fn parse_yield_stmt(&mut self) -> ParserResult<Statement> {
+        self.advance();
+        self.function_yields = true;
+        if self.peek().typ == TokenType::Semicolon {
+            self.advance();
+            return Ok(yield_! {
+                value: None
+            });
+        }
+        let expr = self.parse_expression()?;
+        self.consume(TokenType::Semicolon, "Expected ; at the end of the statement")?;
+        Ok(yield_! {
+            value: Some(expr)
+        })
+    }
+
     pub fn parse_print_stmt(&mut self) -> ParserResult<Statement> {
         self.advance();
         let expr = self.parse_expression()?;
@@ -510,20 +488,21 @@ impl Parser{
     }
 
     pub fn parse_rel(&mut self) -> ParserResult<Expression> {
-        let left = self.parse_term()?;
+        let left = self.parse_range()?;
         let operator = match self.peek().typ {
             TokenType::Grater => Some(Operator::Greater),
             TokenType::Less => Some(Operator::Less),
             TokenType::GraterEqual => Some(Operator::Ge),
             TokenType::LessEqual => Some(Operator::Le),
+            TokenType::In => Some(Operator::In),
             _ => None
         };
-        if self.peek().typ == TokenType::GraterEqual || self.peek().typ == TokenType::Grater || self.peek().typ == TokenType::Less || self.peek().typ == TokenType::LessEqual {
+        if let Some(operator) = operator {
             self.advance();
-            let right = self.parse_term()?;
+            let right = self.parse_range()?;
             return Ok(binary! {
                 left,
-                operator: operator.unwrap(),
+                operator,
                 right,
             });
         }
@@ -531,6 +510,27 @@ impl Parser{
         Ok(left)
     }
 
+    pub fn parse_range(&mut self) -> ParserResult<Expression> {
+        let start = self.parse_term()?;
+        if self.peek().typ != TokenType::DoubleDot && self.peek().typ != TokenType::DoubleDotEqual {
+            return Ok(start);
+        }
+        let inclusive = self.advance().unwrap().typ == TokenType::DoubleDotEqual;
+        let stop = self.parse_term()?;
+        let mut step = None;
+        if self.peek().typ == TokenType::By {
+            self.advance();
+            step = Some(self.parse_term()?);
+        }
+
+        Ok(range! {
+            start,
+            stop,
+            step,
+            inclusive
+        })
+    }
+
     pub fn parse_term(&mut self) -> ParserResult<Expression> {
         let mut rez = self.parse_factor()?;
         while self.peek().typ == TokenType::Plus || self.peek().typ == TokenType::Minus {
@@ -577,6 +577,26 @@ impl Parser{
                 expr
             });
         }
+        if self.peek().typ == TokenType::Spawn {
+            let token = self.advance().unwrap();
+            let Expression::Call(call) = self.parse_call()? else {
+                return Err(ParseError {
+                    message: "Expected function call after spawn".to_string(),
+                    line: token.line
+                });
+            };
+            return Ok(spawn! {
+                callee: call.callee,
+                args: call.args
+            });
+        }
+        if self.peek().typ == TokenType::Await {
+            self.advance();
+            let expr = self.parse_unary()?;
+            return Ok(await_! {
+                expr
+            });
+        }
         let expr = self.parse_pow()?;
         Ok(expr)
     }
@@ -644,6 +664,14 @@ impl Parser{
                         property: member,
                     };
                 }
+            } else if self.peek().typ == TokenType::LeftSquareBracket {
+                self.advance();
+                let property = self.parse_expression()?;
+                self.consume(TokenType::RightSquareBracket, "Expected ] after index")?;
+                rez = member! {
+                    callee: rez,
+                    property,
+                };
             } else{
                 break;
             }
@@ -743,7 +771,7 @@ impl Parser{
             let expr = self.parse_expression()?;
 
             values.push(expr);
-            keys.push(number!(keys.len() as f32));
+            keys.push(number!(keys.len() as f64));
 
             if self.peek().typ == TokenType::Comma {
                 self.consume(TokenType::Comma, "This error shouldn't be displayed ever")?;
@@ -764,6 +792,8 @@ impl Parser{
             TokenType::String(str) => Ok(string!(str.to_string())),
             TokenType::Number(num) => Ok(number!(*num)),
             TokenType::Identifier(ident) => Ok(identifier!(ident.to_string())),
+            // `reverse` is only a keyword right after `loop`, elsewhere it names the std function
+            TokenType::Reverse => Ok(identifier!("reverse".to_string())),
             TokenType::LeftParen => self.parse_object(),
             TokenType::LeftSquareBracket => self.parse_array(),
             TokenType::LeftBrace => {
@@ -803,6 +833,7 @@ impl Parser{
     pub fn new(source: Vec<Token>) -> Self {
         Self {
             source: source.into_iter().peekable(),
+            function_yields: false,
         }
     }
 }
@@ -810,7 +841,7 @@ impl Parser{
 #[cfg(test)]
 mod tests {
     use pantera_ast::expression::{Expression, Operator};
-    use pantera_ast::statement::{DeclarationKind, GlobalStatement, Statement};
+    use pantera_ast::statement::{DeclarationKind, GlobalStatement, Statement, TypeAnnotation};
     use crate::lexer::Lexer;
     use crate::parser::{Parser, FUNCTION_NAME_SEPARATOR};
     
@@ -843,7 +874,7 @@ mod tests {
         let stmt = result.get(0).unwrap();
         if let GlobalStatement::Statement(Statement::Declaration(ref stmt)) = stmt {
             if let Some(Expression::Number(x)) = stmt.value {
-                assert_eq!(x, 3f32);
+                assert_eq!(x, 3f64);
             }
             assert_eq!(stmt.variable, "x");
             assert!(matches!(stmt.kind, DeclarationKind::Var));
@@ -851,6 +882,26 @@ mod tests {
         }
         assert!(false);
     }
+    #[test]
+    pub fn test_parse_typed_declaration() {
+        let result  = get_new_parser("var count: number = 0;");
+        assert_eq!(result.len(), 1);
+
+        if let GlobalStatement::Statement(Statement::Declaration(ref stmt)) = result.first().unwrap() {
+            assert_eq!(stmt.variable, "count");
+            assert_eq!(stmt.typ, Some(TypeAnnotation::Number));
+            assert!(matches!(stmt.value, Some(Expression::Number(_))));
+            return;
+        }
+        panic!("Unexpected statement");
+    }
+
+    #[test]
+    pub fn test_parse_unknown_type() {
+        let tokens = Lexer::new("var count: integer = 0;").scan_tokens().unwrap();
+        assert!(Parser::new(tokens).parse_program().is_err());
+    }
+
     #[test]
     pub fn test_if_statement() {
         let result = get_new_parser("if true {x;} s;");
@@ -875,11 +926,164 @@ mod tests {
         let result = get_new_parser("loop 1..3 {print it;}");
         assert_eq!(result.len(), 1);
         let stmt = result.get(0).unwrap();
-        if let GlobalStatement::Statement(Statement::Block(loop_stmt)) = stmt {
-            assert_eq!(loop_stmt.statements.len(), 2);
+        if let GlobalStatement::Statement(Statement::Iterate(loop_stmt)) = stmt {
+            assert_eq!(loop_stmt.alias, "it");
+            assert!(matches!(loop_stmt.iterable, Expression::Range(ref range) if range.step.is_none() && !range.inclusive));
+            assert!(matches!(loop_stmt.body, Statement::Block(_)));
+            return;
+        }
+        panic!("Unexpected statement");
+    }
+
+    #[test]
+    pub fn test_try_statement() {
+        let result = get_new_parser("try { print read_file(\"a\"); } catch err { print err; } try {} catch {}");
+        assert_eq!(result.len(), 2);
+        if let GlobalStatement::Statement(Statement::Try(ref stmt)) = result.first().unwrap() {
+            assert_eq!(stmt.alias, "err");
+            assert!(matches!(stmt.body, Statement::Block(_)));
+            assert!(matches!(stmt.handler, Statement::Block(_)));
+        } else {
+            panic!("Unexpected statement");
+        }
+        if let GlobalStatement::Statement(Statement::Try(ref stmt)) = result.get(1).unwrap() {
+            assert_eq!(stmt.alias, "error");
+            return;
+        }
+        panic!("Unexpected statement");
+    }
+
+    #[test]
+    pub fn test_try_without_catch() {
+        let tokens = Lexer::new("try { print 1; } print 2;").scan_tokens().unwrap();
+        assert!(Parser::new(tokens).parse_program().is_err());
+    }
+
+    #[test]
+    pub fn test_index_expression() {
+        let result = get_new_parser("a[0][\"key\"] = b[-1];");
+        assert_eq!(result.len(), 1);
+        if let GlobalStatement::Statement(Statement::Expression(ref wrapper)) = result.first().unwrap() {
+            let Expression::Assigment(ref assignment) = wrapper.expr else { panic!("Expected assignment"); };
+            let Expression::Member(ref target) = assignment.assignee else { panic!("Expected member target"); };
+            assert!(matches!(target.property, Expression::String(ref key) if key == "key"));
+            assert!(matches!(target.callee, Expression::Member(_)));
+            let Expression::Member(ref value) = assignment.value else { panic!("Expected member value"); };
+            assert!(matches!(value.property, Expression::Unary(_)));
+            return;
+        }
+        panic!("Unexpected statement");
+    }
+
+    #[test]
+    pub fn test_slice_expression() {
+        let result = get_new_parser("s[1..len(s)];");
+        assert_eq!(result.len(), 1);
+        if let GlobalStatement::Statement(Statement::Expression(ref wrapper)) = result.first().unwrap() {
+            let Expression::Member(ref slice) = wrapper.expr else { panic!("Expected member expression"); };
+            assert!(matches!(slice.property, Expression::Range(_)));
+            return;
+        }
+        panic!("Unexpected statement");
+    }
+
+    #[test]
+    pub fn test_unclosed_index() {
+        let tokens = Lexer::new("a[1;").scan_tokens().unwrap();
+        assert!(Parser::new(tokens).parse_program().is_err());
+    }
 
-            assert!(loop_stmt.statements.get(0).is_some_and(|x| matches!(*x, Statement::Declaration(_))));
-            assert!(loop_stmt.statements.get(1).is_some_and(|x| matches!(*x, Statement::Loop(_))));
+    #[test]
+    pub fn test_range_expression() {
+        let result = get_new_parser("var r = 0..=len(a) - 1 by 2;");
+        assert_eq!(result.len(), 1);
+        if let GlobalStatement::Statement(Statement::Declaration(ref stmt)) = result.first().unwrap() {
+            let Some(Expression::Range(ref range)) = stmt.value else { panic!("Expected a range"); };
+            assert!(range.inclusive);
+            assert!(matches!(range.stop, Expression::Binary(_)));
+            assert!(matches!(range.step, Some(Expression::Number(2f64))));
+            return;
+        }
+        panic!("Unexpected statement");
+    }
+
+    #[test]
+    pub fn test_in_expression() {
+        let result = get_new_parser("x in 0..10 and y;");
+        assert_eq!(result.len(), 1);
+        if let GlobalStatement::Statement(Statement::Expression(ref wrapper)) = result.first().unwrap() {
+            let Expression::Binary(ref and) = wrapper.expr else { panic!("Expected and expression"); };
+            let Expression::Binary(ref membership) = and.left else { panic!("Expected in expression"); };
+            assert!(matches!(membership.operator, Operator::In));
+            assert!(matches!(membership.right, Expression::Range(_)));
+            return;
+        }
+        panic!("Unexpected statement");
+    }
+
+    #[test]
+    pub fn test_loop_collection_statement() {
+        let result = get_new_parser("loop reverse items as item {print item;}");
+        assert_eq!(result.len(), 1);
+        let stmt = result.first().unwrap();
+        if let GlobalStatement::Statement(Statement::Iterate(iterate)) = stmt {
+            assert_eq!(iterate.alias, "item");
+            assert!(iterate.reverse);
+            assert!(matches!(iterate.iterable, Expression::Identifier(_)));
+            return;
+        }
+        panic!("Unexpected statement");
+    }
+
+    #[test]
+    pub fn test_reverse_call() {
+        let result = get_new_parser("loop reverse(items) {print it;} print reverse(items);");
+        assert_eq!(result.len(), 2);
+        let GlobalStatement::Statement(Statement::Iterate(iterate)) = result.first().unwrap() else { panic!("Expected a loop") };
+        assert!(iterate.reverse);
+        assert!(matches!(iterate.iterable, Expression::Group(_)));
+
+        let GlobalStatement::Statement(Statement::Print(print)) = result.get(1).unwrap() else { panic!("Expected a print") };
+        let Expression::Call(ref call) = print.expr else { panic!("Expected a call") };
+        assert!(matches!(call.callee, Expression::Identifier(ref name) if name == "reverse"));
+        assert_eq!(call.args.len(), 1);
+    }
+
+    #[test]
+    pub fn test_spawn_and_await() {
+        let result = get_new_parser("var task = spawn work(1, 2); print await task;");
+        assert_eq!(result.len(), 2);
+        if let GlobalStatement::Statement(Statement::Declaration(ref stmt)) = result.first().unwrap() {
+            assert!(matches!(stmt.value, Some(Expression::Spawn(ref call)) if call.args.len() == 2));
+        } else {
+            panic!("Unexpected statement");
+        }
+        if let GlobalStatement::Statement(Statement::Print(ref stmt)) = result.get(1).unwrap() {
+            assert!(matches!(stmt.expr, Expression::Await(ref value) if matches!(value.expr, Expression::Identifier(_))));
+            return;
+        }
+        panic!("Unexpected statement");
+    }
+
+    #[test]
+    pub fn test_spawn_without_call() {
+        let tokens = Lexer::new("spawn work;").scan_tokens().unwrap();
+        assert!(Parser::new(tokens).parse_program().is_err());
+    }
+
+    #[test]
+    pub fn test_generator_declaration() {
+        let result = get_new_parser("fun count(n) {yield n; yield;} fun plain {return 1;}");
+        assert_eq!(result.len(), 2);
+        if let GlobalStatement::FunctionDeclaration(ref func_dec) = result.first().unwrap() {
+            assert!(func_dec.is_generator);
+            let Statement::FunctionBody(ref body) = func_dec.body else { panic!("Not a function body"); };
+            assert!(body.statements.get(1).is_some_and(|x| matches!(x, Statement::Yield(stmt) if stmt.value.is_none())));
+        } else {
+            panic!("Unexpected statement");
+        }
+        if let GlobalStatement::FunctionDeclaration(ref func_dec) = result.get(1).unwrap() {
+            assert!(!func_dec.is_generator);
             return;
         }
         assert!(false);
@@ -927,6 +1131,23 @@ mod tests {
         }
     }
 
+    #[test]
+    pub fn test_typed_function_declaration() {
+        let result = get_new_parser("fun total(items: array, start) : number {return start;}");
+        assert_eq!(result.len(), 1);
+
+        if let GlobalStatement::FunctionDeclaration(ref func_dec) = result.first().unwrap() {
+            assert_eq!(func_dec.params.len(), 2);
+            assert!(func_dec.params.first().is_some_and(|x| x.typ == Some(TypeAnnotation::Array)));
+            assert!(func_dec.params.get(1).is_some_and(|x| x.typ.is_none()));
+
+            assert_eq!(func_dec.name.name, "total".to_string());
+            assert_eq!(func_dec.return_type, Some(TypeAnnotation::Number));
+        } else {
+            panic!("Unexpected statement");
+        }
+    }
+
     #[test]
     pub fn test_procedure() {
         let result = get_new_parser("fun check {return a > b;}");
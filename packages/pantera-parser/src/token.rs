@@ -18,6 +18,7 @@ pub enum TokenType {
     Dot,
     Possesive,
     DoubleDot,
+    DoubleDotEqual,
     Minus,
     Plus,
     Semicolon,
@@ -31,7 +32,7 @@ pub enum TokenType {
     LessEqual,
     Identifier(String),
     String(String),
-    Number(f32),
+    Number(f64),
     And,
     Or,
     If,
@@ -52,6 +53,13 @@ pub enum TokenType {
     Const,
     While,
     Break,
+    Yield,
+    Spawn,
+    Await,
+    In,
+    By,
+    Try,
+    Catch,
     Eof,
     Colon
 }
@@ -0,0 +1,67 @@
+use pantera_heap::heap::HeapManager;
+use pantera_heap::runtime::Runtime;
+use pantera_heap::stack::Stack;
+use pantera_heap::value::Value;
+
+/// Fails unless the builtin was called with `count` arguments. Builtins pop their
+/// arguments off the stack the script shares, so popping one that wasn't passed would take
+/// a value of the caller.
+pub(crate) fn expect_args(runtime: &dyn Runtime, func: &str, count: usize) -> Result<(), String> {
+    if runtime.arg_count() == count {
+        return Ok(());
+    }
+    match count {
+        1 => Err(format!("{func} expects 1 argument")),
+        _ => Err(format!("{func} expects {count} arguments"))
+    }
+}
+
+pub(crate) fn pop_number(stack: &mut Stack, func: &str) -> Result<f64, String> {
+    match stack.pop().unwrap() {
+        Value::Number(num) => Ok(num),
+        _ => Err(format!("{func} expects a number argument"))
+    }
+}
+
+pub(crate) fn pop_string(stack: &mut Stack, func: &str) -> Result<String, String> {
+    match stack.pop().unwrap() {
+        Value::String(str_ptr) => Ok(HeapManager::get_string(str_ptr)?),
+        _ => Err(format!("{func} expects a string argument"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pantera_heap::value::Value;
+    use crate::collections::contains;
+    use crate::convert::type_of;
+    use crate::len::len;
+    use crate::math;
+    use crate::string;
+    use crate::testing::TestRuntime;
+
+    #[test]
+    fn test_missing_arguments() {
+        let mut runtime = TestRuntime::new();
+        assert_eq!(runtime.call(string::upper, vec![]).unwrap_err(), "upper expects 1 argument");
+        assert_eq!(runtime.call(math::sqrt, vec![]).unwrap_err(), "sqrt expects 1 argument");
+        assert_eq!(runtime.call(type_of, vec![]).unwrap_err(), "type_of expects 1 argument");
+        assert_eq!(runtime.call(contains, vec![Value::Null]).unwrap_err(), "contains expects 2 arguments");
+    }
+
+    #[test]
+    fn test_missing_arguments_leave_the_stack_alone() {
+        let mut runtime = TestRuntime::new();
+        let string = runtime.string("caller");
+        runtime.stack.push(string.clone());
+        assert!(runtime.call(len, vec![]).is_err());
+        assert!(runtime.stack.pop().unwrap().is_same(&string));
+    }
+
+    #[test]
+    fn test_extra_arguments() {
+        let mut runtime = TestRuntime::new();
+        assert_eq!(runtime.call(math::random, vec![Value::Number(1f64)]).unwrap_err(), "random expects 0 arguments");
+        assert_eq!(runtime.call(math::min, vec![Value::Number(1f64), Value::Number(2f64), Value::Number(3f64)]).unwrap_err(), "min expects 2 arguments");
+    }
+}
@@ -1,22 +1,25 @@
-use std::cell::RefCell;
-use std::rc::Rc;
 use pantera_heap::heap::HeapManager;
-use pantera_heap::stack::Stack;
-use pantera_heap::value::Value;
+use pantera_heap::runtime::Runtime;
+use pantera_heap::value::{BuiltinResult, Value};
+use crate::args::expect_args;
 
-pub fn atoi(stack: &mut Stack, _heap_manager: Rc<RefCell<HeapManager>>) {
+pub fn atoi(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "atoi", 1)?;
+    let stack = runtime.stack();
     match stack.pop().unwrap() {
         Value::String(num_as_str) => {
-            let number = HeapManager::get_string(num_as_str);
-            match number.parse::<f32>() {
+            let number = HeapManager::get_string(num_as_str)?;
+            match number.parse::<f64>() {
                 Ok(num) => {
                     stack.push(Value::Number(num));
                 },
                 Err(_e) => {
-                    panic!("Argument is not a stringified number")
+                    return Err("Argument is not a stringified number".to_string())
                 }
             }
         },
-        _ => panic!("Argument is not a stringified number")
+        _ => return Err("Argument is not a stringified number".to_string())
     }
+
+    Ok(())
 }
\ No newline at end of file
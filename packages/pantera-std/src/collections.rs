@@ -0,0 +1,332 @@
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use pantera_heap::arena::Handle;
+use pantera_heap::array::Array;
+use pantera_heap::heap::{HeapError, HeapManager};
+use pantera_heap::range::RangeValue;
+use pantera_heap::runtime::Runtime;
+use pantera_heap::stack::Stack;
+use pantera_heap::value::{BuiltinResult, Value};
+use crate::args::expect_args;
+
+// Builtins that call back into the script leave their arguments on the stack, and push
+// every value they produce, until they're done calling. That keeps them rooted for the GC.
+
+enum Source {
+    Array(Handle<Array>),
+    Range(RangeValue)
+}
+
+/// The items of an array or a range. Array items are read from the array as they're reached,
+/// since a copy would keep the ones a callback replaces in the array, after the GC may have
+/// freed them. The array itself stays rooted as an argument of the builtin.
+struct Items {
+    source: Source,
+    index: usize
+}
+
+impl Iterator for Items {
+    type Item = Result<Value, HeapError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = match &self.source {
+            Source::Array(ptr) => HeapManager::get_array_item(*ptr, self.index).transpose(),
+            Source::Range(range) => range.get(self.index).map(|num| Ok(Value::Number(num)))
+        };
+        self.index += 1;
+        item
+    }
+}
+
+fn items(stack: &Stack, distance: usize, func: &str) -> Result<Items, String> {
+    let source = match stack.peek(distance) {
+        Some(Value::Array(ptr)) => Source::Array(*ptr),
+        Some(Value::Range(ptr)) => Source::Range(HeapManager::get_range(*ptr)?),
+        _ => return Err(format!("{func} expects an array or a range"))
+    };
+    Ok(Items { source, index: 0 })
+}
+
+fn function(stack: &Stack, distance: usize, func: &str) -> Result<Value, String> {
+    match stack.peek(distance) {
+        Some(callee @ Value::Function(_)) => Ok(callee.clone()),
+        _ => Err(format!("{func} expects a function argument"))
+    }
+}
+
+fn test(runtime: &mut dyn Runtime, callee: &Value, item: &Value, func: &str) -> Result<bool, String> {
+    match runtime.call(callee.clone(), vec![item.clone()])? {
+        Value::Bool(result) => Ok(result),
+        _ => Err(format!("The function passed to {func} must return a bool"))
+    }
+}
+
+/// Replaces the builtin's arguments and the `count` values it pushed above them with an
+/// array of those values
+fn finish_array(runtime: &mut dyn Runtime, args: usize, count: usize) {
+    let heap_manager = runtime.heap();
+    let stack = runtime.stack();
+    // popping gives the values last first, which is the order allocate_array takes
+    let values = (0..count).map(|_| stack.pop().unwrap()).collect();
+    (0..args).for_each(|_| { stack.pop(); });
+    let arr_ptr = heap_manager.borrow_mut().allocate_array(values);
+    stack.push(Value::Array(arr_ptr));
+}
+
+fn finish(runtime: &mut dyn Runtime, args: usize, result: Value) {
+    let stack = runtime.stack();
+    (0..args).for_each(|_| { stack.pop(); });
+    stack.push(result);
+}
+
+pub fn map(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "map", 2)?;
+    let callee = function(runtime.stack(), 0, "map")?;
+    let mut count = 0;
+    for item in items(runtime.stack(), 1, "map")? {
+        let item = item?;
+        let result = runtime.call(callee.clone(), vec![item])?;
+        runtime.stack().push(result);
+        count += 1;
+    }
+    finish_array(runtime, 2, count);
+    Ok(())
+}
+
+pub fn filter(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "filter", 2)?;
+    let callee = function(runtime.stack(), 0, "filter")?;
+    let items = items(runtime.stack(), 1, "filter")?;
+    let mut count = 0;
+    for item in items {
+        let item = item?;
+        if test(runtime, &callee, &item, "filter")? {
+            runtime.stack().push(item);
+            count += 1;
+        }
+    }
+    finish_array(runtime, 2, count);
+    Ok(())
+}
+
+/// `reduce(arr, fn, initial)`; without an initial value the first item is used
+pub fn reduce(runtime: &mut dyn Runtime) -> BuiltinResult {
+    let args = runtime.arg_count();
+    let (callee, mut items) = match args {
+        2 => (function(runtime.stack(), 0, "reduce")?, items(runtime.stack(), 1, "reduce")?),
+        3 => (function(runtime.stack(), 1, "reduce")?, items(runtime.stack(), 2, "reduce")?),
+        _ => return Err("reduce expects an array, a function and an optional initial value".to_string())
+    };
+    let initial = match args {
+        2 => items.next().transpose()?.ok_or("reduce of an empty array needs an initial value")?,
+        _ => runtime.stack().peek(0).unwrap().clone()
+    };
+
+    runtime.stack().push(initial);
+    for item in items {
+        let item = item?;
+        let accumulator = runtime.stack().pop().unwrap();
+        // the accumulator is an argument of the call, which keeps it rooted meanwhile
+        let result = runtime.call(callee.clone(), vec![accumulator, item])?;
+        runtime.stack().push(result);
+    }
+    let result = runtime.stack().pop().unwrap();
+    finish(runtime, args, result);
+    Ok(())
+}
+
+/// The first item the function accepts, or null
+pub fn find(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "find", 2)?;
+    let callee = function(runtime.stack(), 0, "find")?;
+    let items = items(runtime.stack(), 1, "find")?;
+    let mut found = Value::Null;
+    for item in items {
+        let item = item?;
+        if test(runtime, &callee, &item, "find")? {
+            found = item;
+            break;
+        }
+    }
+    finish(runtime, 2, found);
+    Ok(())
+}
+
+pub fn any(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "any", 2)?;
+    let callee = function(runtime.stack(), 0, "any")?;
+    let items = items(runtime.stack(), 1, "any")?;
+    let mut result = false;
+    for item in items {
+        let item = item?;
+        if test(runtime, &callee, &item, "any")? {
+            result = true;
+            break;
+        }
+    }
+    finish(runtime, 2, Value::Bool(result));
+    Ok(())
+}
+
+pub fn all(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "all", 2)?;
+    let callee = function(runtime.stack(), 0, "all")?;
+    let items = items(runtime.stack(), 1, "all")?;
+    let mut result = true;
+    for item in items {
+        let item = item?;
+        if !test(runtime, &callee, &item, "all")? {
+            result = false;
+            break;
+        }
+    }
+    finish(runtime, 2, Value::Bool(result));
+    Ok(())
+}
+
+/// Returns a sorted copy of an array. Numbers and strings sort in ascending order by
+/// default; `cmp(a, b)` can return a negative number to put `a` first, a positive one to
+/// put `b` first, or 0 to keep their order.
+pub fn sort(runtime: &mut dyn Runtime) -> BuiltinResult {
+    let args = runtime.arg_count();
+    let (callee, items) = match args {
+        1 => (None, items(runtime.stack(), 0, "sort")?),
+        2 => (Some(function(runtime.stack(), 0, "sort")?), items(runtime.stack(), 1, "sort")?),
+        _ => return Err("sort expects an array and an optional comparison function".to_string())
+    };
+
+    // the comparison function may replace items in the array, so they're rooted on the
+    // stack until the sorted copy replaces them
+    let items: Vec<Value> = items.collect::<Result<_, _>>()?;
+    items.iter().for_each(|item| runtime.stack().push(item.clone()));
+    let count = items.len();
+    let sorted = merge_sort(items, &mut |first, second| match &callee {
+        Some(callee) => match runtime.call(callee.clone(), vec![first.clone(), second.clone()])? {
+            Value::Number(num) => Ok(num.partial_cmp(&0f64).unwrap_or(Ordering::Equal)),
+            _ => Err("The comparison function of sort must return a number".to_string())
+        },
+        None => compare(first, second)
+    })?;
+
+    (0..count).for_each(|_| { runtime.stack().pop(); });
+    sorted.into_iter().for_each(|item| runtime.stack().push(item));
+    finish_array(runtime, args, count);
+    Ok(())
+}
+
+fn compare(first: &Value, second: &Value) -> Result<Ordering, String> {
+    match (first, second) {
+        (Value::Number(num1), Value::Number(num2)) => Ok(num1.partial_cmp(num2).unwrap_or(Ordering::Equal)),
+        (Value::String(str1), Value::String(str2)) => Ok(HeapManager::get_string(*str1)?.cmp(&HeapManager::get_string(*str2)?)),
+        _ => Err("sort can only order numbers or strings without a comparison function".to_string())
+    }
+}
+
+/// A stable merge sort that stops at the first failing comparison. The std sorts can't
+/// return errors and may panic on comparison functions that aren't a total order.
+fn merge_sort(mut items: Vec<Value>, compare: &mut impl FnMut(&Value, &Value) -> Result<Ordering, String>) -> Result<Vec<Value>, String> {
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+    let right = items.split_off(items.len() / 2);
+    let mut left = VecDeque::from(merge_sort(items, compare)?);
+    let mut right = VecDeque::from(merge_sort(right, compare)?);
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    while let (Some(first), Some(second)) = (left.front(), right.front()) {
+        if compare(first, second)? == Ordering::Greater {
+            merged.push(right.pop_front().unwrap());
+        } else {
+            merged.push(left.pop_front().unwrap());
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
+
+/// Reverses an array or a range into a new array, or a string into a new string
+pub fn reverse(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "reverse", 1)?;
+    let heap_manager = runtime.heap();
+    let stack = runtime.stack();
+    if let Some(Value::String(str_ptr)) = stack.peek(0) {
+        let reversed = HeapManager::get_string(*str_ptr)?.chars().rev().collect();
+        stack.pop();
+        stack.push(Value::String(heap_manager.borrow_mut().allocate_string(reversed)));
+        return Ok(());
+    }
+
+    // allocate_array takes the values last first, so the items as they are come out reversed
+    let values = items(stack, 0, "reverse")?.collect::<Result<_, _>>()?;
+    stack.pop();
+    let arr_ptr = heap_manager.borrow_mut().allocate_array(values);
+    stack.push(Value::Array(arr_ptr));
+    Ok(())
+}
+
+fn object_entries(stack: &mut Stack, func: &str) -> Result<Vec<(Value, Value)>, String> {
+    let Some(Value::Object(obj_ptr)) = stack.pop() else { return Err(format!("{func} expects an object")) };
+    let entries = (0..HeapManager::get_object_len(obj_ptr)?).map(|index| {
+        let (key, val) = HeapManager::get_object_entry(obj_ptr, index)?;
+        Ok((Value::String(key), val))
+    }).collect::<Result<_, HeapError>>()?;
+    Ok(entries)
+}
+
+pub fn keys(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "keys", 1)?;
+    let heap_manager = runtime.heap();
+    let stack = runtime.stack();
+    let values = object_entries(stack, "keys")?.into_iter().rev().map(|(key, _val)| key).collect();
+    let arr_ptr = heap_manager.borrow_mut().allocate_array(values);
+    stack.push(Value::Array(arr_ptr));
+    Ok(())
+}
+
+pub fn values(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "values", 1)?;
+    let heap_manager = runtime.heap();
+    let stack = runtime.stack();
+    let values = object_entries(stack, "values")?.into_iter().rev().map(|(_key, val)| val).collect();
+    let arr_ptr = heap_manager.borrow_mut().allocate_array(values);
+    stack.push(Value::Array(arr_ptr));
+    Ok(())
+}
+
+/// `[key, value]` pairs of an object, in the order a loop visits them
+pub fn entries(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "entries", 1)?;
+    let heap_manager = runtime.heap();
+    let stack = runtime.stack();
+    let mut heap_manager = heap_manager.borrow_mut();
+    let mut values = vec![];
+    for (key, val) in object_entries(stack, "entries")?.into_iter().rev() {
+        values.push(Value::Array(heap_manager.allocate_array(vec![val, key])));
+    }
+    let arr_ptr = heap_manager.allocate_array(values);
+    stack.push(Value::Array(arr_ptr));
+    Ok(())
+}
+
+/// Like `in`, with substring search for strings
+pub fn contains(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "contains", 2)?;
+    let stack = runtime.stack();
+    let value = stack.pop().unwrap();
+    let found = match stack.pop().unwrap() {
+        Value::Array(ptr) => HeapManager::get_array(ptr)?.iter().any(|item| item.is_same(&value)),
+        Value::Range(ptr) => matches!(value, Value::Number(num) if HeapManager::get_range(ptr)?.contains(num)),
+        Value::Object(ptr) => match value {
+            Value::String(key) => HeapManager::get_object(ptr)?.keys().any(|obj_key| HeapManager::compare_strings(*obj_key, key)),
+            _ => false
+        },
+        Value::String(ptr) => match value {
+            Value::String(part) => HeapManager::get_string(ptr)?.contains(&HeapManager::get_string(part)?),
+            _ => return Err("contains expects a string to search for in a string".to_string())
+        },
+        _ => return Err("contains expects an array, a range, an object or a string".to_string())
+    };
+    stack.push(Value::Bool(found));
+    Ok(())
+}
@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use pantera_heap::arena::Handle;
+use pantera_heap::array::Array;
+use pantera_heap::hash_table::HashTable;
+use pantera_heap::heap::{HeapError, HeapManager};
+use pantera_heap::runtime::Runtime;
+use pantera_heap::value::{BuiltinResult, Value};
+use crate::args::expect_args;
+
+pub fn type_of(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "type_of", 1)?;
+    let heap_manager = runtime.heap();
+    let stack = runtime.stack();
+    let name = match stack.pop().unwrap() {
+        Value::Number(_) => "number",
+        Value::Bool(_) => "bool",
+        Value::Null => "null",
+        Value::Function(_) => "function",
+        Value::String(_) => "string",
+        Value::Object(_) => "object",
+        Value::Array(_) => "array",
+        Value::Generator(_) => "generator",
+        Value::Task(_) => "task",
+        Value::Channel(_) => "channel",
+        Value::Range(_) => "range"
+    };
+    let str_ptr = heap_manager.borrow_mut().allocate_string(name.to_string());
+    stack.push(Value::String(str_ptr));
+    Ok(())
+}
+
+/// The value as `print` shows it
+pub fn to_string(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "to_string", 1)?;
+    let heap_manager = runtime.heap();
+    let stack = runtime.stack();
+    let value = stack.pop().unwrap();
+    if let Value::String(_) = value {
+        stack.push(value);
+        return Ok(());
+    }
+    let str_ptr = heap_manager.borrow_mut().allocate_string(value.to_string());
+    stack.push(Value::String(str_ptr));
+    Ok(())
+}
+
+/// Converts numeric strings and bools to numbers. Values that can't be converted, infinite
+/// and NaN strings included, are an error, unless a second argument is given, which is
+/// then returned instead.
+pub fn to_number(runtime: &mut dyn Runtime) -> BuiltinResult {
+    let args = runtime.arg_count();
+    let stack = runtime.stack();
+    let fallback = match args {
+        1 => None,
+        2 => stack.pop(),
+        _ => return Err("to_number expects a value and an optional fallback".to_string())
+    };
+    let value = stack.pop().unwrap();
+    let number = match &value {
+        Value::Number(num) => Some(*num),
+        Value::Bool(val) => Some(if *val { 1f64 } else { 0f64 }),
+        // Rust also parses "inf" and "NaN", which aren't numbers a script could write
+        Value::String(str_ptr) => HeapManager::get_string(*str_ptr)?.trim().parse::<f64>().ok().filter(|num| num.is_finite()),
+        _ => None
+    };
+
+    match (number, fallback) {
+        (Some(num), _) => stack.push(Value::Number(num)),
+        (None, Some(fallback)) => stack.push(fallback),
+        (None, None) => return Err(format!("Cannot convert {value} to a number"))
+    }
+    Ok(())
+}
+
+/// `false`, `null`, 0, NaN and the empty string are false, everything else is true
+pub fn to_bool(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "to_bool", 1)?;
+    let stack = runtime.stack();
+    let truthy = match stack.pop().unwrap() {
+        Value::Bool(val) => val,
+        Value::Null => false,
+        Value::Number(num) => num != 0f64 && !num.is_nan(),
+        Value::String(str_ptr) => !HeapManager::get_string(str_ptr)?.is_empty(),
+        _ => true
+    };
+    stack.push(Value::Bool(truthy));
+    Ok(())
+}
+
+pub fn is_null(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "is_null", 1)?;
+    let stack = runtime.stack();
+    let null = matches!(stack.pop().unwrap(), Value::Null);
+    stack.push(Value::Bool(null));
+    Ok(())
+}
+
+/// Compares arrays and objects by their contents, recursively, and everything else like `is`
+pub fn deep_equals(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "deep_equals", 2)?;
+    let stack = runtime.stack();
+    let second = stack.pop().unwrap();
+    let first = stack.pop().unwrap();
+    stack.push(Value::Bool(equals(&first, &second, &mut Comparing::default())?));
+    Ok(())
+}
+
+/// The pairs of containers being compared further up
+#[derive(Default)]
+struct Comparing {
+    arrays: Vec<(Handle<Array>, Handle<Array>)>,
+    objects: Vec<(Handle<HashTable>, Handle<HashTable>)>
+}
+
+/// `comparing` holds the pairs of containers being compared further up, so that cyclic
+/// structures are taken as equal when they repeat the same way
+fn equals(first: &Value, second: &Value, comparing: &mut Comparing) -> Result<bool, HeapError> {
+    match (first, second) {
+        (Value::Array(ptr1), Value::Array(ptr2)) if ptr1 == ptr2 || comparing.arrays.contains(&(*ptr1, *ptr2)) => Ok(true),
+        (Value::Object(ptr1), Value::Object(ptr2)) if ptr1 == ptr2 || comparing.objects.contains(&(*ptr1, *ptr2)) => Ok(true),
+        (Value::Array(ptr1), Value::Array(ptr2)) => {
+            let (items1, items2) = (HeapManager::get_array(*ptr1)?, HeapManager::get_array(*ptr2)?);
+            if items1.len() != items2.len() {
+                return Ok(false);
+            }
+            comparing.arrays.push((*ptr1, *ptr2));
+            let equal = all(items1.iter().zip(items2.iter()), |(item1, item2)| equals(item1, item2, comparing));
+            comparing.arrays.pop();
+            equal
+        },
+        (Value::Object(ptr1), Value::Object(ptr2)) => {
+            let (obj1, obj2) = (HeapManager::get_object(*ptr1)?, HeapManager::get_object(*ptr2)?);
+            if obj1.len() != obj2.len() {
+                return Ok(false);
+            }
+            comparing.objects.push((*ptr1, *ptr2));
+            // keys are interned, so the same key has the same pointer in both objects
+            let equal = all(obj1.iter(), |(key, val1)| match obj2.get(key) {
+                Some(val2) => equals(val1, val2, comparing),
+                None => Ok(false)
+            });
+            comparing.objects.pop();
+            equal
+        },
+        _ => Ok(first.is_same(second))
+    }
+}
+
+/// `Iterator::all` for tests that can fail
+fn all<T>(items: impl Iterator<Item = T>, mut test: impl FnMut(T) -> Result<bool, HeapError>) -> Result<bool, HeapError> {
+    for item in items {
+        if !test(item)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Copies arrays and objects with everything they contain. Containers that appear more
+/// than once, cycles included, are copied once and shared the same way in the copy.
+pub fn clone(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "clone", 1)?;
+    let heap_manager = runtime.heap();
+    let stack = runtime.stack();
+    let value = stack.pop().unwrap();
+    let copy = copy(&value, &mut heap_manager.borrow_mut(), &mut Copies::default())?;
+    stack.push(copy);
+    Ok(())
+}
+
+/// The copies made so far, by original
+#[derive(Default)]
+struct Copies {
+    arrays: HashMap<Handle<Array>, Handle<Array>>,
+    objects: HashMap<Handle<HashTable>, Handle<HashTable>>
+}
+
+fn copy(value: &Value, heap_manager: &mut HeapManager, copies: &mut Copies) -> Result<Value, HeapError> {
+    match value {
+        Value::Array(ptr) if copies.arrays.contains_key(ptr) => Ok(Value::Array(copies.arrays[ptr])),
+        Value::Object(ptr) if copies.objects.contains_key(ptr) => Ok(Value::Object(copies.objects[ptr])),
+        Value::Array(ptr) => {
+            let items = HeapManager::get_array(*ptr)?;
+            let arr_ptr = heap_manager.allocate_array(vec![Value::Null; items.len()]);
+            copies.arrays.insert(*ptr, arr_ptr);
+            for (index, item) in items.iter().enumerate() {
+                let item = copy(item, heap_manager, copies)?;
+                heap_manager.set_property_for_array_num(arr_ptr, index, item)?;
+            }
+            Ok(Value::Array(arr_ptr))
+        },
+        Value::Object(ptr) => {
+            let obj = HeapManager::get_object(*ptr)?;
+            let obj_ptr = heap_manager.allocate_object(obj.keys().map(|key| (*key, Value::Null)).collect());
+            copies.objects.insert(*ptr, obj_ptr);
+            for (key, val) in obj.iter() {
+                let val = copy(val, heap_manager, copies)?;
+                heap_manager.set_property_for_object(obj_ptr, *key, val)?;
+            }
+            Ok(Value::Object(obj_ptr))
+        },
+        _ => Ok(value.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestRuntime;
+
+    fn string_of(value: Value) -> String {
+        match value {
+            Value::String(str_ptr) => HeapManager::get_string(str_ptr).unwrap(),
+            value => panic!("{value} is not a string")
+        }
+    }
+
+    fn truth(value: Value) -> bool {
+        matches!(value, Value::Bool(true))
+    }
+
+    #[test]
+    fn test_type_of() {
+        let mut runtime = TestRuntime::new();
+        let string = runtime.string("a");
+        let array = Value::Array(runtime.heap_manager.borrow_mut().allocate_array(vec![]));
+        for (value, name) in [(Value::Number(1f64), "number"), (Value::Bool(true), "bool"), (Value::Null, "null"), (string, "string"), (array, "array")] {
+            assert_eq!(string_of(runtime.call(type_of, vec![value]).unwrap()), name);
+        }
+    }
+
+    #[test]
+    fn test_to_number() {
+        let mut runtime = TestRuntime::new();
+        let numeric = runtime.string(" 2.5 ");
+        assert!(matches!(runtime.call(to_number, vec![numeric]).unwrap(), Value::Number(num) if num == 2.5));
+        assert!(matches!(runtime.call(to_number, vec![Value::Bool(true)]).unwrap(), Value::Number(num) if num == 1f64));
+
+        let word = runtime.string("abc");
+        assert_eq!(runtime.call(to_number, vec![word.clone()]).unwrap_err(), "Cannot convert abc to a number");
+        assert!(matches!(runtime.call(to_number, vec![word, Value::Number(-1f64)]).unwrap(), Value::Number(num) if num == -1f64));
+        assert!(runtime.call(to_number, vec![Value::Null]).is_err());
+    }
+
+    #[test]
+    fn test_to_number_rejects_infinity_and_nan() {
+        let mut runtime = TestRuntime::new();
+        for source in ["inf", "-inf", "infinity", "NaN", "nan"] {
+            let string = runtime.string(source);
+            assert!(runtime.call(to_number, vec![string.clone()]).is_err(), "{source}");
+            assert!(matches!(runtime.call(to_number, vec![string, Value::Null]).unwrap(), Value::Null), "{source}");
+        }
+    }
+
+    #[test]
+    fn test_to_string_and_to_bool() {
+        let mut runtime = TestRuntime::new();
+        assert_eq!(string_of(runtime.call(to_string, vec![Value::Number(1.5)]).unwrap()), "1.5");
+        assert_eq!(string_of(runtime.call(to_string, vec![Value::Null]).unwrap()), "null");
+
+        let (empty, text) = (runtime.string(""), runtime.string("no"));
+        for (value, expected) in [(Value::Number(0f64), false), (Value::Number(f64::NAN), false), (Value::Null, false), (empty, false), (text, true), (Value::Number(-1f64), true)] {
+            assert_eq!(truth(runtime.call(to_bool, vec![value]).unwrap()), expected);
+        }
+        assert!(truth(runtime.call(is_null, vec![Value::Null]).unwrap()));
+        assert!(!truth(runtime.call(is_null, vec![Value::Bool(false)]).unwrap()));
+    }
+
+    #[test]
+    fn test_deep_equals_and_clone() {
+        let mut runtime = TestRuntime::new();
+        let (key, inner) = {
+            let mut heap_manager = runtime.heap_manager.borrow_mut();
+            (heap_manager.allocate_string("key".to_string()), heap_manager.allocate_array(vec![Value::Number(2f64), Value::Number(1f64)]))
+        };
+        let obj_ptr = runtime.heap_manager.borrow_mut().allocate_object(HashMap::from([(key, Value::Array(inner))]));
+        // a cycle through the object
+        runtime.heap_manager.borrow_mut().set_property_for_array_num(inner, 1, Value::Object(obj_ptr)).unwrap();
+
+        let copy = runtime.call(clone, vec![Value::Object(obj_ptr)]).unwrap();
+        let Value::Object(copy_ptr) = copy else { panic!("clone of an object should be an object") };
+        assert_ne!(copy_ptr, obj_ptr);
+        assert!(truth(runtime.call(deep_equals, vec![Value::Object(obj_ptr), copy.clone()]).unwrap()));
+
+        runtime.heap_manager.borrow_mut().set_property_for_array_num(inner, 0, Value::Number(3f64)).unwrap();
+        assert!(!truth(runtime.call(deep_equals, vec![Value::Object(obj_ptr), copy]).unwrap()));
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::HashMap;
+use pantera_heap::stack::Stack;
+use pantera_heap::runtime::Runtime;
+use pantera_heap::value::{BuiltinResult, Value};
+use crate::args::{expect_args, pop_number, pop_string};
+
+const MILLIS_PER_DAY: i64 = 24 * 60 * 60 * 1000;
+const WEEKDAYS: [&str; 7] = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+const MONTHS: [&str; 12] = ["January", "February", "March", "April", "May", "June", "July", "August", "September", "October", "November", "December"];
+
+/// A UTC date and time, broken down from milliseconds since the Unix epoch
+struct DateTime {
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    millisecond: u32
+}
+
+impl DateTime {
+    fn from_timestamp(timestamp: i64) -> Self {
+        let days = timestamp.div_euclid(MILLIS_PER_DAY);
+        let millis = timestamp.rem_euclid(MILLIS_PER_DAY);
+        let (year, month, day) = civil_from_days(days);
+
+        Self {
+            year,
+            month,
+            day,
+            hour: (millis / 3_600_000) as u32,
+            minute: (millis / 60_000 % 60) as u32,
+            second: (millis / 1000 % 60) as u32,
+            millisecond: (millis % 1000) as u32
+        }
+    }
+
+    fn timestamp(&self) -> i64 {
+        days_from_civil(self.year, self.month, self.day) * MILLIS_PER_DAY
+            + self.hour as i64 * 3_600_000 + self.minute as i64 * 60_000 + self.second as i64 * 1000 + self.millisecond as i64
+    }
+
+    /// 0 is Monday
+    fn weekday(&self) -> usize {
+        (days_from_civil(self.year, self.month, self.day) + 3).rem_euclid(7) as usize
+    }
+
+    fn day_of_year(&self) -> i64 {
+        days_from_civil(self.year, self.month, self.day) - days_from_civil(self.year, 1, 1) + 1
+    }
+}
+
+// Conversions between days since the epoch and proleptic Gregorian dates, after
+// http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31
+    }
+}
+
+fn pop_timestamp(stack: &mut Stack, func: &str) -> Result<i64, String> {
+    let timestamp = pop_number(stack, func)?;
+    if !timestamp.is_finite() {
+        return Err(format!("{func} expects a timestamp in milliseconds"));
+    }
+    Ok(timestamp.floor() as i64)
+}
+
+/// Formats a timestamp, in UTC, with strftime-like directives: `%Y %y %m %d %H %M %S %L %j %a %A %b %B %%`
+pub fn format_date(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "format_date", 2)?;
+    let heap_manager = runtime.heap();
+    let stack = runtime.stack();
+    let format = pop_string(stack, "format_date")?;
+    let date = DateTime::from_timestamp(pop_timestamp(stack, "format_date")?);
+
+    let mut out = String::new();
+    let mut chars = format.chars();
+    while let Some(chr) = chars.next() {
+        if chr != '%' {
+            out.push(chr);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out.push_str(&format!("{:04}", date.year)),
+            Some('y') => out.push_str(&format!("{:02}", date.year.rem_euclid(100))),
+            Some('m') => out.push_str(&format!("{:02}", date.month)),
+            Some('d') => out.push_str(&format!("{:02}", date.day)),
+            Some('H') => out.push_str(&format!("{:02}", date.hour)),
+            Some('M') => out.push_str(&format!("{:02}", date.minute)),
+            Some('S') => out.push_str(&format!("{:02}", date.second)),
+            Some('L') => out.push_str(&format!("{:03}", date.millisecond)),
+            Some('j') => out.push_str(&format!("{:03}", date.day_of_year())),
+            Some('a') => out.push_str(&WEEKDAYS[date.weekday()][..3]),
+            Some('A') => out.push_str(WEEKDAYS[date.weekday()]),
+            Some('b') => out.push_str(&MONTHS[date.month as usize - 1][..3]),
+            Some('B') => out.push_str(MONTHS[date.month as usize - 1]),
+            Some('%') => out.push('%'),
+            Some(other) => return Err(format!("Unknown date directive %{other}")),
+            None => return Err("Date format cannot end with %".to_string())
+        }
+    }
+
+    let str_ptr = heap_manager.borrow_mut().allocate_string(out);
+    stack.push(Value::String(str_ptr));
+    Ok(())
+}
+
+/// Parses a UTC date written in `format`, which accepts the numeric directives of
+/// `format_date` (`%Y %m %d %H %M %S %L`), and returns its timestamp
+pub fn parse_date(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "parse_date", 2)?;
+    let stack = runtime.stack();
+    let format = pop_string(stack, "parse_date")?;
+    let string = pop_string(stack, "parse_date")?;
+    let mismatch = || format!("{string} doesn't match the date format {format}");
+
+    let mut date = DateTime { year: 1970, month: 1, day: 1, hour: 0, minute: 0, second: 0, millisecond: 0 };
+    let mut input = string.chars().peekable();
+    let mut chars = format.chars();
+    while let Some(chr) = chars.next() {
+        if chr != '%' {
+            if input.next() != Some(chr) {
+                return Err(mismatch());
+            }
+            continue;
+        }
+
+        let directive = chars.next().ok_or("Date format cannot end with %")?;
+        if directive == '%' {
+            if input.next() != Some('%') {
+                return Err(mismatch());
+            }
+            continue;
+        }
+        let max_digits = match directive {
+            'Y' => 4,
+            'L' => 3,
+            'm' | 'd' | 'H' | 'M' | 'S' => 2,
+            other => return Err(format!("Unsupported date directive %{other} for parsing"))
+        };
+        let mut digits = String::new();
+        while digits.len() < max_digits {
+            match input.next_if(|chr| chr.is_ascii_digit()) {
+                Some(digit) => digits.push(digit),
+                None => break
+            }
+        }
+        let value: u32 = digits.parse().map_err(|_| mismatch())?;
+        match directive {
+            'Y' => date.year = value as i64,
+            'm' => date.month = value,
+            'd' => date.day = value,
+            'H' => date.hour = value,
+            'M' => date.minute = value,
+            'S' => date.second = value,
+            _ => date.millisecond = value
+        }
+    }
+
+    if input.next().is_some() {
+        return Err(mismatch());
+    }
+    if !(1..=12).contains(&date.month) || date.day < 1 || date.day > days_in_month(date.year, date.month)
+        || date.hour > 23 || date.minute > 59 || date.second > 59 {
+        return Err(format!("{string} is not a valid date"));
+    }
+
+    stack.push(Value::Number(date.timestamp() as f64));
+    Ok(())
+}
+
+/// Breaks a timestamp into an object with its UTC calendar fields
+pub fn date_parts(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "date_parts", 1)?;
+    let heap_manager = runtime.heap();
+    let stack = runtime.stack();
+    let date = DateTime::from_timestamp(pop_timestamp(stack, "date_parts")?);
+    let fields = [
+        ("year", date.year as f64),
+        ("month", date.month as f64),
+        ("day", date.day as f64),
+        ("hour", date.hour as f64),
+        ("minute", date.minute as f64),
+        ("second", date.second as f64),
+        ("millisecond", date.millisecond as f64),
+        ("weekday", (date.weekday() + 1) as f64)
+    ];
+
+    let mut heap_manager = heap_manager.borrow_mut();
+    let mut obj = HashMap::new();
+    for (name, value) in fields {
+        obj.insert(heap_manager.allocate_string(name.to_string()), Value::Number(value));
+    }
+    let obj_ptr = heap_manager.allocate_object(obj);
+    stack.push(Value::Object(obj_ptr));
+    Ok(())
+}
+
+pub fn add_days(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "add_days", 2)?;
+    let stack = runtime.stack();
+    let days = pop_number(stack, "add_days")?;
+    let timestamp = pop_number(stack, "add_days")?;
+    stack.push(Value::Number(timestamp + days * MILLIS_PER_DAY as f64));
+    Ok(())
+}
+
+/// Moves a timestamp by whole calendar months, clamping the day to the length of the
+/// target month (January 31st plus one month is the last day of February)
+pub fn add_months(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "add_months", 2)?;
+    let stack = runtime.stack();
+    let months = pop_number(stack, "add_months")?;
+    if months.fract() != 0f64 {
+        return Err("add_months expects a whole number of months".to_string());
+    }
+    let mut date = DateTime::from_timestamp(pop_timestamp(stack, "add_months")?);
+
+    let month_index = date.year * 12 + date.month as i64 - 1 + months as i64;
+    date.year = month_index.div_euclid(12);
+    date.month = (month_index.rem_euclid(12) + 1) as u32;
+    date.day = date.day.min(days_in_month(date.year, date.month));
+
+    stack.push(Value::Number(date.timestamp() as f64));
+    Ok(())
+}
+
+/// Whole days from the first timestamp to the second one, negative when the second is earlier
+pub fn days_between(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "days_between", 2)?;
+    let stack = runtime.stack();
+    let to = pop_timestamp(stack, "days_between")?;
+    let from = pop_timestamp(stack, "days_between")?;
+    stack.push(Value::Number(((to - from) / MILLIS_PER_DAY) as f64));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pantera_heap::heap::HeapManager;
+    use super::*;
+    use crate::testing::TestRuntime;
+
+    /// 2024-02-29 12:34:56.789 UTC, a Thursday
+    const LEAP_DAY: f64 = 1_709_210_096_789f64;
+
+    fn format(runtime: &mut TestRuntime, timestamp: f64, template: &str) -> Result<String, String> {
+        let template = runtime.string(template);
+        match runtime.call(format_date, vec![Value::Number(timestamp), template])? {
+            Value::String(str_ptr) => Ok(HeapManager::get_string(str_ptr).unwrap()),
+            value => panic!("{value} is not a string")
+        }
+    }
+
+    fn parse(runtime: &mut TestRuntime, string: &str, template: &str) -> Result<f64, String> {
+        let (string, template) = (runtime.string(string), runtime.string(template));
+        match runtime.call(parse_date, vec![string, template])? {
+            Value::Number(num) => Ok(num),
+            value => panic!("{value} is not a number")
+        }
+    }
+
+    fn number(result: Result<Value, String>) -> f64 {
+        match result.unwrap() {
+            Value::Number(num) => num,
+            value => panic!("{value} is not a number")
+        }
+    }
+
+    #[test]
+    fn test_format_date() {
+        let mut runtime = TestRuntime::new();
+        assert_eq!(format(&mut runtime, 0f64, "%Y-%m-%d %H:%M:%S.%L %j %a %A %b %B %%").unwrap(), "1970-01-01 00:00:00.000 001 Thu Thursday Jan January %");
+        assert_eq!(format(&mut runtime, LEAP_DAY, "%d/%m/%y %H:%M:%S.%L, day %j, %A").unwrap(), "29/02/24 12:34:56.789, day 060, Thursday");
+        assert_eq!(format(&mut runtime, -1f64, "%Y-%m-%d %H:%M:%S.%L").unwrap(), "1969-12-31 23:59:59.999");
+        assert_eq!(format(&mut runtime, 0f64, "%Q").unwrap_err(), "Unknown date directive %Q");
+        assert_eq!(format(&mut runtime, 0f64, "%").unwrap_err(), "Date format cannot end with %");
+        assert_eq!(format(&mut runtime, f64::NAN, "%Y").unwrap_err(), "format_date expects a timestamp in milliseconds");
+    }
+
+    #[test]
+    fn test_parse_date() {
+        let mut runtime = TestRuntime::new();
+        assert_eq!(parse(&mut runtime, "2024-02-29 12:34:56.789", "%Y-%m-%d %H:%M:%S.%L").unwrap(), LEAP_DAY);
+        assert_eq!(parse(&mut runtime, "1/1/1970", "%d/%m/%Y").unwrap(), 0f64);
+        assert_eq!(parse(&mut runtime, "2023-02-29", "%Y-%m-%d").unwrap_err(), "2023-02-29 is not a valid date");
+        assert_eq!(parse(&mut runtime, "2024-02-29T", "%Y-%m-%d").unwrap_err(), "2024-02-29T doesn't match the date format %Y-%m-%d");
+        assert_eq!(parse(&mut runtime, "Thursday", "%A").unwrap_err(), "Unsupported date directive %A for parsing");
+    }
+
+    #[test]
+    fn test_date_parts() {
+        let mut runtime = TestRuntime::new();
+        let Value::Object(obj_ptr) = runtime.call(date_parts, vec![Value::Number(LEAP_DAY)]).unwrap() else { panic!("Not an object") };
+        let parts: HashMap<String, f64> = HeapManager::get_object(obj_ptr).unwrap().iter().map(|(key, value)| match &**value {
+            Value::Number(num) => (HeapManager::get_string(*key).unwrap(), *num),
+            value => panic!("{value} is not a number")
+        }).collect();
+        let expected = [("year", 2024f64), ("month", 2f64), ("day", 29f64), ("hour", 12f64), ("minute", 34f64), ("second", 56f64), ("millisecond", 789f64), ("weekday", 4f64)];
+        assert_eq!(parts, expected.into_iter().map(|(name, value)| (name.to_string(), value)).collect());
+    }
+
+    #[test]
+    fn test_date_arithmetic() {
+        let mut runtime = TestRuntime::new();
+        let end_of_january = parse(&mut runtime, "2024-01-31", "%Y-%m-%d").unwrap();
+        let end_of_february = parse(&mut runtime, "2024-02-29", "%Y-%m-%d").unwrap();
+        assert_eq!(number(runtime.call(add_months, vec![Value::Number(end_of_january), Value::Number(1f64)])), end_of_february);
+        assert_eq!(number(runtime.call(add_months, vec![Value::Number(end_of_february), Value::Number(-13f64)])), parse(&mut runtime, "2023-01-29", "%Y-%m-%d").unwrap());
+        assert_eq!(runtime.call(add_months, vec![Value::Number(0f64), Value::Number(1.5)]).unwrap_err(), "add_months expects a whole number of months");
+
+        assert_eq!(number(runtime.call(add_days, vec![Value::Number(end_of_january), Value::Number(29f64)])), end_of_february);
+        assert_eq!(number(runtime.call(days_between, vec![Value::Number(end_of_january), Value::Number(end_of_february)])), 29f64);
+        assert_eq!(number(runtime.call(days_between, vec![Value::Number(end_of_february), Value::Number(end_of_january)])), -29f64);
+    }
+}
@@ -0,0 +1,152 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::rc::Rc;
+use pantera_heap::heap::HeapManager;
+use pantera_heap::stack::Stack;
+use pantera_heap::runtime::Runtime;
+use pantera_heap::value::{BuiltinResult, FunctionValue, Value};
+use crate::args::{expect_args, pop_string};
+
+fn push_string(stack: &mut Stack, heap_manager: &Rc<RefCell<HeapManager>>, string: String) {
+    let str_ptr = heap_manager.borrow_mut().allocate_string(string);
+    stack.push(Value::String(str_ptr));
+}
+
+pub fn read_file(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "read_file", 1)?;
+    let heap_manager = runtime.heap();
+    let stack = runtime.stack();
+    let path = pop_string(stack, "read_file")?;
+    let content = fs::read_to_string(&path).map_err(|err| format!("Couldn't read {path}: {err}"))?;
+    push_string(stack, &heap_manager, content);
+    Ok(())
+}
+
+pub fn write_file(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "write_file", 2)?;
+    let stack = runtime.stack();
+    let content = pop_string(stack, "write_file")?;
+    let path = pop_string(stack, "write_file")?;
+    fs::write(&path, content).map_err(|err| format!("Couldn't write {path}: {err}"))?;
+    stack.push(Value::Null);
+    Ok(())
+}
+
+pub fn append_file(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "append_file", 2)?;
+    let stack = runtime.stack();
+    let content = pop_string(stack, "append_file")?;
+    let path = pop_string(stack, "append_file")?;
+    OpenOptions::new().create(true).append(true).open(&path)
+        .and_then(|mut file| file.write_all(content.as_bytes()))
+        .map_err(|err| format!("Couldn't append to {path}: {err}"))?;
+    stack.push(Value::Null);
+    Ok(())
+}
+
+/// Returns an iterator object over the lines of a file. The file is read whole up front,
+/// so nothing stays open if the loop ends early.
+pub fn read_lines(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "read_lines", 1)?;
+    let heap_manager = runtime.heap();
+    let stack = runtime.stack();
+    let path = pop_string(stack, "read_lines")?;
+    let content = fs::read_to_string(&path).map_err(|err| format!("Couldn't read {path}: {err}"))?;
+
+    let mut heap_manager = heap_manager.borrow_mut();
+    let lines = content.lines().rev().map(|line| Value::String(heap_manager.allocate_string(line.to_string()))).collect();
+    let mut iterator = HashMap::new();
+    iterator.insert(heap_manager.allocate_string("lines".to_string()), Value::Array(heap_manager.allocate_array(lines)));
+    iterator.insert(heap_manager.allocate_string("position".to_string()), Value::Number(0f64));
+    iterator.insert(heap_manager.allocate_string("next".to_string()), Value::Function(FunctionValue::Builtin(next_line)));
+    let obj_ptr = heap_manager.allocate_object(iterator);
+    stack.push(Value::Object(obj_ptr));
+    Ok(())
+}
+
+/// `next` of the `read_lines` iterator; it receives the iterator object itself
+fn next_line(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "next", 1)?;
+    let heap_manager = runtime.heap();
+    let stack = runtime.stack();
+    let Value::Object(obj_ptr) = stack.pop().unwrap() else { return Err("next expects a line iterator".to_string()) };
+    let mut heap_manager = heap_manager.borrow_mut();
+    let (lines_key, position_key) = (heap_manager.allocate_string("lines".to_string()), heap_manager.allocate_string("position".to_string()));
+    let (Value::Array(arr_ptr), Value::Number(position)) = (heap_manager.get_property_from_object(obj_ptr, &lines_key)?, heap_manager.get_property_from_object(obj_ptr, &position_key)?) else {
+        return Err("next expects a line iterator".to_string());
+    };
+
+    let line = heap_manager.get_property_from_array_num(arr_ptr, position as usize)?;
+    if !matches!(line, Value::Null) {
+        heap_manager.set_property_for_object(obj_ptr, position_key, Value::Number(position + 1f64))?;
+    }
+    stack.push(line);
+    Ok(())
+}
+
+pub fn exists(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "exists", 1)?;
+    let stack = runtime.stack();
+    let path = pop_string(stack, "exists")?;
+    stack.push(Value::Bool(Path::new(&path).exists()));
+    Ok(())
+}
+
+/// Names of the entries in a directory, sorted
+pub fn list_dir(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "list_dir", 1)?;
+    let heap_manager = runtime.heap();
+    let stack = runtime.stack();
+    let path = pop_string(stack, "list_dir")?;
+    let mut names = fs::read_dir(&path)
+        .and_then(|entries| entries.map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().to_string())).collect::<Result<Vec<String>, _>>())
+        .map_err(|err| format!("Couldn't list {path}: {err}"))?;
+    names.sort();
+
+    let mut values = vec![];
+    for name in names.into_iter().rev() {
+        values.push(Value::String(heap_manager.borrow_mut().allocate_string(name)));
+    }
+    let arr_ptr = heap_manager.borrow_mut().allocate_array(values);
+    stack.push(Value::Array(arr_ptr));
+    Ok(())
+}
+
+/// Removes a file or an empty directory
+pub fn remove(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "remove", 1)?;
+    let stack = runtime.stack();
+    let path = pop_string(stack, "remove")?;
+    let result = if Path::new(&path).is_dir() { fs::remove_dir(&path) } else { fs::remove_file(&path) };
+    result.map_err(|err| format!("Couldn't remove {path}: {err}"))?;
+    stack.push(Value::Null);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestRuntime;
+
+    #[test]
+    fn test_read_lines() {
+        let path = std::env::temp_dir().join(format!("pantera-read-lines-{}.txt", std::process::id()));
+        fs::write(&path, "one\r\ntwo\n").unwrap();
+        let mut runtime = TestRuntime::new();
+        let path_value = runtime.string(path.to_str().unwrap());
+        let iterator = runtime.call(read_lines, vec![path_value]).unwrap();
+        // the file is read up front, so it can go away before the loop ends
+        fs::remove_file(&path).unwrap();
+
+        let mut lines = vec![];
+        while let Value::String(str_ptr) = runtime.call(next_line, vec![iterator.clone()]).unwrap() {
+            lines.push(HeapManager::get_string(str_ptr).unwrap());
+        }
+        assert_eq!(lines, vec!["one", "two"]);
+        assert!(matches!(runtime.call(next_line, vec![iterator]).unwrap(), Value::Null));
+    }
+}
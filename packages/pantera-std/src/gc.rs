@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+use pantera_heap::heap::{HeapError, HeapManager};
+use pantera_heap::runtime::Runtime;
+use pantera_heap::value::{BuiltinResult, Value};
+use crate::args::expect_args;
+
+/// Builds an object with `fields` in order
+fn object(heap_manager: &mut HeapManager, fields: Vec<(&str, Value)>) -> Result<Value, HeapError> {
+    let obj_ptr = heap_manager.allocate_object(HashMap::new());
+    for (name, value) in fields {
+        let key = heap_manager.allocate_string(name.to_string());
+        heap_manager.set_property_for_object(obj_ptr, key, value)?;
+    }
+
+    Ok(Value::Object(obj_ptr))
+}
+
+pub fn gc_stats(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "gc_stats", 0)?;
+    let heap_manager = runtime.heap();
+    let mut heap_manager = heap_manager.borrow_mut();
+    let stats = heap_manager.stats();
+    let number = |count: usize| Value::Number(count as f64);
+
+    let allocations = &stats.allocations;
+    let allocations = object(&mut heap_manager, vec![
+        ("strings", number(allocations.strings)),
+        ("objects", number(allocations.objects)),
+        ("arrays", number(allocations.arrays)),
+        ("generators", number(allocations.generators)),
+        ("ranges", number(allocations.ranges))
+    ])?;
+    let stats = object(&mut heap_manager, vec![
+        ("allocations", allocations),
+        ("allocated_bytes", number(stats.allocated_bytes)),
+        ("live_bytes", number(stats.live_bytes)),
+        ("peak_bytes", number(stats.peak_bytes)),
+        ("heap_size", number(stats.heap_size)),
+        ("minor_collections", number(stats.minor_collections)),
+        ("major_collections", number(stats.major_collections)),
+        ("reclaimed_bytes", number(stats.reclaimed_bytes)),
+        ("pauses", number(stats.pauses)),
+        ("total_pause_ms", Value::Number(stats.total_pause.as_secs_f64() * 1000f64)),
+        ("max_pause_ms", Value::Number(stats.max_pause.as_secs_f64() * 1000f64))
+    ])?;
+    drop(heap_manager);
+
+    runtime.stack().push(stats);
+    Ok(())
+}
@@ -0,0 +1,392 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::rc::Rc;
+use std::str::CharIndices;
+use pantera_heap::arena::Handle;
+use pantera_heap::array::Array;
+use pantera_heap::hash_table::HashTable;
+use pantera_heap::heap::HeapManager;
+use pantera_heap::runtime::Runtime;
+use pantera_heap::value::{BuiltinResult, Value};
+use crate::args::{expect_args, pop_number, pop_string};
+
+pub fn json_parse(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "json_parse", 1)?;
+    let heap_manager = runtime.heap();
+    let stack = runtime.stack();
+    let source = pop_string(stack, "json_parse")?;
+    let mut parser = JsonParser { chars: source.char_indices().peekable(), heap_manager: &heap_manager };
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+    if let Some((pos, _chr)) = parser.chars.peek() {
+        return Err(format!("Invalid JSON at position {pos}: unexpected trailing characters"));
+    }
+
+    stack.push(value);
+    Ok(())
+}
+
+/// Serializes `value` as JSON. An `indent` of 0 produces compact output, otherwise nested
+/// values are put on their own lines, indented by that many spaces per level.
+pub fn json_stringify(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "json_stringify", 2)?;
+    let heap_manager = runtime.heap();
+    let stack = runtime.stack();
+    let indent = pop_number(stack, "json_stringify")?.max(0f64) as usize;
+    let value = stack.pop().unwrap();
+
+    let mut out = String::new();
+    JsonWriter { indent, arrays: vec![], objects: vec![] }.write(&value, 0, &mut out)?;
+    let str_ptr = heap_manager.borrow_mut().allocate_string(out);
+    stack.push(Value::String(str_ptr));
+    Ok(())
+}
+
+struct JsonParser<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    heap_manager: &'a Rc<RefCell<HeapManager>>
+}
+
+impl JsonParser<'_> {
+    fn error(&mut self, message: &str) -> String {
+        match self.chars.peek() {
+            Some((pos, _chr)) => format!("Invalid JSON at position {pos}: {message}"),
+            None => format!("Invalid JSON: {message} at the end of the input")
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|(_pos, chr)| matches!(chr, ' ' | '\t' | '\n' | '\r')).is_some() {}
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        if self.chars.next_if(|(_pos, chr)| *chr == expected).is_none() {
+            return Err(self.error(&format!("expected '{expected}'")));
+        }
+        Ok(())
+    }
+
+    fn expect_keyword(&mut self, keyword: &str, value: Value) -> Result<Value, String> {
+        for expected in keyword.chars() {
+            if self.chars.next_if(|(_pos, chr)| *chr == expected).is_none() {
+                return Err(self.error(&format!("expected {keyword}")));
+            }
+        }
+        Ok(value)
+    }
+
+    fn parse_value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.chars.peek().map(|(_pos, chr)| *chr) {
+            Some('{') => self.parse_object(),
+            Some('[') => self.parse_array(),
+            Some('"') => {
+                let string = self.parse_string()?;
+                Ok(Value::String(self.heap_manager.borrow_mut().allocate_string(string)))
+            },
+            Some('t') => self.expect_keyword("true", Value::Bool(true)),
+            Some('f') => self.expect_keyword("false", Value::Bool(false)),
+            Some('n') => self.expect_keyword("null", Value::Null),
+            Some('-' | '0'..='9') => self.parse_number(),
+            _ => Err(self.error("expected a value"))
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut obj: HashMap<Handle<String>, Value> = HashMap::new();
+        self.skip_whitespace();
+        if self.chars.next_if(|(_pos, chr)| *chr == '}').is_none() {
+            loop {
+                self.skip_whitespace();
+                if !matches!(self.chars.peek(), Some((_pos, '"'))) {
+                    return Err(self.error("expected a string key"));
+                }
+                let key = self.parse_string()?;
+                self.skip_whitespace();
+                self.expect(':')?;
+                let value = self.parse_value()?;
+                obj.insert(self.heap_manager.borrow_mut().allocate_string(key), value);
+
+                self.skip_whitespace();
+                if self.chars.next_if(|(_pos, chr)| *chr == ',').is_none() {
+                    self.expect('}')?;
+                    break;
+                }
+            }
+        }
+
+        Ok(Value::Object(self.heap_manager.borrow_mut().allocate_object(obj)))
+    }
+
+    fn parse_array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut values = vec![];
+        self.skip_whitespace();
+        if self.chars.next_if(|(_pos, chr)| *chr == ']').is_none() {
+            loop {
+                values.push(self.parse_value()?);
+                self.skip_whitespace();
+                if self.chars.next_if(|(_pos, chr)| *chr == ',').is_none() {
+                    self.expect(']')?;
+                    break;
+                }
+            }
+        }
+
+        values.reverse();
+        Ok(Value::Array(self.heap_manager.borrow_mut().allocate_array(values)))
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            let Some((_pos, chr)) = self.chars.next() else {
+                return Err(self.error("unterminated string"));
+            };
+            match chr {
+                '"' => return Ok(string),
+                '\\' => {
+                    let Some((_pos, escaped)) = self.chars.next() else {
+                        return Err(self.error("unterminated string"));
+                    };
+                    match escaped {
+                        '"' | '\\' | '/' => string.push(escaped),
+                        'b' => string.push('\u{8}'),
+                        'f' => string.push('\u{c}'),
+                        'n' => string.push('\n'),
+                        'r' => string.push('\r'),
+                        't' => string.push('\t'),
+                        'u' => string.push(self.parse_unicode_escape()?),
+                        _ => return Err(self.error(&format!("invalid escape '\\{escaped}'")))
+                    }
+                },
+                '\u{0}'..='\u{1f}' => return Err(self.error("control character in string")),
+                _ => string.push(chr)
+            }
+        }
+    }
+
+    fn parse_hex(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let Some(digit) = self.chars.peek().and_then(|(_pos, chr)| chr.to_digit(16)) else {
+                return Err(self.error("invalid unicode escape"));
+            };
+            self.chars.next();
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn parse_unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.parse_hex()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            // a surrogate pair, as JSON escapes characters outside the BMP
+            self.expect('\\')?;
+            self.expect('u')?;
+            let low = self.parse_hex()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(self.error("invalid surrogate pair"));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+
+        char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape"))
+    }
+
+    fn parse_number(&mut self) -> Result<Value, String> {
+        let start = self.chars.peek().map(|(pos, _chr)| *pos).unwrap_or_default();
+        let mut number = String::new();
+        while let Some((_pos, chr)) = self.chars.next_if(|(_pos, chr)| matches!(chr, '-' | '+' | '.' | 'e' | 'E' | '0'..='9')) {
+            number.push(chr);
+        }
+
+        let digits = number.strip_prefix('-').unwrap_or(&number);
+        let leading_zero = digits.len() > 1 && digits.starts_with('0') && digits.as_bytes()[1].is_ascii_digit();
+        match number.parse::<f64>() {
+            Ok(num) if !leading_zero && !digits.starts_with('.') && !number.ends_with('.') => Ok(Value::Number(num)),
+            _ => Err(format!("Invalid JSON at position {start}: invalid number {number}"))
+        }
+    }
+}
+
+struct JsonWriter {
+    indent: usize,
+    /// Arrays and objects currently being written, to detect cycles
+    arrays: Vec<Handle<Array>>,
+    objects: Vec<Handle<HashTable>>
+}
+
+impl JsonWriter {
+    fn write(&mut self, value: &Value, depth: usize, out: &mut String) -> Result<(), String> {
+        match value {
+            Value::Null => out.push_str("null"),
+            Value::Bool(val) => out.push_str(&val.to_string()),
+            Value::Number(num) => {
+                if !num.is_finite() {
+                    return Err(format!("Cannot stringify {num} as JSON"));
+                }
+                out.push_str(&num.to_string());
+            },
+            Value::String(ptr) => Self::write_string(&HeapManager::get_string(*ptr)?, out),
+            Value::Array(ptr) => {
+                Self::enter(&mut self.arrays, *ptr)?;
+                let values = HeapManager::get_array(*ptr)?;
+                self.write_sequence('[', ']', values.len(), depth, out, |writer, index, out| writer.write(&values[index], depth + 1, out))?;
+                self.arrays.pop();
+            },
+            Value::Range(ptr) => {
+                let range = HeapManager::get_range(*ptr)?;
+                self.write_sequence('[', ']', range.len(), depth, out, |writer, index, out| writer.write(&Value::Number(range.get(index).unwrap()), depth + 1, out))?;
+            },
+            Value::Object(ptr) => {
+                Self::enter(&mut self.objects, *ptr)?;
+                let len = HeapManager::get_object_len(*ptr)?;
+                let separator = if self.indent > 0 { ": " } else { ":" };
+                self.write_sequence('{', '}', len, depth, out, |writer, index, out| {
+                    let (key, val) = HeapManager::get_object_entry(*ptr, index)?;
+                    Self::write_string(&HeapManager::get_string(key)?, out);
+                    out.push_str(separator);
+                    writer.write(&val, depth + 1, out)
+                })?;
+                self.objects.pop();
+            },
+            Value::Function(_) => return Err("Cannot stringify a function as JSON".to_string()),
+            Value::Generator(_) => return Err("Cannot stringify a generator as JSON".to_string()),
+            Value::Task(_) => return Err("Cannot stringify a task as JSON".to_string()),
+            Value::Channel(_) => return Err("Cannot stringify a channel as JSON".to_string())
+        }
+
+        Ok(())
+    }
+
+    fn enter<T>(path: &mut Vec<Handle<T>>, ptr: Handle<T>) -> Result<(), String> {
+        if path.contains(&ptr) {
+            return Err("Cannot stringify a cyclic structure as JSON".to_string());
+        }
+        path.push(ptr);
+        Ok(())
+    }
+
+    fn write_sequence(&mut self, open: char, close: char, len: usize, depth: usize, out: &mut String, mut write_item: impl FnMut(&mut Self, usize, &mut String) -> Result<(), String>) -> Result<(), String> {
+        out.push(open);
+        for index in 0..len {
+            if index > 0 {
+                out.push(',');
+            }
+            self.new_line(depth + 1, out);
+            write_item(self, index, out)?;
+        }
+        if len > 0 {
+            self.new_line(depth, out);
+        }
+        out.push(close);
+        Ok(())
+    }
+
+    fn new_line(&self, depth: usize, out: &mut String) {
+        if self.indent > 0 {
+            out.push('\n');
+            out.push_str(&" ".repeat(self.indent * depth));
+        }
+    }
+
+    fn write_string(string: &str, out: &mut String) {
+        out.push('"');
+        for chr in string.chars() {
+            match chr {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                '\u{8}' => out.push_str("\\b"),
+                '\u{c}' => out.push_str("\\f"),
+                '\u{0}'..='\u{1f}' => out.push_str(&format!("\\u{:04x}", chr as u32)),
+                _ => out.push(chr)
+            }
+        }
+        out.push('"');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestRuntime;
+
+    fn parse(runtime: &mut TestRuntime, source: &str) -> Result<Value, String> {
+        let source = runtime.string(source);
+        runtime.call(json_parse, vec![source])
+    }
+
+    fn stringify(runtime: &mut TestRuntime, value: Value, indent: f64) -> Result<String, String> {
+        match runtime.call(json_stringify, vec![value, Value::Number(indent)])? {
+            Value::String(str_ptr) => Ok(HeapManager::get_string(str_ptr).unwrap()),
+            value => panic!("json_stringify returned {value}")
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut runtime = TestRuntime::new();
+        for source in ["null", "true", "-1.5", "1000000", "\"text\"", "[]", "{}", "[1,[2,[3]],{\"a\":null}]", "{\"nested\":{\"list\":[true,false]}}"] {
+            let value = parse(&mut runtime, source).unwrap();
+            assert_eq!(stringify(&mut runtime, value, 0f64).unwrap(), source);
+        }
+    }
+
+    #[test]
+    fn test_parse_whitespace_and_escapes() {
+        let mut runtime = TestRuntime::new();
+        let value = parse(&mut runtime, " [ \"a\\n\\\"b\\\"\" , \"\\u00e9\\ud83d\\ude00\" , 2e3 ] ").unwrap();
+        let Value::Array(arr_ptr) = value else { panic!("expected an array") };
+        let items = HeapManager::get_array(arr_ptr).unwrap().iter().map(|item| item.to_string()).collect::<Vec<String>>();
+        assert_eq!(items, vec!["a\n\"b\"", "é😀", "2000"]);
+    }
+
+    #[test]
+    fn test_stringify_indent_and_escapes() {
+        let mut runtime = TestRuntime::new();
+        let value = parse(&mut runtime, "{\"list\":[1,\"tab\\there\"],\"empty\":[]}").unwrap();
+        let pretty = stringify(&mut runtime, value, 2f64).unwrap();
+        let list = "\"list\": [\n    1,\n    \"tab\\there\"\n  ]";
+        assert!(pretty.starts_with("{\n  ") && pretty.ends_with("\n}"));
+        assert!(pretty.contains(list) && pretty.contains("\"empty\": []"), "{pretty}");
+    }
+
+    #[test]
+    fn test_invalid_json() {
+        let mut runtime = TestRuntime::new();
+        for (source, error) in [
+            ("", "expected a value at the end of the input"),
+            ("[1, 2", "expected ']'"),
+            ("{1: 2}", "expected a string key"),
+            ("\"open", "unterminated string"),
+            ("\"\\x\"", "invalid escape"),
+            ("01", "invalid number 01"),
+            ("1.", "invalid number 1."),
+            ("nul", "expected null"),
+            ("[1] 2", "unexpected trailing characters")
+        ] {
+            let err = parse(&mut runtime, source).unwrap_err();
+            assert!(err.contains(error), "{source}: {err}");
+        }
+    }
+
+    #[test]
+    fn test_unstringifiable_values() {
+        let mut runtime = TestRuntime::new();
+        assert!(stringify(&mut runtime, Value::Number(f64::NAN), 0f64).unwrap_err().contains("Cannot stringify"));
+        assert!(stringify(&mut runtime, Value::Number(f64::INFINITY), 0f64).is_err());
+
+        let arr_ptr = runtime.heap_manager.borrow_mut().allocate_array(vec![Value::Null]);
+        runtime.heap_manager.borrow_mut().set_property_for_array_num(arr_ptr, 0, Value::Array(arr_ptr)).unwrap();
+        assert!(stringify(&mut runtime, Value::Array(arr_ptr), 0f64).is_err());
+    }
+}
@@ -1,20 +1,27 @@
-use std::cell::RefCell;
-use std::rc::Rc;
-use pantera_heap::stack::Stack;
-use pantera_heap::value::Value;
-use pantera_heap::array::Array;
-use pantera_heap::hash_table::HashTable;
+use pantera_heap::runtime::Runtime;
+use pantera_heap::value::{BuiltinResult, Value};
+use crate::args::expect_args;
 use pantera_heap::heap::HeapManager;
 
-pub fn len(stack: &mut Stack, _heap_manager: Rc<RefCell<HeapManager>>) {
+pub fn len(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "len", 1)?;
+    let stack = runtime.stack();
     let collection = stack.pop().unwrap();
     match collection {
-        Value::Array(arr) => unsafe {
-            stack.push(Value::Number(Array::from(arr).get_count() as f32));
+        Value::Array(arr) => {
+            stack.push(Value::Number(HeapManager::get_array_len(arr)? as f64));
         },
-        Value::Object(obj) => unsafe {
-            stack.push(Value::Number(HashTable::from(obj).get_count() as f32));
+        Value::Object(obj) => {
+            stack.push(Value::Number(HeapManager::get_object_len(obj)? as f64));
         },
-        _ => panic!("Object is not a collection to have a length")
+        Value::String(str_ptr) => {
+            stack.push(Value::Number(HeapManager::get_string(str_ptr)?.chars().count() as f64));
+        },
+        Value::Range(range) => {
+            stack.push(Value::Number(HeapManager::get_range(range)?.len() as f64));
+        },
+        _ => return Err("Object is not a collection to have a length".to_string())
     }
+
+    Ok(())
 }
\ No newline at end of file
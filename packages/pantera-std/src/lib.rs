@@ -1,26 +1,39 @@
-pub mod sleep;
 pub mod len;
-mod input;
 mod atoi;
-mod internal_iterate_get;
+mod args;
+mod math;
+mod string;
+mod fs;
+mod json;
+mod process;
+mod date;
+mod regex;
+mod collections;
+mod convert;
+mod output;
+mod gc;
+#[cfg(test)]
+mod testing;
 
-use std::cell::RefCell;
 use std::collections::HashMap;
-use std::rc::Rc;
-use pantera_heap::heap::HeapManager;
-use pantera_heap::stack::Stack;
-use pantera_heap::value::{FunctionValue, Value};
+use std::fmt::{Display, Formatter};
+pub use crate::process::set_args;
+use pantera_heap::value::{FunctionValue, SchedulerCall, Value};
 use crate::atoi::atoi;
-use crate::internal_iterate_get::internal_iterable_get;
-use crate::input::input;
+use crate::convert::{clone, deep_equals, is_null, to_bool, to_number, to_string, type_of};
+use crate::collections::{all, any, contains, entries, filter, find, keys, map, reduce, reverse, sort, values};
+use crate::gc::gc_stats;
+use crate::date::{add_days, add_months, date_parts, days_between, format_date, parse_date};
+use crate::json::{json_parse, json_stringify};
 use crate::len::len;
-use crate::sleep::sleep;
+use crate::output::{eprint, format, write};
+use crate::process::env;
 
 pub fn init_vm_globals() -> HashMap<u16, Value> {
     let mut globals = HashMap::new();
 
     for (ind, g) in STD_LIB.iter().enumerate() {
-        globals.insert(ind as u16, Value::Function(FunctionValue::Builtin(g.func)));
+        globals.insert(ind as u16, g.value.clone());
     }
 
     globals
@@ -36,32 +49,116 @@ pub fn init_compiler_globals() -> HashMap<String, u16> {
     globals
 }
 
+/// What a std entry can reach outside the script. Embedders choose the capabilities a
+/// script gets, and the compiler rejects references to entries that need any other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// Console input and output beyond `print`
+    Io,
+    Fs,
+    /// Reading the clock and sleeping
+    Time,
+    /// Environment variables and script arguments
+    Env,
+    /// Ending the process
+    Process
+}
+
+impl Capability {
+    pub const ALL: [Capability; 5] = [Self::Io, Self::Fs, Self::Time, Self::Env, Self::Process];
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Io => "io",
+            Self::Fs => "fs",
+            Self::Time => "time",
+            Self::Env => "env",
+            Self::Process => "process"
+        })
+    }
+}
+
+/// The std entries a script cannot reference with only the `allowed` capabilities,
+/// with the capability each one needs
+pub fn restricted_globals(allowed: &[Capability]) -> HashMap<String, Capability> {
+    STD_LIB.iter()
+        .filter_map(|g| g.capability.filter(|capability| !allowed.contains(capability)).map(|capability| (g.name.to_string(), capability)))
+        .collect()
+}
+
+/// Whether `name` refers to a std lib module, such as `math`. Module members are
+/// registered as `module.member` and resolved by the compiler from `module's member`.
+pub fn is_std_module(name: &str) -> bool {
+    STD_LIB.iter().any(|g| g.name.split_once('.').is_some_and(|(module, _member)| module == name))
+}
+
 struct StdLibEntry {
     name: &'static str,
-    func: fn(&mut Stack, Rc<RefCell<HeapManager>>)
+    value: Value,
+    capability: Option<Capability>
 }
 
 impl StdLibEntry {
-    const fn new(name: &'static str, func: fn(&mut Stack, Rc<RefCell<HeapManager>>)) -> Self {
+    const fn new(name: &'static str, value: Value, capability: Option<Capability>) -> Self {
         Self {
-            name, func
+            name, value, capability
         }
     }
 }
 
+macro_rules! capability {
+    () => { None };
+    ($capability:ident) => { Some(Capability::$capability) };
+}
+
 macro_rules! generate_std_lib {
-    ($($func:ident),*) => {
-        const STD_LIB: [StdLibEntry; generate_std_lib!(@count $($func),*)] = [
+    ($($func:ident $([$func_capability:ident])?),*;
+     $($scheduled:ident => $call:ident $([$scheduled_capability:ident])?),*;
+     $($runtime:ident $([$runtime_capability:ident])?),*;
+     $($module:ident::{$($member:ident $([$member_capability:ident])?),*; $($constant:ident = $value:expr),*})*) => {
+        const STD_LIB: &[StdLibEntry] = &[
             $(
-                StdLibEntry::new(stringify!($func), $func),
+                StdLibEntry::new(stringify!($func), Value::Function(FunctionValue::Builtin($func)), capability!($($func_capability)?)),
+            )*
+            $(
+                StdLibEntry::new(stringify!($scheduled), Value::Function(FunctionValue::Scheduler(SchedulerCall::$call)), capability!($($scheduled_capability)?)),
+            )*
+            $(
+                StdLibEntry::new(stringify!($runtime), Value::Null, capability!($($runtime_capability)?)),
+            )*
+            $(
+                $(
+                    StdLibEntry::new(concat!(stringify!($module), ".", stringify!($member)), Value::Function(FunctionValue::Builtin($module::$member)), capability!($($member_capability)?)),
+                )*
+                $(
+                    StdLibEntry::new(concat!(stringify!($module), ".", stringify!($constant)), Value::Number($value), None),
+                )*
             )*
         ];
     };
-    (@count $($t:tt),*) => {
-        <[()]>::len(&[$(generate_std_lib!(@sub $t)),*])
-    };
-
-    (@sub $t:tt) => { () };
 }
 
-generate_std_lib!(len, sleep, input, atoi, internal_iterable_get);
\ No newline at end of file
+// Entries tagged with a [Capability] are only available to scripts granted it
+generate_std_lib!(
+    len, atoi, json_parse, json_stringify, env [Env], format_date, parse_date, date_parts, add_days, add_months, days_between,
+    sort, map, filter, reduce, find, any, all, reverse, keys, values, entries, contains,
+    type_of, to_string, to_number, to_bool, is_null, deep_equals, clone, format, write [Io], eprint [Io], gc_stats;
+    sleep => Sleep [Time], input => Input [Io], channel => Channel, send => Send, receive => Receive, exit => Exit [Process],
+    now => Now [Time], clock => Clock [Time];
+    args [Env];
+    math::{
+        sqrt, abs, floor, ceil, round, min, max, sin, cos, tan, asin, acos, atan, atan2, log, log2, log10, exp, seed, random, random_int;
+        pi = std::f64::consts::PI, tau = std::f64::consts::TAU, e = std::f64::consts::E
+    }
+    string::{
+        split, join, trim, upper, lower, contains, starts_with, ends_with, replace, find, substring, repeat, code, char;
+    }
+    fs::{
+        read_file [Fs], write_file [Fs], append_file [Fs], read_lines [Fs], exists [Fs], list_dir [Fs], remove [Fs];
+    }
+    regex::{
+        matches, find_all, captures, replace_all;
+    }
+);
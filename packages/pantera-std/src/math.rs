@@ -0,0 +1,186 @@
+use std::cell::Cell;
+use std::time::{SystemTime, UNIX_EPOCH};
+use pantera_heap::runtime::Runtime;
+use pantera_heap::value::{BuiltinResult, Value};
+use crate::args::{expect_args, pop_number};
+
+thread_local! {
+    static RANDOM_STATE: Cell<u64> = Cell::new(SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_nanos() as u64).unwrap_or(0));
+}
+
+macro_rules! unary_math_functions {
+    ($($func:ident => $op:expr),*) => {
+        $(
+            pub fn $func(runtime: &mut dyn Runtime) -> BuiltinResult {
+                expect_args(runtime, stringify!($func), 1)?;
+                let stack = runtime.stack();
+                let num = pop_number(stack, stringify!($func))?;
+                let op: fn(f64) -> f64 = $op;
+                stack.push(Value::Number(op(num)));
+                Ok(())
+            }
+        )*
+    };
+}
+
+unary_math_functions!(
+    sqrt => f64::sqrt,
+    abs => f64::abs,
+    floor => f64::floor,
+    ceil => f64::ceil,
+    round => f64::round,
+    sin => f64::sin,
+    cos => f64::cos,
+    tan => f64::tan,
+    asin => f64::asin,
+    acos => f64::acos,
+    atan => f64::atan,
+    log => f64::ln,
+    log2 => f64::log2,
+    log10 => f64::log10,
+    exp => f64::exp
+);
+
+pub fn min(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "min", 2)?;
+    let stack = runtime.stack();
+    let second = pop_number(stack, "min")?;
+    let first = pop_number(stack, "min")?;
+    stack.push(Value::Number(first.min(second)));
+    Ok(())
+}
+
+pub fn max(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "max", 2)?;
+    let stack = runtime.stack();
+    let second = pop_number(stack, "max")?;
+    let first = pop_number(stack, "max")?;
+    stack.push(Value::Number(first.max(second)));
+    Ok(())
+}
+
+pub fn atan2(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "atan2", 2)?;
+    let stack = runtime.stack();
+    let x = pop_number(stack, "atan2")?;
+    let y = pop_number(stack, "atan2")?;
+    stack.push(Value::Number(y.atan2(x)));
+    Ok(())
+}
+
+/// splitmix64, so a given seed always produces the same sequence on every platform
+fn next_random() -> u64 {
+    RANDOM_STATE.with(|state| {
+        let next = state.get().wrapping_add(0x9E3779B97F4A7C15);
+        state.set(next);
+
+        let mut z = next;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    })
+}
+
+fn next_unit() -> f64 {
+    (next_random() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+pub fn seed(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "seed", 1)?;
+    let stack = runtime.stack();
+    let seed = pop_number(stack, "seed")?;
+    RANDOM_STATE.with(|state| state.set(seed as i64 as u64));
+    stack.push(Value::Null);
+    Ok(())
+}
+
+pub fn random(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "random", 0)?;
+    let stack = runtime.stack();
+    stack.push(Value::Number(next_unit()));
+    Ok(())
+}
+
+/// Returns an integer between `low` and `high`, both inclusive
+pub fn random_int(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "random_int", 2)?;
+    let stack = runtime.stack();
+    let high = pop_number(stack, "random_int")?.floor() as i64;
+    let low = pop_number(stack, "random_int")?.ceil() as i64;
+    if high < low {
+        return Err("random_int expects the lower bound to be at most the upper bound".to_string());
+    }
+
+    let span = (high - low + 1) as f64;
+    stack.push(Value::Number((low + (next_unit() * span) as i64) as f64));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestRuntime;
+
+    fn number(value: Value) -> f64 {
+        match value {
+            Value::Number(num) => num,
+            value => panic!("{value} is not a number")
+        }
+    }
+
+    fn call(runtime: &mut TestRuntime, func: fn(&mut dyn Runtime) -> BuiltinResult, args: &[f64]) -> f64 {
+        number(runtime.call(func, args.iter().map(|arg| Value::Number(*arg)).collect()).unwrap())
+    }
+
+    #[test]
+    fn test_unary_functions() {
+        let mut runtime = TestRuntime::new();
+        assert_eq!(call(&mut runtime, sqrt, &[9f64]), 3f64);
+        assert_eq!(call(&mut runtime, abs, &[-2.5]), 2.5);
+        assert_eq!(call(&mut runtime, floor, &[-1.5]), -2f64);
+        assert_eq!(call(&mut runtime, ceil, &[1.2]), 2f64);
+        assert_eq!(call(&mut runtime, round, &[2.5]), 3f64);
+        assert_eq!(call(&mut runtime, log2, &[8f64]), 3f64);
+        assert!(call(&mut runtime, sqrt, &[-1f64]).is_nan());
+    }
+
+    #[test]
+    fn test_binary_functions_take_arguments_in_order() {
+        let mut runtime = TestRuntime::new();
+        assert_eq!(call(&mut runtime, min, &[3f64, -1f64]), -1f64);
+        assert_eq!(call(&mut runtime, max, &[3f64, -1f64]), 3f64);
+        assert_eq!(call(&mut runtime, atan2, &[1f64, 0f64]), std::f64::consts::FRAC_PI_2);
+    }
+
+    #[test]
+    fn test_non_numbers_are_rejected() {
+        let mut runtime = TestRuntime::new();
+        let string = runtime.string("9");
+        assert_eq!(runtime.call(sqrt, vec![string]).unwrap_err(), "sqrt expects a number argument");
+        assert_eq!(runtime.call(max, vec![Value::Null, Value::Number(1f64)]).unwrap_err(), "max expects a number argument");
+    }
+
+    #[test]
+    fn test_seeded_random_repeats() {
+        let mut runtime = TestRuntime::new();
+        runtime.call(seed, vec![Value::Number(42f64)]).unwrap();
+        let first = (0..5).map(|_| call(&mut runtime, random, &[])).collect::<Vec<f64>>();
+        runtime.call(seed, vec![Value::Number(42f64)]).unwrap();
+        let second = (0..5).map(|_| call(&mut runtime, random, &[])).collect::<Vec<f64>>();
+
+        assert_eq!(first, second);
+        assert!(first.iter().all(|num| (0f64..1f64).contains(num)));
+    }
+
+    #[test]
+    fn test_random_int_bounds() {
+        let mut runtime = TestRuntime::new();
+        runtime.call(seed, vec![Value::Number(7f64)]).unwrap();
+        let rolls = (0..200).map(|_| call(&mut runtime, random_int, &[1f64, 6f64])).collect::<Vec<f64>>();
+
+        assert!(rolls.iter().all(|roll| (1f64..=6f64).contains(roll) && roll.fract() == 0f64));
+        assert!(rolls.contains(&1f64) && rolls.contains(&6f64));
+        assert_eq!(call(&mut runtime, random_int, &[3f64, 3f64]), 3f64);
+        assert!(runtime.call(random_int, vec![Value::Number(2f64), Value::Number(1f64)]).is_err());
+    }
+}
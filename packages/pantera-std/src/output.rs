@@ -0,0 +1,229 @@
+use pantera_heap::heap::{HeapError, HeapManager};
+use pantera_heap::runtime::Runtime;
+use pantera_heap::value::{BuiltinResult, Value};
+use crate::args::{expect_args, pop_string};
+
+/// `format(template, ...values)` replaces each `{}` in the template with the next value.
+/// Placeholders can pick a value by position (`{0}`) and take a spec after a colon:
+/// `[[fill]align][0][width][.precision]`, with `<`, `>` and `^` aligning left, right and
+/// center. Precision is the number of decimals for numbers and the maximum length for
+/// anything else. `{{` and `}}` are literal braces. Every value must be used.
+pub fn format(runtime: &mut dyn Runtime) -> BuiltinResult {
+    let args = runtime.arg_count();
+    if args == 0 {
+        return Err("format expects a template".to_string());
+    }
+    let heap_manager = runtime.heap();
+    let stack = runtime.stack();
+    let mut values = (1..args).map(|_| stack.pop().unwrap()).collect::<Vec<Value>>();
+    values.reverse();
+    let template = pop_string(stack, "format")?;
+
+    let mut out = String::new();
+    let mut next = 0;
+    let mut used = vec![false; values.len()];
+    let mut chars = template.chars().peekable();
+    while let Some(chr) = chars.next() {
+        match chr {
+            '{' if chars.next_if_eq(&'{').is_some() => out.push('{'),
+            '}' if chars.next_if_eq(&'}').is_some() => out.push('}'),
+            '}' => return Err("Unmatched } in format template, write }} for a literal brace".to_string()),
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(chr) => placeholder.push(chr),
+                        None => return Err("Unclosed { in format template, write {{ for a literal brace".to_string())
+                    }
+                }
+                let (position, spec) = placeholder.split_once(':').unwrap_or((&placeholder, ""));
+                let index = if position.is_empty() {
+                    next += 1;
+                    next - 1
+                } else {
+                    position.parse::<usize>().map_err(|_| format!("Invalid format placeholder {{{placeholder}}}"))?
+                };
+                let value = values.get(index).ok_or_else(|| format!("format has no value for placeholder {index}"))?;
+                out.push_str(&Spec::parse(spec)?.apply(value)?);
+                used[index] = true;
+            },
+            _ => out.push(chr)
+        }
+    }
+    if let Some(index) = used.iter().position(|used| !used) {
+        return Err(format!("format got more values than the template uses, value {index} has no placeholder"));
+    }
+
+    let str_ptr = heap_manager.borrow_mut().allocate_string(out);
+    stack.push(Value::String(str_ptr));
+    Ok(())
+}
+
+#[derive(Default)]
+struct Spec {
+    fill: Option<char>,
+    align: Option<char>,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>
+}
+
+impl Spec {
+    fn parse(spec: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid format spec :{spec}");
+        let mut parsed = Spec::default();
+        let chars = spec.chars().collect::<Vec<char>>();
+        let mut pos = 0;
+
+        if chars.len() >= 2 && matches!(chars[1], '<' | '>' | '^') {
+            parsed.fill = Some(chars[0]);
+            parsed.align = Some(chars[1]);
+            pos = 2;
+        } else if chars.first().is_some_and(|chr| matches!(chr, '<' | '>' | '^')) {
+            parsed.align = Some(chars[0]);
+            pos = 1;
+        }
+        if chars.get(pos) == Some(&'0') {
+            parsed.zero = true;
+            pos += 1;
+        }
+        let digits = |pos: &mut usize| {
+            let start = *pos;
+            while chars.get(*pos).is_some_and(|chr| chr.is_ascii_digit()) {
+                *pos += 1;
+            }
+            chars[start..*pos].iter().collect::<String>()
+        };
+        let width = digits(&mut pos);
+        if !width.is_empty() {
+            parsed.width = width.parse().map_err(|_| invalid())?;
+        }
+        if chars.get(pos) == Some(&'.') {
+            pos += 1;
+            parsed.precision = Some(digits(&mut pos).parse().map_err(|_| invalid())?);
+        }
+        if pos != chars.len() {
+            return Err(invalid());
+        }
+
+        Ok(parsed)
+    }
+
+    fn apply(&self, value: &Value) -> Result<String, HeapError> {
+        let (text, is_number) = match (value, self.precision) {
+            (Value::Number(num), Some(precision)) => (format!("{num:.precision$}"), true),
+            (Value::Number(num), None) => (num.to_string(), true),
+            (Value::String(str_ptr), precision) => (Self::truncate(HeapManager::get_string(*str_ptr)?, precision), false),
+            (value, precision) => (Self::truncate(value.to_string(), precision), false)
+        };
+
+        let len = text.chars().count();
+        if len >= self.width {
+            return Ok(text);
+        }
+        let padding = self.width - len;
+        if self.zero && is_number && self.align.is_none() {
+            // zeros go after the sign
+            let (sign, digits) = text.split_at(if text.starts_with('-') { 1 } else { 0 });
+            return Ok(format!("{sign}{}{digits}", "0".repeat(padding)));
+        }
+
+        let fill = self.fill.unwrap_or(' ').to_string();
+        Ok(match self.align.unwrap_or(if is_number { '>' } else { '<' }) {
+            '<' => format!("{text}{}", fill.repeat(padding)),
+            '>' => format!("{}{text}", fill.repeat(padding)),
+            _ => format!("{}{text}{}", fill.repeat(padding / 2), fill.repeat(padding - padding / 2))
+        })
+    }
+
+    fn truncate(text: String, precision: Option<usize>) -> String {
+        match precision {
+            Some(precision) => text.chars().take(precision).collect(),
+            None => text
+        }
+    }
+}
+
+/// Like `print`, without ending the line
+pub fn write(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "write", 1)?;
+    let value = runtime.stack().pop().unwrap();
+    runtime.write(&value.to_string());
+    runtime.stack().push(Value::Null);
+    Ok(())
+}
+
+/// Prints a line to stderr
+pub fn eprint(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "eprint", 1)?;
+    let value = runtime.stack().pop().unwrap();
+    runtime.write_error(&format!("{value}\n"));
+    runtime.stack().push(Value::Null);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestRuntime;
+
+    fn format_values(runtime: &mut TestRuntime, template: &str, mut values: Vec<Value>) -> Result<String, String> {
+        values.insert(0, runtime.string(template));
+        match runtime.call(format, values)? {
+            Value::String(str_ptr) => Ok(HeapManager::get_string(str_ptr).unwrap()),
+            value => panic!("format returned {value}")
+        }
+    }
+
+    #[test]
+    fn test_placeholders() {
+        let mut runtime = TestRuntime::new();
+        let name = runtime.string("pan");
+        assert_eq!(format_values(&mut runtime, "{} has {} points", vec![name.clone(), Value::Number(3f64)]).unwrap(), "pan has 3 points");
+        assert_eq!(format_values(&mut runtime, "{1}-{0}-{1}", vec![name, Value::Bool(true)]).unwrap(), "true-pan-true");
+        assert_eq!(format_values(&mut runtime, "{{}} {}", vec![Value::Null]).unwrap(), "{} null");
+        assert_eq!(format_values(&mut runtime, "plain", vec![]).unwrap(), "plain");
+    }
+
+    #[test]
+    fn test_specs() {
+        let mut runtime = TestRuntime::new();
+        let word = runtime.string("word");
+        assert_eq!(format_values(&mut runtime, "[{:.2}]", vec![Value::Number(1.005f64 + 2f64)]).unwrap(), "[3.00]");
+        assert_eq!(format_values(&mut runtime, "[{:6}]", vec![Value::Number(42f64)]).unwrap(), "[    42]");
+        assert_eq!(format_values(&mut runtime, "[{:06.1}]", vec![Value::Number(-2.5)]).unwrap(), "[-002.5]");
+        assert_eq!(format_values(&mut runtime, "[{0:6}|{0:>6}|{0:*^8}|{0:.2}]", vec![word]).unwrap(), "[word  |  word|**word**|wo]");
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        let mut runtime = TestRuntime::new();
+        assert!(format_values(&mut runtime, "{} {}", vec![Value::Null]).unwrap_err().contains("no value for placeholder 1"));
+        assert!(format_values(&mut runtime, "{", vec![]).unwrap_err().contains("Unclosed {"));
+        assert!(format_values(&mut runtime, "}", vec![]).unwrap_err().contains("Unmatched }"));
+        assert!(format_values(&mut runtime, "{:x}", vec![Value::Null]).unwrap_err().contains("Invalid format spec"));
+        assert!(format_values(&mut runtime, "{a}", vec![Value::Null]).unwrap_err().contains("Invalid format placeholder"));
+        assert_eq!(runtime.call(format, vec![]).unwrap_err(), "format expects a template");
+    }
+
+    #[test]
+    fn test_extra_values_are_rejected() {
+        let mut runtime = TestRuntime::new();
+        let err = format_values(&mut runtime, "{}", vec![Value::Number(1f64), Value::Number(2f64)]).unwrap_err();
+        assert_eq!(err, "format got more values than the template uses, value 1 has no placeholder");
+        assert!(format_values(&mut runtime, "{1}", vec![Value::Number(1f64), Value::Number(2f64)]).is_err());
+        assert!(format_values(&mut runtime, "no placeholders", vec![Value::Null]).is_err());
+    }
+
+    #[test]
+    fn test_write_and_eprint() {
+        let mut runtime = TestRuntime::new();
+        runtime.call(write, vec![Value::Number(1f64)]).unwrap();
+        let text = runtime.string("text");
+        runtime.call(write, vec![text]).unwrap();
+        runtime.call(eprint, vec![Value::Bool(false)]).unwrap();
+        assert_eq!(runtime.output, "1text");
+        assert_eq!(runtime.errors, "false\n");
+    }
+}
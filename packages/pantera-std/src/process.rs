@@ -0,0 +1,58 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use pantera_heap::heap::HeapManager;
+use pantera_heap::runtime::Runtime;
+use pantera_heap::value::{BuiltinResult, Value};
+use crate::args::{expect_args, pop_string};
+use crate::STD_LIB;
+
+/// Looks up an environment variable, returning null when it isn't set
+pub fn env(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "env", 1)?;
+    let heap_manager = runtime.heap();
+    let stack = runtime.stack();
+    let name = pop_string(stack, "env")?;
+    match std::env::var(&name) {
+        Ok(value) => {
+            let str_ptr = heap_manager.borrow_mut().allocate_string(value);
+            stack.push(Value::String(str_ptr));
+        },
+        Err(std::env::VarError::NotPresent) => stack.push(Value::Null),
+        Err(err) => return Err(format!("Couldn't read {name}: {err}"))
+    }
+    Ok(())
+}
+
+/// Exposes the arguments given after the script name as the `args` global
+pub fn set_args(globals: &mut HashMap<u16, Value>, heap_manager: &Rc<RefCell<HeapManager>>, args: &[String]) {
+    let Some(ind) = STD_LIB.iter().position(|g| g.name == "args") else { return; };
+
+    let mut heap_manager = heap_manager.borrow_mut();
+    let mut values = vec![];
+    for arg in args.iter().rev() {
+        values.push(Value::String(heap_manager.allocate_string(arg.clone())));
+    }
+    let arr_ptr = heap_manager.allocate_array(values);
+    globals.insert(ind as u16, Value::Array(arr_ptr));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestRuntime;
+
+    #[test]
+    fn test_env() {
+        std::env::set_var("PANTERA_TEST_ENV", "set");
+        let mut runtime = TestRuntime::new();
+        let name = runtime.string("PANTERA_TEST_ENV");
+        let Value::String(str_ptr) = runtime.call(env, vec![name]).unwrap() else { panic!("Not a string") };
+        assert_eq!(HeapManager::get_string(str_ptr).unwrap(), "set");
+
+        let name = runtime.string("PANTERA_TEST_ENV_MISSING");
+        assert!(matches!(runtime.call(env, vec![name]).unwrap(), Value::Null));
+        assert_eq!(runtime.call(env, vec![Value::Number(1f64)]).unwrap_err(), "env expects a string argument");
+        assert_eq!(runtime.call(env, vec![]).unwrap_err(), "env expects 1 argument");
+    }
+}
@@ -0,0 +1,161 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use ::regex::Regex;
+use pantera_heap::heap::HeapManager;
+use pantera_heap::runtime::Runtime;
+use pantera_heap::value::{BuiltinResult, Value};
+use crate::args::{expect_args, pop_string};
+
+/// How many compiled patterns a VM keeps
+const CACHED_PATTERNS: usize = 64;
+
+/// Patterns compiled by this VM, keyed by their source. Scripts that build patterns at
+/// runtime could make it grow without end, so the least recently used one is dropped
+/// once it's full.
+#[derive(Default)]
+struct PatternCache {
+    patterns: HashMap<String, (Regex, u64)>,
+    /// Bumped on every use, to tell which pattern was used last
+    uses: u64
+}
+
+impl PatternCache {
+    fn get(&mut self, pattern: &str) -> Option<Regex> {
+        self.uses += 1;
+        let (regex, last_use) = self.patterns.get_mut(pattern)?;
+        *last_use = self.uses;
+        Some(regex.clone())
+    }
+
+    fn insert(&mut self, pattern: &str, regex: Regex) {
+        if self.patterns.len() >= CACHED_PATTERNS {
+            if let Some(oldest) = self.patterns.iter().min_by_key(|(_pattern, (_regex, last_use))| *last_use).map(|(pattern, _entry)| pattern.clone()) {
+                self.patterns.remove(&oldest);
+            }
+        }
+        self.uses += 1;
+        self.patterns.insert(pattern.to_string(), (regex, self.uses));
+    }
+}
+
+fn compile(runtime: &mut dyn Runtime, pattern: &str) -> Result<Regex, String> {
+    let cache = runtime.extensions().get_or_default::<PatternCache>();
+    if let Some(regex) = cache.get(pattern) {
+        return Ok(regex);
+    }
+
+    let regex = Regex::new(pattern).map_err(|err| format!("Invalid regex {pattern}: {err}"))?;
+    cache.insert(pattern, regex.clone());
+    Ok(regex)
+}
+
+fn allocate_string(heap_manager: &Rc<RefCell<HeapManager>>, string: &str) -> Value {
+    Value::String(heap_manager.borrow_mut().allocate_string(string.to_string()))
+}
+
+pub fn matches(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "matches", 2)?;
+    let stack = runtime.stack();
+    let pattern = pop_string(stack, "matches")?;
+    let string = pop_string(stack, "matches")?;
+    let regex = compile(runtime, &pattern)?;
+    runtime.stack().push(Value::Bool(regex.is_match(&string)));
+    Ok(())
+}
+
+/// Every non-overlapping match, in order
+pub fn find_all(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "find_all", 2)?;
+    let heap_manager = runtime.heap();
+    let stack = runtime.stack();
+    let pattern = pop_string(stack, "find_all")?;
+    let string = pop_string(stack, "find_all")?;
+    let regex = compile(runtime, &pattern)?;
+
+    let mut values: Vec<Value> = regex.find_iter(&string).map(|found| allocate_string(&heap_manager, found.as_str())).collect();
+    values.reverse();
+    let arr_ptr = heap_manager.borrow_mut().allocate_array(values);
+    runtime.stack().push(Value::Array(arr_ptr));
+    Ok(())
+}
+
+/// The groups of the first match, or null when nothing matches. Patterns with named
+/// groups give an object of those groups, others an array whose first item is the
+/// whole match. Groups that didn't take part in the match are null.
+pub fn captures(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "captures", 2)?;
+    let heap_manager = runtime.heap();
+    let stack = runtime.stack();
+    let pattern = pop_string(stack, "captures")?;
+    let string = pop_string(stack, "captures")?;
+    let regex = compile(runtime, &pattern)?;
+
+    let Some(groups) = regex.captures(&string) else {
+        runtime.stack().push(Value::Null);
+        return Ok(());
+    };
+    let group_value = |index: usize| groups.get(index).map_or(Value::Null, |group| allocate_string(&heap_manager, group.as_str()));
+
+    let value = if regex.capture_names().any(|name| name.is_some()) {
+        let mut obj = HashMap::new();
+        for (index, name) in regex.capture_names().enumerate() {
+            if let Some(name) = name {
+                let key = heap_manager.borrow_mut().allocate_string(name.to_string());
+                obj.insert(key, group_value(index));
+            }
+        }
+        Value::Object(heap_manager.borrow_mut().allocate_object(obj))
+    } else {
+        let values = (0..groups.len()).rev().map(group_value).collect();
+        Value::Array(heap_manager.borrow_mut().allocate_array(values))
+    };
+    runtime.stack().push(value);
+    Ok(())
+}
+
+/// Replaces every match; the replacement can refer to groups as `$1` or `$name`
+pub fn replace_all(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "replace_all", 3)?;
+    let heap_manager = runtime.heap();
+    let stack = runtime.stack();
+    let replacement = pop_string(stack, "replace_all")?;
+    let pattern = pop_string(stack, "replace_all")?;
+    let string = pop_string(stack, "replace_all")?;
+    let regex = compile(runtime, &pattern)?;
+
+    let replaced = regex.replace_all(&string, replacement.as_str());
+    runtime.stack().push(allocate_string(&heap_manager, &replaced));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestRuntime;
+
+    #[test]
+    fn test_pattern_cache_drops_the_least_recently_used() {
+        let mut cache = PatternCache::default();
+        for index in 0..CACHED_PATTERNS {
+            cache.insert(&index.to_string(), Regex::new(&index.to_string()).unwrap());
+        }
+        assert!(cache.get("0").is_some());
+
+        cache.insert("new", Regex::new("new").unwrap());
+        assert_eq!(cache.patterns.len(), CACHED_PATTERNS);
+        assert!(cache.get("0").is_some());
+        assert!(cache.get("1").is_none());
+        assert!(cache.get("new").is_some());
+    }
+
+    #[test]
+    fn test_patterns_built_at_runtime_stay_bounded() {
+        let mut runtime = TestRuntime::new();
+        for index in 0..CACHED_PATTERNS * 2 {
+            let (string, pattern) = (runtime.string("a1"), runtime.string(&format!("a{index}")));
+            runtime.call(matches, vec![string, pattern]).unwrap();
+        }
+        assert_eq!(runtime.extensions().get_or_default::<PatternCache>().patterns.len(), CACHED_PATTERNS);
+    }
+}
@@ -0,0 +1,193 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use pantera_heap::heap::HeapManager;
+use pantera_heap::stack::Stack;
+use pantera_heap::runtime::Runtime;
+use pantera_heap::value::{BuiltinResult, Value};
+use crate::args::{expect_args, pop_number, pop_string};
+
+fn push_string(stack: &mut Stack, heap_manager: &Rc<RefCell<HeapManager>>, string: String) {
+    let str_ptr = heap_manager.borrow_mut().allocate_string(string);
+    stack.push(Value::String(str_ptr));
+}
+
+macro_rules! transform_functions {
+    ($($func:ident => $op:expr),*) => {
+        $(
+            pub fn $func(runtime: &mut dyn Runtime) -> BuiltinResult {
+                expect_args(runtime, stringify!($func), 1)?;
+                let heap_manager = runtime.heap();
+                let stack = runtime.stack();
+                let string = pop_string(stack, stringify!($func))?;
+                let op: fn(&str) -> String = $op;
+                push_string(stack, &heap_manager, op(&string));
+                Ok(())
+            }
+        )*
+    };
+}
+
+transform_functions!(
+    trim => |string| string.trim().to_string(),
+    upper => |string| string.to_uppercase(),
+    lower => |string| string.to_lowercase()
+);
+
+macro_rules! search_functions {
+    ($($func:ident => $op:expr),*) => {
+        $(
+            pub fn $func(runtime: &mut dyn Runtime) -> BuiltinResult {
+                expect_args(runtime, stringify!($func), 2)?;
+                let stack = runtime.stack();
+                let pattern = pop_string(stack, stringify!($func))?;
+                let string = pop_string(stack, stringify!($func))?;
+                let op: fn(&str, &str) -> bool = $op;
+                stack.push(Value::Bool(op(&string, &pattern)));
+                Ok(())
+            }
+        )*
+    };
+}
+
+search_functions!(
+    contains => |string, pattern| string.contains(pattern),
+    starts_with => |string, pattern| string.starts_with(pattern),
+    ends_with => |string, pattern| string.ends_with(pattern)
+);
+
+/// Splits on `separator`, or into single characters when the separator is empty
+pub fn split(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "split", 2)?;
+    let heap_manager = runtime.heap();
+    let stack = runtime.stack();
+    let separator = pop_string(stack, "split")?;
+    let string = pop_string(stack, "split")?;
+    let parts: Vec<String> = if separator.is_empty() {
+        string.chars().map(|chr| chr.to_string()).collect()
+    } else {
+        string.split(separator.as_str()).map(|part| part.to_string()).collect()
+    };
+
+    let mut values = vec![];
+    for part in parts.into_iter().rev() {
+        values.push(Value::String(heap_manager.borrow_mut().allocate_string(part)));
+    }
+    let arr_ptr = heap_manager.borrow_mut().allocate_array(values);
+    stack.push(Value::Array(arr_ptr));
+    Ok(())
+}
+
+pub fn join(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "join", 2)?;
+    let heap_manager = runtime.heap();
+    let stack = runtime.stack();
+    let separator = pop_string(stack, "join")?;
+    let Value::Array(arr_ptr) = stack.pop().unwrap() else { return Err("join expects an array argument".to_string()) };
+    let joined = HeapManager::get_array(arr_ptr)?.iter().map(|val| val.to_string()).collect::<Vec<String>>().join(&separator);
+    push_string(stack, &heap_manager, joined);
+    Ok(())
+}
+
+pub fn replace(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "replace", 3)?;
+    let heap_manager = runtime.heap();
+    let stack = runtime.stack();
+    let to = pop_string(stack, "replace")?;
+    let from = pop_string(stack, "replace")?;
+    let string = pop_string(stack, "replace")?;
+    push_string(stack, &heap_manager, string.replace(&from, &to));
+    Ok(())
+}
+
+/// Returns the character index of the first occurrence of the pattern, or -1
+pub fn find(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "find", 2)?;
+    let stack = runtime.stack();
+    let pattern = pop_string(stack, "find")?;
+    let string = pop_string(stack, "find")?;
+    let index = match string.find(&pattern) {
+        Some(byte_index) => string[..byte_index].chars().count() as f64,
+        None => -1f64
+    };
+    stack.push(Value::Number(index));
+    Ok(())
+}
+
+/// Characters from `start` up to, but excluding, `end`. Indexes are clamped to the string.
+pub fn substring(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "substring", 3)?;
+    let heap_manager = runtime.heap();
+    let stack = runtime.stack();
+    let end = pop_number(stack, "substring")?.max(0f64) as usize;
+    let start = pop_number(stack, "substring")?.max(0f64) as usize;
+    let string = pop_string(stack, "substring")?;
+    let substring = string.chars().skip(start).take(end.saturating_sub(start)).collect::<String>();
+    push_string(stack, &heap_manager, substring);
+    Ok(())
+}
+
+pub fn repeat(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "repeat", 2)?;
+    let heap_manager = runtime.heap();
+    let stack = runtime.stack();
+    let times = pop_number(stack, "repeat")?;
+    let string = pop_string(stack, "repeat")?;
+    if times < 0f64 || times.fract() != 0f64 {
+        return Err("repeat expects a non negative whole count".to_string());
+    }
+    push_string(stack, &heap_manager, string.repeat(times as usize));
+    Ok(())
+}
+
+/// Code point of the first character
+pub fn code(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "code", 1)?;
+    let stack = runtime.stack();
+    let string = pop_string(stack, "code")?;
+    let Some(chr) = string.chars().next() else { return Err("code expects a non empty string".to_string()) };
+    stack.push(Value::Number(chr as u32 as f64));
+    Ok(())
+}
+
+pub fn char(runtime: &mut dyn Runtime) -> BuiltinResult {
+    expect_args(runtime, "char", 1)?;
+    let heap_manager = runtime.heap();
+    let stack = runtime.stack();
+    let code = pop_number(stack, "char")?;
+    let Some(chr) = char::from_u32(code as u32) else { return Err(format!("{code} is not a valid code point")) };
+    push_string(stack, &heap_manager, chr.to_string());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestRuntime;
+
+    fn string_of(value: Value) -> String {
+        match value {
+            Value::String(str_ptr) => HeapManager::get_string(str_ptr).unwrap(),
+            value => panic!("{value} is not a string")
+        }
+    }
+
+    #[test]
+    fn test_repeat() {
+        let mut runtime = TestRuntime::new();
+        let string = runtime.string("ab");
+        assert_eq!(string_of(runtime.call(repeat, vec![string.clone(), Value::Number(3f64)]).unwrap()), "ababab");
+        assert_eq!(string_of(runtime.call(repeat, vec![string.clone(), Value::Number(0f64)]).unwrap()), "");
+        assert!(runtime.call(repeat, vec![string.clone(), Value::Number(1.5)]).unwrap_err().contains("whole count"));
+        assert!(runtime.call(repeat, vec![string.clone(), Value::Number(-1f64)]).is_err());
+        assert!(runtime.call(repeat, vec![string, Value::Number(f64::NAN)]).is_err());
+    }
+
+    #[test]
+    fn test_substring_counts_characters() {
+        let mut runtime = TestRuntime::new();
+        let string = runtime.string("héllo");
+        let substring = runtime.call(substring, vec![string.clone(), Value::Number(1f64), Value::Number(3f64)]).unwrap();
+        assert_eq!(string_of(substring), "él");
+        assert!(matches!(runtime.call(find, vec![string, runtime.string("l")]).unwrap(), Value::Number(num) if num == 2f64));
+    }
+}
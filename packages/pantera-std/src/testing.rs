@@ -0,0 +1,75 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use pantera_heap::heap::HeapManager;
+use pantera_heap::runtime::{Extensions, Runtime};
+use pantera_heap::stack::Stack;
+use pantera_heap::value::{BuiltinResult, Value};
+
+/// A runtime for calling builtins directly, without a VM. It can't run script functions.
+pub(crate) struct TestRuntime {
+    pub(crate) stack: Stack,
+    pub(crate) heap_manager: Rc<RefCell<HeapManager>>,
+    extensions: Extensions,
+    arg_count: usize,
+    pub(crate) output: String,
+    pub(crate) errors: String
+}
+
+impl TestRuntime {
+    pub(crate) fn new() -> Self {
+        Self {
+            stack: Stack::init(),
+            heap_manager: Rc::new(RefCell::new(HeapManager::new(1024 * 1024))),
+            extensions: Extensions::default(),
+            arg_count: 0,
+            output: String::new(),
+            errors: String::new()
+        }
+    }
+
+    pub(crate) fn string(&self, string: &str) -> Value {
+        Value::String(self.heap_manager.borrow_mut().allocate_string(string.to_string()))
+    }
+
+    /// Calls `builtin` with `args` and returns what it pushed
+    pub(crate) fn call(&mut self, builtin: fn(&mut dyn Runtime) -> BuiltinResult, args: Vec<Value>) -> Result<Value, String> {
+        self.arg_count = args.len();
+        args.into_iter().for_each(|arg| self.stack.push(arg));
+        builtin(self)?;
+        Ok(self.stack.pop().unwrap())
+    }
+}
+
+impl Runtime for TestRuntime {
+    fn stack(&mut self) -> &mut Stack {
+        &mut self.stack
+    }
+
+    fn heap(&self) -> Rc<RefCell<HeapManager>> {
+        Rc::clone(&self.heap_manager)
+    }
+
+    fn extensions(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+
+    fn arg_count(&self) -> usize {
+        self.arg_count
+    }
+
+    fn call(&mut self, _callee: Value, _args: Vec<Value>) -> Result<Value, String> {
+        Err("TestRuntime can't call script functions".to_string())
+    }
+
+    fn write(&mut self, text: &str) {
+        self.output.push_str(text);
+    }
+
+    fn write_error(&mut self, text: &str) {
+        self.errors.push_str(text);
+    }
+
+    fn read_line(&mut self) -> Option<String> {
+        None
+    }
+}
@@ -0,0 +1,151 @@
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Time source used by the scheduler and by `now` and `clock`. Times are milliseconds since
+/// the clock was created.
+pub trait Clock {
+    fn now(&self) -> u64;
+    fn sleep_until(&mut self, time: u64);
+
+    /// Sleeps towards `time` for at most `max`, so that the caller gets to check whether it
+    /// should keep waiting
+    fn sleep_at_most(&mut self, time: u64, max: Duration) {
+        let time = time.min(self.now() + max.as_millis() as u64);
+        self.sleep_until(time);
+    }
+
+    /// Wall clock time, in milliseconds since the Unix epoch
+    fn epoch(&self) -> u64;
+
+    /// Like `now`, with sub-millisecond precision when the clock has it
+    fn elapsed(&self) -> f64 {
+        self.now() as f64
+    }
+}
+
+pub struct SystemClock {
+    start: Instant,
+    start_epoch: u64
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            start_epoch: SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_millis() as u64).unwrap_or(0)
+        }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+
+    fn sleep_until(&mut self, time: u64) {
+        let now = self.now();
+        if time > now {
+            thread::sleep(Duration::from_millis(time - now));
+        }
+    }
+
+    fn epoch(&self) -> u64 {
+        self.start_epoch + self.now()
+    }
+
+    fn elapsed(&self) -> f64 {
+        self.start.elapsed().as_secs_f64() * 1000f64
+    }
+}
+
+/// A clock that never waits: sleeping jumps straight to the wake-up time, so scripts
+/// using `sleep` run instantly and always interleave the same way. Its wall clock starts
+/// at the Unix epoch unless given another start.
+#[derive(Default)]
+pub struct VirtualClock {
+    time: u64,
+    start_epoch: u64
+}
+
+impl VirtualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn starting_at(start_epoch: u64) -> Self {
+        Self {
+            time: 0,
+            start_epoch
+        }
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> u64 {
+        self.time
+    }
+
+    fn sleep_until(&mut self, time: u64) {
+        self.time = self.time.max(time);
+    }
+
+    // no real time passes, so there's nothing to check meanwhile
+    fn sleep_at_most(&mut self, time: u64, _max: Duration) {
+        self.sleep_until(time);
+    }
+
+    fn epoch(&self) -> u64 {
+        self.start_epoch + self.time
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+    use crate::{execute_with_options, Options};
+    use super::*;
+
+    #[test]
+    fn test_virtual_clock_skips_sleeps() {
+        let source = "print now(); sleep(60); print now(); print clock(); sleep(0.5); print clock();";
+        let started = Instant::now();
+        let options = Options { clock: Box::new(VirtualClock::starting_at(1_000_000)), ..Options::default() };
+        let result = execute_with_options(source, options).unwrap();
+
+        assert_eq!(result.output, vec!["1000000", "1060000", "60000", "60500"]);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_dates_follow_the_virtual_clock() {
+        let source = "var pattern = \"%Y-%m-%d %H:%M:%S.%L %A\";
+            print format_date(now(), pattern);
+            sleep(86400);
+            print format_date(now(), pattern);
+            print date_parts(now())'s weekday;
+            print days_between(parse_date(\"2024-02-29\", \"%Y-%m-%d\"), now());";
+        let options = Options { clock: Box::new(VirtualClock::starting_at(1_709_210_096_789)), ..Options::default() };
+        let result = execute_with_options(source, options).unwrap();
+
+        assert_eq!(result.output, vec!["2024-02-29 12:34:56.789 Thursday", "2024-03-01 12:34:56.789 Friday", "5", "1"]);
+    }
+
+    #[test]
+    fn test_virtual_clock_orders_sleeping_tasks() {
+        let source = "fun nap(seconds, name) { sleep(seconds); print name + \" at \" + to_string(clock()); return 0; }
+            var slow = spawn nap(3, \"slow\");
+            var fast = spawn nap(1, \"fast\");
+            await slow;
+            await fast;";
+        let options = Options { clock: Box::new(VirtualClock::new()), ..Options::default() };
+        let result = execute_with_options(source, options).unwrap();
+
+        assert_eq!(result.output, vec!["fast at 1000", "slow at 3000"]);
+    }
+}
@@ -0,0 +1,683 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+use pantera_heap::arena::Handle;
+use pantera_heap::array::Array;
+use pantera_heap::hash_table::HashTable;
+use pantera_heap::heap::{HeapError, HeapManager};
+use pantera_heap::value::Value;
+use serde::de::{DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess, VariantAccess, Visitor};
+use serde::ser::{SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple, SerializeTupleStruct, SerializeTupleVariant};
+use serde::{Deserializer, Serialize, Serializer};
+
+/// Integers beyond this lose precision as a number, so serializing them is an error
+const MAX_SAFE_INTEGER: u64 = 1 << 53;
+
+/// Turns Rust data into a Pantera value allocated in `heap_manager`. Structs and maps become
+/// objects, sequences and tuples become arrays, and enum variants with data become
+/// `{ Variant: data }` objects, as in JSON. 64-bit integers must fit in 53 bits.
+pub fn serialize<T: Serialize + ?Sized>(data: &T, heap_manager: &mut HeapManager) -> Result<Value, String> {
+    data.serialize(ValueSerializer { heap_manager }).map_err(|err| err.0)
+}
+
+/// Reads a Pantera value back into Rust data. Numbers convert to integer types only when they
+/// are whole and in range, and `null` stands for `None` and `()`. Cyclic values are an error.
+pub fn deserialize<T: DeserializeOwned>(value: &Value) -> Result<T, String> {
+    T::deserialize(ValueDeserializer { value: value.clone(), path: Rc::default() }).map_err(|err| err.0)
+}
+
+#[derive(Debug)]
+struct Error(String);
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl serde::ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+impl From<HeapError> for Error {
+    fn from(err: HeapError) -> Self {
+        Error(err.to_string())
+    }
+}
+
+impl serde::de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+struct ValueSerializer<'a> {
+    heap_manager: &'a mut HeapManager
+}
+
+impl ValueSerializer<'_> {
+    fn string(self, string: String) -> Result<Value, Error> {
+        Ok(Value::String(self.heap_manager.allocate_string(string)))
+    }
+
+    fn variant(self, variant: &str, value: Value) -> Result<Value, Error> {
+        let key = self.heap_manager.allocate_string(variant.to_string());
+        let obj_ptr = self.heap_manager.allocate_object(HashMap::from([(key, value)]));
+        Ok(Value::Object(obj_ptr))
+    }
+}
+
+/// Collects the elements of a sequence, tuple or tuple variant
+struct ArrayBuilder<'a> {
+    heap_manager: &'a mut HeapManager,
+    values: Vec<Value>,
+    variant: Option<&'static str>
+}
+
+impl ArrayBuilder<'_> {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.values.push(value.serialize(ValueSerializer { heap_manager: self.heap_manager })?);
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Value, Error> {
+        self.values.reverse();
+        let array = Value::Array(self.heap_manager.allocate_array(self.values));
+        match self.variant {
+            Some(variant) => ValueSerializer { heap_manager: self.heap_manager }.variant(variant, array),
+            None => Ok(array)
+        }
+    }
+}
+
+/// Collects the entries of a map, struct or struct variant
+struct ObjectBuilder<'a> {
+    heap_manager: &'a mut HeapManager,
+    entries: HashMap<Handle<String>, Value>,
+    key: Option<Handle<String>>,
+    variant: Option<&'static str>
+}
+
+impl ObjectBuilder<'_> {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<(), Error> {
+        let key = self.heap_manager.allocate_string(key.to_string());
+        let value = value.serialize(ValueSerializer { heap_manager: self.heap_manager })?;
+        self.entries.insert(key, value);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value, Error> {
+        let obj = Value::Object(self.heap_manager.allocate_object(self.entries));
+        match self.variant {
+            Some(variant) => ValueSerializer { heap_manager: self.heap_manager }.variant(variant, obj),
+            None => Ok(obj)
+        }
+    }
+}
+
+impl<'a> Serializer for ValueSerializer<'a> {
+    type Ok = Value;
+    type Error = Error;
+    type SerializeSeq = ArrayBuilder<'a>;
+    type SerializeTuple = ArrayBuilder<'a>;
+    type SerializeTupleStruct = ArrayBuilder<'a>;
+    type SerializeTupleVariant = ArrayBuilder<'a>;
+    type SerializeMap = ObjectBuilder<'a>;
+    type SerializeStruct = ObjectBuilder<'a>;
+    type SerializeStructVariant = ObjectBuilder<'a>;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, Error> {
+        Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, Error> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, Error> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, Error> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, Error> {
+        if v.unsigned_abs() > MAX_SAFE_INTEGER {
+            return Err(Error(format!("{v} is too large to be a number exactly")));
+        }
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, Error> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, Error> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, Error> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, Error> {
+        if v > MAX_SAFE_INTEGER {
+            return Err(Error(format!("{v} is too large to be a number exactly")));
+        }
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, Error> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, Error> {
+        Ok(Value::Number(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, Error> {
+        self.string(v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, Error> {
+        self.string(v.to_string())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, Error> {
+        let mut seq = self.serialize_seq(Some(v.len()))?;
+        for byte in v {
+            seq.push(byte)?;
+        }
+        seq.finish()
+    }
+
+    fn serialize_none(self) -> Result<Value, Error> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, Error> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, Error> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _variant_index: u32, variant: &'static str) -> Result<Value, Error> {
+        self.string(variant.to_string())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<Value, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _name: &'static str, _variant_index: u32, variant: &'static str, value: &T) -> Result<Value, Error> {
+        let value = value.serialize(ValueSerializer { heap_manager: self.heap_manager })?;
+        self.variant(variant, value)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<ArrayBuilder<'a>, Error> {
+        Ok(ArrayBuilder { heap_manager: self.heap_manager, values: Vec::with_capacity(len.unwrap_or_default()), variant: None })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<ArrayBuilder<'a>, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<ArrayBuilder<'a>, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(self, _name: &'static str, _variant_index: u32, variant: &'static str, len: usize) -> Result<ArrayBuilder<'a>, Error> {
+        Ok(ArrayBuilder { heap_manager: self.heap_manager, values: Vec::with_capacity(len), variant: Some(variant) })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<ObjectBuilder<'a>, Error> {
+        Ok(ObjectBuilder { heap_manager: self.heap_manager, entries: HashMap::new(), key: None, variant: None })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<ObjectBuilder<'a>, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(self, _name: &'static str, _variant_index: u32, variant: &'static str, _len: usize) -> Result<ObjectBuilder<'a>, Error> {
+        Ok(ObjectBuilder { heap_manager: self.heap_manager, entries: HashMap::new(), key: None, variant: Some(variant) })
+    }
+}
+
+impl SerializeSeq for ArrayBuilder<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl SerializeTuple for ArrayBuilder<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl SerializeTupleStruct for ArrayBuilder<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl SerializeTupleVariant for ArrayBuilder<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl SerializeMap for ObjectBuilder<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        match key.serialize(ValueSerializer { heap_manager: self.heap_manager })? {
+            Value::String(key) => self.key = Some(key),
+            Value::Number(num) => self.key = Some(self.heap_manager.allocate_string(num.to_string())),
+            _ => return Err(Error("Object keys must be strings or numbers".to_string()))
+        }
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self.key.take().ok_or_else(|| Error("Object value without a key".to_string()))?;
+        let value = value.serialize(ValueSerializer { heap_manager: self.heap_manager })?;
+        self.entries.insert(key, value);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl SerializeStruct for ObjectBuilder<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl SerializeStructVariant for ObjectBuilder<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+/// Arrays and objects currently being read, to detect cycles
+#[derive(Default)]
+struct Path {
+    arrays: Vec<Handle<Array>>,
+    objects: Vec<Handle<HashTable>>
+}
+
+impl Path {
+    fn enter<T>(path: &mut Vec<Handle<T>>, ptr: Handle<T>) -> Result<(), Error> {
+        if path.contains(&ptr) {
+            return Err(Error("Cannot convert a cyclic structure to Rust data".to_string()));
+        }
+        path.push(ptr);
+        Ok(())
+    }
+}
+
+struct ValueDeserializer {
+    value: Value,
+    path: Rc<RefCell<Path>>
+}
+
+impl ValueDeserializer {
+    fn child(&self, value: Value) -> Self {
+        Self { value, path: self.path.clone() }
+    }
+
+    fn type_error(&self, expected: &str) -> Error {
+        Error(format!("Expected {expected} but got {}", self.value))
+    }
+
+    fn integer(&self, expected: &str, min: f64, max: f64) -> Result<f64, Error> {
+        match self.value {
+            Value::Number(num) if num.fract() == 0f64 && num >= min && num <= max => Ok(num),
+            _ => Err(self.type_error(expected))
+        }
+    }
+}
+
+macro_rules! deserialize_integer {
+    ($($method:ident => $visit:ident($typ:ty)),*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                let num = self.integer(stringify!($typ), <$typ>::MIN as f64, <$typ>::MAX as f64)?;
+                visitor.$visit(num as $typ)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for ValueDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Value::Null => visitor.visit_unit(),
+            Value::Bool(val) => visitor.visit_bool(val),
+            Value::Number(num) if num.fract() == 0f64 && num.abs() < i64::MAX as f64 => visitor.visit_i64(num as i64),
+            Value::Number(num) => visitor.visit_f64(num),
+            Value::String(ptr) => visitor.visit_string(HeapManager::get_string(ptr)?),
+            Value::Array(ptr) => {
+                Path::enter(&mut self.path.borrow_mut().arrays, ptr)?;
+                let result = visitor.visit_seq(Elements { values: HeapManager::get_array(ptr)?.into_iter(), path: self.path.clone() });
+                self.path.borrow_mut().arrays.pop();
+                result
+            },
+            Value::Range(ptr) => {
+                let range = HeapManager::get_range(ptr)?;
+                let numbers: Vec<Value> = (0..range.len()).map(|index| Value::Number(range.get(index).unwrap())).collect();
+                visitor.visit_seq(Elements { values: numbers.into_iter(), path: self.path.clone() })
+            },
+            Value::Object(ptr) => {
+                Path::enter(&mut self.path.borrow_mut().objects, ptr)?;
+                let result = visitor.visit_map(Entries { ptr, index: 0, len: HeapManager::get_object_len(ptr)?, value: None, path: self.path.clone() });
+                self.path.borrow_mut().objects.pop();
+                result
+            },
+            Value::Function(_) => Err(Error("Cannot convert a function to Rust data".to_string())),
+            Value::Generator(_) => Err(Error("Cannot convert a generator to Rust data".to_string())),
+            Value::Task(_) => Err(Error("Cannot convert a task to Rust data".to_string())),
+            Value::Channel(_) => Err(Error("Cannot convert a channel to Rust data".to_string()))
+        }
+    }
+
+    deserialize_integer!(
+        deserialize_i8 => visit_i8(i8), deserialize_i16 => visit_i16(i16), deserialize_i32 => visit_i32(i32), deserialize_i64 => visit_i64(i64),
+        deserialize_u8 => visit_u8(u8), deserialize_u16 => visit_u16(u16), deserialize_u32 => visit_u32(u32), deserialize_u64 => visit_u64(u64)
+    );
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_f64(visitor)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Value::Number(num) => visitor.visit_f64(num),
+            _ => Err(self.type_error("a number"))
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Value::String(ptr) => visitor.visit_enum(HeapManager::get_string(ptr)?.into_deserializer()),
+            Value::Object(ptr) if HeapManager::get_object_len(ptr)? == 1 => {
+                Path::enter(&mut self.path.borrow_mut().objects, ptr)?;
+                let (key, value) = HeapManager::get_object_entry(ptr, 0)?;
+                let result = visitor.visit_enum(Variant { name: HeapManager::get_string(key)?, value: self.child(value) });
+                self.path.borrow_mut().objects.pop();
+                result
+            },
+            _ => Err(self.type_error("an enum variant"))
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool char str string bytes byte_buf unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct Elements {
+    values: std::vec::IntoIter<Value>,
+    path: Rc<RefCell<Path>>
+}
+
+impl<'de> SeqAccess<'de> for Elements {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Error> {
+        self.values.next().map(|value| seed.deserialize(ValueDeserializer { value, path: self.path.clone() })).transpose()
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.values.len())
+    }
+}
+
+struct Entries {
+    ptr: Handle<HashTable>,
+    index: usize,
+    len: usize,
+    value: Option<Value>,
+    path: Rc<RefCell<Path>>
+}
+
+impl<'de> MapAccess<'de> for Entries {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+        if self.index == self.len {
+            return Ok(None);
+        }
+        let (key, value) = HeapManager::get_object_entry(self.ptr, self.index)?;
+        self.index += 1;
+        self.value = Some(value);
+        seed.deserialize(ValueDeserializer { value: Value::String(key), path: self.path.clone() }).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let value = self.value.take().ok_or_else(|| Error("Object value without a key".to_string()))?;
+        seed.deserialize(ValueDeserializer { value, path: self.path.clone() })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len - self.index)
+    }
+}
+
+/// An enum variant with data, written as `{ Variant: data }`
+struct Variant {
+    name: String,
+    value: ValueDeserializer
+}
+
+impl<'de> EnumAccess<'de> for Variant {
+    type Error = Error;
+    type Variant = ValueDeserializer;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, ValueDeserializer), Error> {
+        let variant = seed.deserialize(IntoDeserializer::<Error>::into_deserializer(self.name))?;
+        Ok((variant, self.value))
+    }
+}
+
+impl<'de> VariantAccess<'de> for ValueDeserializer {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self.value {
+            Value::Null => Ok(()),
+            _ => Err(self.type_error("no data for a unit variant"))
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_any(visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_any(visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::de::IgnoredAny;
+    use serde::Deserialize;
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Shape {
+        Empty,
+        Circle(f64),
+        Segment(f64, f64),
+        Rect { width: u32, height: u32 }
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Drawing {
+        name: String,
+        layer: Option<u8>,
+        note: Option<String>,
+        shapes: Vec<Shape>,
+        grid: Vec<Vec<i32>>,
+        tags: HashMap<String, Vec<bool>>
+    }
+
+    fn round_trip<T: Serialize + DeserializeOwned>(data: &T) -> T {
+        let mut heap_manager = HeapManager::new(1024 * 1024);
+        let value = serialize(data, &mut heap_manager).unwrap();
+        deserialize(&value).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let drawing = Drawing {
+            name: "plan".to_string(),
+            layer: Some(2),
+            note: None,
+            shapes: vec![Shape::Empty, Shape::Circle(1.5), Shape::Segment(-1f64, 2f64), Shape::Rect { width: 3, height: 4 }],
+            grid: vec![vec![1, -2], vec![], vec![3]],
+            tags: HashMap::from([("visible".to_string(), vec![true, false]), ("empty".to_string(), vec![])])
+        };
+
+        assert_eq!(round_trip(&drawing), drawing);
+        assert_eq!(round_trip(&(1u8, "two".to_string(), None::<bool>)), (1u8, "two".to_string(), None));
+    }
+
+    #[test]
+    fn test_variants_are_objects_keyed_by_name() {
+        let mut heap_manager = HeapManager::new(1024 * 1024);
+        let value = serialize(&Shape::Rect { width: 3, height: 4 }, &mut heap_manager).unwrap();
+        let rect: HashMap<String, HashMap<String, u32>> = deserialize(&value).unwrap();
+        assert_eq!(rect, HashMap::from([("Rect".to_string(), HashMap::from([("width".to_string(), 3), ("height".to_string(), 4)]))]));
+        assert!(matches!(serialize(&Shape::Empty, &mut heap_manager).unwrap(), Value::String(ptr) if HeapManager::get_string(ptr).unwrap() == "Empty"));
+        assert!(matches!(serialize(&None::<u8>, &mut heap_manager).unwrap(), Value::Null));
+    }
+
+    #[test]
+    fn test_integers_beyond_53_bits() {
+        let mut heap_manager = HeapManager::new(1024 * 1024);
+        assert!(matches!(serialize(&(1u64 << 53), &mut heap_manager), Ok(Value::Number(_))));
+        assert!(matches!(serialize(&-(1i64 << 53), &mut heap_manager), Ok(Value::Number(_))));
+        assert!(serialize(&((1u64 << 53) + 1), &mut heap_manager).unwrap_err().contains("too large"));
+        assert!(serialize(&i64::MIN, &mut heap_manager).unwrap_err().contains("too large"));
+        assert!(serialize(&vec![u64::MAX], &mut heap_manager).is_err());
+    }
+
+    #[test]
+    fn test_conversion_errors() {
+        let mut heap_manager = HeapManager::new(1024 * 1024);
+        let string = Value::String(heap_manager.allocate_string("text".to_string()));
+        assert!(deserialize::<f64>(&string).unwrap_err().contains("Expected a number"));
+        assert!(deserialize::<u8>(&Value::Number(1.5)).unwrap_err().contains("Expected u8"));
+        assert!(deserialize::<u8>(&Value::Number(256f64)).is_err());
+        assert!(deserialize::<u32>(&Value::Number(-1f64)).is_err());
+        assert!(deserialize::<Drawing>(&Value::Null).is_err());
+        assert!(deserialize::<Shape>(&Value::Number(1f64)).is_err());
+
+        let partial = serialize(&HashMap::from([("name", "plan")]), &mut heap_manager).unwrap();
+        assert!(deserialize::<Drawing>(&partial).unwrap_err().contains("missing field"));
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct Node {
+        #[serde(rename = "self")]
+        _next: Box<Node>
+    }
+
+    #[test]
+    fn test_cycles_are_errors() {
+        let mut heap_manager = HeapManager::new(1024 * 1024);
+        // o's self = o;
+        let obj_ptr = heap_manager.allocate_object(HashMap::new());
+        let key = heap_manager.allocate_string("self".to_string());
+        heap_manager.set_property_for_object(obj_ptr, key, Value::Object(obj_ptr)).unwrap();
+        assert_eq!(deserialize::<Node>(&Value::Object(obj_ptr)).unwrap_err(), "Cannot convert a cyclic structure to Rust data");
+        assert!(deserialize::<IgnoredAny>(&Value::Object(obj_ptr)).is_err());
+
+        let arr_ptr = heap_manager.allocate_array(vec![Value::Null]);
+        heap_manager.set_property_for_array_num(arr_ptr, 0, Value::Array(arr_ptr)).unwrap();
+        assert!(deserialize::<IgnoredAny>(&Value::Array(arr_ptr)).unwrap_err().contains("cyclic"));
+
+        // a value reached twice without a cycle is read both times
+        let shared = Value::Array(heap_manager.allocate_array(vec![Value::Number(2f64)]));
+        let twice = Value::Array(heap_manager.allocate_array(vec![shared.clone(), shared]));
+        assert_eq!(deserialize::<Vec<Vec<f64>>>(&twice).unwrap(), vec![vec![2f64], vec![2f64]]);
+    }
+}
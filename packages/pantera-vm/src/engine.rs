@@ -0,0 +1,432 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use pantera_compiler::compiler::Compiler;
+use pantera_heap::heap::HeapManager;
+use pantera_heap::runtime::Runtime;
+use pantera_heap::stats::HeapStats;
+use pantera_heap::value::{BuiltinResult, FunctionValue, Value};
+use pantera_parser::lexer::Lexer;
+use pantera_parser::parser::Parser;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use pantera_std::{init_vm_globals, set_args};
+use crate::gc::GC;
+use crate::limits::Limits;
+use crate::data::{deserialize, serialize};
+use crate::output::Buffer;
+use crate::scheduler::Scheduler;
+use crate::vm::VM;
+use crate::error::Error;
+use crate::{ExecutionResult, Options};
+
+pub(crate) type NativeFunction = Rc<RefCell<dyn FnMut(&mut dyn Runtime) -> BuiltinResult>>;
+
+/// Runs scripts with host functions available as globals, next to the std lib.
+///
+/// ```ignore
+/// let mut count = 0;
+/// let engine = Engine::new()
+///     .register("double", 1, |args| Ok(args.get::<f64>(0)? * 2.0))
+///     .register("next_id", 0, move |_args| { count += 1; Ok(count as f64) });
+/// engine.execute("print double(next_id());")?;
+/// ```
+#[derive(Default)]
+pub struct Engine {
+    natives: Vec<(String, NativeFunction)>
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Exposes `function` to scripts as the global `name`, taking exactly `arity` arguments.
+    /// Registering a std name replaces the std function.
+    pub fn register<R, F>(mut self, name: &str, arity: usize, mut function: F) -> Self
+    where R: IntoValue, F: FnMut(&Args) -> Result<R, String> + 'static {
+        let func_name = name.to_string();
+        let native = move |runtime: &mut dyn Runtime| -> BuiltinResult {
+            let arg_count = runtime.arg_count();
+            if arg_count != arity {
+                return Err(format!("{func_name} expects {arity} arguments but got {arg_count}"));
+            }
+            let stack = runtime.stack();
+            let mut values: Vec<Value> = (0..arity).map(|_| stack.pop().unwrap()).collect();
+            values.reverse();
+
+            let result = function(&Args { name: &func_name, values })?;
+            let value = result.into_value(&mut runtime.heap().borrow_mut());
+            runtime.stack().push(value);
+            Ok(())
+        };
+        self.natives.retain(|(registered, _native)| registered != name);
+        self.natives.push((name.to_string(), Rc::new(RefCell::new(native))));
+        self
+    }
+
+    pub fn execute(&self, string: &str) -> Result<ExecutionResult, Error> {
+        self.execute_with_options(string, Options::default())
+    }
+
+    pub fn execute_with_options(&self, string: &str, options: Options) -> Result<ExecutionResult, Error> {
+        let script = self.load(string, options)?;
+        Ok(ExecutionResult { output: script.output(), exit_code: script.exit_code(), gc_stats: script.gc_stats() })
+    }
+
+    /// Compiles and runs a script, keeping it around so that the host can call its functions
+    pub fn load(&self, string: &str, options: Options) -> Result<Script, Error> {
+        let lexer = Lexer::new(string);
+        let tokens = lexer.scan_tokens().map_err(|err| Error::Compile(err.get_message()))?;
+        let parser = Parser::new(tokens);
+
+        let heap_manager = Rc::new(RefCell::new(HeapManager::new(options.max_heap_size)));
+
+        let mut compiler = Compiler::new(Rc::clone(&heap_manager));
+        if let Some(capabilities) = &options.capabilities {
+            compiler.allow_capabilities(capabilities);
+        }
+        let keys: Vec<u16> = self.natives.iter().map(|(name, _native)| compiler.declare_global(name)).collect();
+        let code = compiler.compile(parser).map_err(Error::Compile)?;
+        let mut globals = init_vm_globals();
+        set_args(&mut globals, &heap_manager, &options.args);
+        for (index, key) in keys.into_iter().enumerate() {
+            globals.insert(key, Value::Function(FunctionValue::Native(index)));
+        }
+        let gc = GC::new(Rc::clone(&heap_manager), options.max_heap_size, options.nursery_size, options.gc_mode);
+        let buffer = Buffer::new();
+        let output = options.output.unwrap_or_else(|| Box::new(buffer.clone()));
+        let mut vm = VM::new(code, globals, gc, Rc::clone(&heap_manager), Scheduler::new(options.clock, options.input), output);
+        vm.natives = self.natives.iter().map(|(_name, native)| Rc::clone(native)).collect();
+        vm.limits = Limits::new(options.max_instructions, options.max_call_depth, options.deadline, options.interrupt);
+        if let Err(err) = vm.execute() {
+            return Err(script_error(&mut vm, err));
+        }
+        Ok(Script { vm, globals: compiler.globals, buffer })
+    }
+}
+
+/// Tells the limits the VM stopped the script at, and running out of memory, from the errors
+/// it raised
+fn script_error(vm: &mut VM, err: String) -> Error {
+    match vm.limits.exceeded.take() {
+        Some(limit) => Error::Limit(limit),
+        None if vm.out_of_memory => Error::OutOfMemory,
+        None => Error::Runtime(err)
+    }
+}
+
+/// A script that has run, whose global functions the host can still call
+pub struct Script {
+    vm: VM,
+    globals: HashMap<String, u16>,
+    buffer: Buffer
+}
+
+impl Script {
+    /// Everything printed so far, one entry per line. Empty when `Options::output` was set.
+    pub fn output(&self) -> Vec<String> {
+        self.buffer.lines()
+    }
+
+    pub fn exit_code(&self) -> Option<i32> {
+        self.vm.exit_code
+    }
+
+    /// The heap the script's values live in, for `data::serialize`
+    pub fn heap(&self) -> Rc<RefCell<HeapManager>> {
+        self.vm.heap()
+    }
+
+    pub fn gc_stats(&self) -> HeapStats {
+        self.vm.heap().borrow().stats()
+    }
+
+    /// Calls the script's function `name` with `args`, a tuple (or any sequence) of Rust data,
+    /// and converts its result to `R`. Multi-part function names are joined with `_`, so
+    /// `fun check(a)greater_than(b)` is called as `check_greater_than`.
+    pub fn call<A: Serialize, R: DeserializeOwned>(&mut self, name: &str, args: A) -> Result<R, Error> {
+        let value = self.call_value(name, args)?;
+        deserialize(&value).map_err(|err| Error::Call(format!("Couldn't convert the result of {name}: {err}")))
+    }
+
+    /// Like `call`, but returns the result as a raw `Value`. The value isn't rooted: once
+    /// the script runs again, a collection frees it unless the script still references it.
+    /// Convert it before the next call; reading it once it's collected fails with a
+    /// use-after-free `HeapError`.
+    pub fn call_value<A: Serialize>(&mut self, name: &str, args: A) -> Result<Value, Error> {
+        if let Some(code) = self.vm.exit_code {
+            return Err(Error::Call(format!("The script has exited with code {code}")));
+        }
+        let Some(key) = self.globals.get(name).copied() else {
+            return Err(Error::Call(format!("The script has no function {name}")));
+        };
+        let args = match serialize(&args, &mut self.vm.heap().borrow_mut()).map_err(Error::Call)? {
+            Value::Null => vec![],
+            Value::Array(ptr) => HeapManager::get_array(ptr).map_err(|err| Error::Runtime(err.to_string()))?,
+            arg => vec![arg]
+        };
+        self.vm.call_global(key, args).map_err(|err| script_error(&mut self.vm, err))
+    }
+}
+
+/// The arguments a script passed to a registered function
+pub struct Args<'a> {
+    name: &'a str,
+    values: Vec<Value>
+}
+
+impl Args<'_> {
+    /// The argument at `index`, converted to `T`
+    pub fn get<T: FromValue>(&self, index: usize) -> Result<T, String> {
+        let value = self.values.get(index).ok_or_else(|| format!("{} has no argument {}", self.name, index + 1))?;
+        T::from_value(value).ok_or_else(|| format!("{} expects {} as argument {} but got {value}", self.name, T::expected(), index + 1))
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+/// Rust types that registered functions can take as arguments
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> Option<Self>;
+
+    /// Describes the accepted values in error messages, e.g. "a number"
+    fn expected() -> String;
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Option<Self> {
+        Some(value.clone())
+    }
+
+    fn expected() -> String {
+        "any value".to_string()
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Number(num) => Some(*num),
+            _ => None
+        }
+    }
+
+    fn expected() -> String {
+        "a number".to_string()
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Number(num) if num.fract() == 0f64 && num.is_finite() => Some(*num as i64),
+            _ => None
+        }
+    }
+
+    fn expected() -> String {
+        "a whole number".to_string()
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Bool(val) => Some(*val),
+            _ => None
+        }
+    }
+
+    fn expected() -> String {
+        "a bool".to_string()
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::String(ptr) => HeapManager::get_string(*ptr).ok(),
+            _ => None
+        }
+    }
+
+    fn expected() -> String {
+        "a string".to_string()
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Null => Some(None),
+            _ => T::from_value(value).map(Some)
+        }
+    }
+
+    fn expected() -> String {
+        format!("{} or null", T::expected())
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Array(ptr) => HeapManager::get_array(*ptr).ok()?.iter().map(T::from_value).collect(),
+            Value::Range(ptr) => {
+                let range = HeapManager::get_range(*ptr).ok()?;
+                (0..range.len()).map(|index| T::from_value(&Value::Number(range.get(index).unwrap()))).collect()
+            },
+            _ => None
+        }
+    }
+
+    fn expected() -> String {
+        format!("an array of {}", T::expected().trim_start_matches("a ").trim_start_matches("an "))
+    }
+}
+
+/// Rust types that registered functions can return to scripts
+pub trait IntoValue {
+    fn into_value(self, heap_manager: &mut HeapManager) -> Value;
+}
+
+impl IntoValue for Value {
+    fn into_value(self, _heap_manager: &mut HeapManager) -> Value {
+        self
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self, _heap_manager: &mut HeapManager) -> Value {
+        Value::Null
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self, _heap_manager: &mut HeapManager) -> Value {
+        Value::Number(self)
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self, _heap_manager: &mut HeapManager) -> Value {
+        Value::Number(self as f64)
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self, _heap_manager: &mut HeapManager) -> Value {
+        Value::Bool(self)
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self, heap_manager: &mut HeapManager) -> Value {
+        Value::String(heap_manager.allocate_string(self))
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self, heap_manager: &mut HeapManager) -> Value {
+        self.to_string().into_value(heap_manager)
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self, heap_manager: &mut HeapManager) -> Value {
+        match self {
+            Some(value) => value.into_value(heap_manager),
+            None => Value::Null
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self, heap_manager: &mut HeapManager) -> Value {
+        let values = self.into_iter().rev().map(|value| value.into_value(heap_manager)).collect();
+        Value::Array(heap_manager.allocate_array(values))
+    }
+}
+
+impl<T: IntoValue> IntoValue for HashMap<String, T> {
+    fn into_value(self, heap_manager: &mut HeapManager) -> Value {
+        let mut obj = HashMap::new();
+        for (key, value) in self {
+            let key = heap_manager.allocate_string(key);
+            obj.insert(key, value.into_value(heap_manager));
+        }
+        Value::Object(heap_manager.allocate_object(obj))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pantera_heap::heap::HeapError;
+    use crate::data::deserialize;
+    use crate::Capability;
+    use super::*;
+
+    #[test]
+    fn test_lexer_errors_are_compile_errors() {
+        let result = Engine::new().execute("print \"unterminated;");
+        assert!(matches!(result, Err(Error::Compile(message)) if message.starts_with("Parser Error")));
+    }
+
+    #[test]
+    fn test_parser_errors_are_compile_errors() {
+        let result = Engine::new().execute("print (1;");
+        assert!(matches!(result, Err(Error::Compile(_))));
+    }
+
+    fn rejected(source: &str, capabilities: Vec<Capability>) -> String {
+        match Engine::new().load(source, Options { capabilities: Some(capabilities), ..Options::default() }) {
+            Err(Error::Compile(message)) => message,
+            _ => panic!("{source} should not compile")
+        }
+    }
+
+    #[test]
+    fn test_capabilities_are_checked_at_compile_time() {
+        assert!(rejected("print fs's read_file(\"notes.txt\");", vec![Capability::Time]).contains("needs the fs capability"));
+        assert!(rejected("print env(\"HOME\");", vec![Capability::Fs]).contains("env needs the env capability"));
+        assert!(rejected("print args;", vec![Capability::Time]).contains("args needs the env capability"));
+        assert!(rejected("print now();", vec![Capability::Env]).contains("now needs the time capability"));
+    }
+
+    #[test]
+    fn test_granted_capabilities_compile() {
+        let options = Options { capabilities: Some(vec![Capability::Fs, Capability::Time]), ..Options::default() };
+        assert!(Engine::new().load("print fs's exists(\"notes.txt\"); print now();", options).is_ok());
+        assert!(Engine::new().load("print len(\"abc\");", Options { capabilities: Some(vec![]), ..Options::default() }).is_ok());
+    }
+
+    #[test]
+    fn test_running_out_of_memory_is_an_error() {
+        let source = "var list = null; try { loop { list = [list, \"node\"]; } } catch err { print \"caught\"; }";
+        let result = Engine::new().execute_with_options(source, Options { max_heap_size: 4096, ..Options::default() });
+        assert!(matches!(result, Err(Error::OutOfMemory)), "{:?}", result.err());
+    }
+
+    #[test]
+    fn test_reading_collected_values_is_an_error() {
+        let source = "fun label(n) { return \"item \" + to_string(n); }
+            fun churn { var count = 0; loop { if count is 200 { break; } var garbage = [count]; count = count + 1; } }";
+        let mut script = Engine::new().load(source, Options { max_heap_size: 4096, nursery_size: 0, ..Options::default() }).unwrap();
+        let label = script.call_value("label", (7,)).unwrap();
+        assert_eq!(label.to_string(), "item 7");
+
+        script.call_value("churn", ()).unwrap();
+        let Value::String(ptr) = label else { panic!("{label} is not a string") };
+        assert!(!HeapManager::is_live(&label));
+        assert_eq!(HeapManager::get_string(ptr), Err(HeapError::UseAfterFree("string")));
+        assert_eq!(deserialize::<String>(&label).unwrap_err(), "Use after free: the string was already collected");
+        assert_eq!(label.to_string(), "[Use after free: the string was already collected]");
+    }
+}
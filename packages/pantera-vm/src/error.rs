@@ -0,0 +1,31 @@
+use std::fmt::{Display, Formatter};
+use pantera_heap::heap::HeapError;
+use crate::limits::LimitExceeded;
+
+/// Why running a script, or calling one of its functions, failed
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The script doesn't lex, parse or pass the compile-time checks
+    Compile(String),
+    /// An error the script raised and no `try` caught
+    Runtime(String),
+    /// The VM stopped the script at one of the `Options` limits
+    Limit(LimitExceeded),
+    /// The script's live values outgrew `Options::max_heap_size`
+    OutOfMemory,
+    /// The host's call into the script was invalid: an unknown function, a script that has
+    /// exited, or arguments and results that don't convert
+    Call(String)
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Compile(message) | Self::Runtime(message) | Self::Call(message) => f.write_str(message),
+            Self::Limit(limit) => limit.fmt(f),
+            Self::OutOfMemory => HeapError::OutOfMemory.fmt(f)
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -1,86 +1,162 @@
 use std::cell::RefCell;
-use std::collections::HashMap;
 use std::rc::Rc;
-use pantera_heap::heap::{HeapManager, Ptr};
-use pantera_heap::stack::Stack;
+use std::time::Instant;
+use pantera_heap::heap::{Collection, HeapManager};
 use pantera_heap::value::Value;
 use crate::runtime_context::RuntimeContext;
 
 pub const GC_RATE: f64 = 0.8;
 
+/// How the whole heap gets collected
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GcMode {
+    /// In one go, pausing the script for the whole mark and sweep
+    StopTheWorld,
+    /// A bit at a time: after each allocation, the collector traces up to `step` values. If
+    /// the heap still fills up to `GC_RATE` first, the rest of the marking happens at once.
+    Incremental { step: usize }
+}
+
+/// A generational collector. Values start in the nursery, which a minor collection frees
+/// every `nursery_size` allocated bytes, promoting the survivors to the old generation. The
+/// whole heap is only collected once it is `GC_RATE` full. Incremental collections start
+/// earlier, halfway between what was left by the last one and `GC_RATE`, so that the script has
+/// the other half to allocate in while they mark.
 pub(crate) struct GC {
     pub heap_manager: Rc<RefCell<HeapManager>>,
-    pub max_heap_size: usize
+    pub max_heap_size: usize,
+    /// 0 turns minor collections off
+    pub nursery_size: usize,
+    pub mode: GcMode,
+    /// The allocated bytes an incremental collection starts at
+    marking_threshold: usize,
+    /// The children of the last grey value traced, still to be shaded
+    pending: Vec<Value>
 }
 
 impl GC {
-    fn mark(&self, context: &RuntimeContext) -> HashMap<Ptr, bool> {
-        let mut iterable_objects = self.heap_manager.borrow().objects.clone();
-
-        self.mark_globals(&mut iterable_objects, context.globals);
-        self.mark_stack(&mut iterable_objects, context.execution_stack);
+    pub fn new(heap_manager: Rc<RefCell<HeapManager>>, max_heap_size: usize, nursery_size: usize, mode: GcMode) -> Self {
+        let mut gc = Self { heap_manager, max_heap_size, nursery_size, mode, marking_threshold: 0, pending: vec![] };
+        gc.update_marking_threshold();
+        gc
+    }
 
-        iterable_objects
+    fn update_marking_threshold(&mut self) {
+        let limit = (GC_RATE * self.max_heap_size as f64) as usize;
+        let live = self.heap_manager.borrow().allocated_memory;
+        self.marking_threshold = live + limit.saturating_sub(live) / 2;
     }
 
-    fn mark_strings(&self, context: &RuntimeContext) -> HashMap<Ptr, bool> {
-        let mut iterable_strings = self.heap_manager.borrow().interned_strings.iter().filter(|(_str_ptr, is_compiled)| !**is_compiled).map(|(str_ptr, _is_compiled)| (*str_ptr, false)).collect::<HashMap<Ptr, bool>>();
+    fn roots(&self, context: &RuntimeContext) -> Vec<Value> {
+        let mut roots: Vec<Value> = context.globals.values().cloned().collect();
+        roots.extend(context.execution_stack.elements.iter().cloned());
+        roots.extend(context.generators.iter().map(|(ptr, _offset)| Value::Generator(*ptr)));
+        roots.extend(context.scheduler.roots());
 
-        self.mark_globals(&mut iterable_strings, context.globals);
-        self.mark_stack(&mut iterable_strings, context.execution_stack);
+        roots
+    }
 
-        iterable_strings
+    fn mark(&self, context: &RuntimeContext, collection: Collection) {
+        self.roots(context).iter().for_each(|val| self.mark_value(val, collection));
     }
 
-    fn mark_globals(&self, objects: &mut HashMap<Ptr, bool>, globals: &HashMap<u16, Value>) {
-        for (_key, val) in globals.iter() {
-            self.mark_value(val, objects);
+    fn mark_value(&self, value: &Value, collection: Collection) {
+        if self.heap_manager.borrow_mut().mark(value, collection) {
+            self.mark_children(value, collection);
         }
     }
 
-    fn mark_stack(&self, objects: &mut HashMap<Ptr, bool>, execution_stack: &Stack) {
-        execution_stack.elements.iter().for_each(|val| self.mark_value(val, objects));
+    fn mark_children(&self, value: &Value, collection: Collection) {
+        // object keys built at runtime (e.g. by json_parse) aren't compiled strings, so they're
+        // children too
+        HeapManager::get_children(value).iter().for_each(|val| self.mark_value(val, collection));
     }
 
-    fn mark_value(&self, value: &Value, objects: &mut HashMap<Ptr, bool>) {
-        match value {
-            Value::String(ptr) => {
-                objects.insert(*ptr, true);
-            }
-            Value::Object(ptr) => {
-                let object = HeapManager::get_object(*ptr);
-                object.values().for_each(|val| self.mark_value(val, objects));
-                objects.insert(*ptr, true);
-            }
-            _ => {}
-        }
+    fn collect_young(&mut self, context: &RuntimeContext) {
+        self.mark(context, Collection::Minor);
+        let remembered = self.heap_manager.borrow_mut().take_remembered();
+        remembered.iter().for_each(|val| self.mark_children(val, Collection::Minor));
+
+        self.heap_manager.borrow_mut().sweep(Collection::Minor);
     }
 
-    fn sweep_objects(&mut self, objects: HashMap<Ptr, bool>) {
-        for (key, value) in objects {
-            if !value {
-                self.heap_manager.borrow_mut().free_object(key);
+    /// Shades up to `step` values, taking the children of the next grey value when out of them,
+    /// so that large arrays and objects are traced over several steps. Returns whether marking
+    /// is done.
+    fn mark_step(&mut self, step: usize) -> bool {
+        for _ in 0..step {
+            if let Some(child) = self.pending.pop() {
+                self.heap_manager.borrow_mut().shade(&child);
+                continue;
             }
+
+            let Some(value) = self.heap_manager.borrow_mut().take_grey() else {
+                return true;
+            };
+            self.pending = HeapManager::get_children(&value);
         }
+
+        false
     }
 
-    fn sweep_strings(&mut self, strings: HashMap<Ptr, bool>) {
-        for (key, value) in strings {
-            if !value {
-                self.heap_manager.borrow_mut().free_string(key);
-            }
+    fn continue_marking(&mut self, context: &RuntimeContext, step: usize) {
+        let behind = self.heap_manager.borrow().allocated_memory > (GC_RATE * self.max_heap_size as f64) as usize;
+        if !self.mark_step(if behind { usize::MAX } else { step }) {
+            return;
         }
+
+        // the roots have no write barrier, so whatever they hold now is shaded before sweeping
+        self.roots(context).iter().for_each(|val| self.heap_manager.borrow_mut().shade(val));
+        self.mark_step(usize::MAX);
+        self.heap_manager.borrow_mut().sweep(Collection::Major);
+        self.update_marking_threshold();
     }
 
     pub fn collect(&mut self, context: &RuntimeContext) {
-        if (self.heap_manager.borrow().allocated_memory) <= (GC_RATE * self.max_heap_size as f64) as usize {
-            return;
+        let start = Instant::now();
+        if self.collect_due(context) {
+            self.heap_manager.borrow_mut().record_pause(start.elapsed());
+        }
+    }
+
+    /// Does the collecting that's due, if any. Returns whether there was some.
+    fn collect_due(&mut self, context: &RuntimeContext) -> bool {
+        if let GcMode::Incremental { step } = self.mode {
+            // minor sweeps would unmark young values the collection already traced
+            if self.heap_manager.borrow().is_marking() {
+                self.continue_marking(context, step);
+                return true;
+            }
+        }
+
+        let mut collected = false;
+        if self.nursery_size > 0 && self.heap_manager.borrow().nursery_memory >= self.nursery_size {
+            self.collect_young(context);
+            collected = true;
         }
 
-        let unmarked_objects = self.mark(context);
-        let unmarked_strings = self.mark_strings(context);
+        let allocated_memory = self.heap_manager.borrow().allocated_memory;
+        match self.mode {
+            GcMode::StopTheWorld => {
+                if allocated_memory <= (GC_RATE * self.max_heap_size as f64) as usize {
+                    return collected;
+                }
+
+                self.mark(context, Collection::Major);
+                self.heap_manager.borrow_mut().sweep(Collection::Major);
+            },
+            GcMode::Incremental { step } => {
+                if allocated_memory <= self.marking_threshold {
+                    return collected;
+                }
+
+                self.heap_manager.borrow_mut().start_marking();
+                self.roots(context).iter().for_each(|val| self.heap_manager.borrow_mut().shade(val));
+                self.continue_marking(context, step);
+            }
+        }
 
-        self.sweep_objects(unmarked_objects);
-        self.sweep_strings(unmarked_strings);
+        true
     }
 }
+
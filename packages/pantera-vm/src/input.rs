@@ -0,0 +1,141 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
+
+/// Where `input()` reads lines from. Closures returning an `Option<String>` are inputs too.
+pub trait Input {
+    /// The next line, without its line ending, or `None` once the input has ended
+    fn read_line(&mut self) -> Option<String>;
+
+    /// Like `read_line`, but returns `None` instead of blocking when no line is ready yet,
+    /// so that other tasks keep running meanwhile
+    fn try_read_line(&mut self) -> Option<Option<String>> {
+        Some(self.read_line())
+    }
+}
+
+impl<F: FnMut() -> Option<String>> Input for F {
+    fn read_line(&mut self) -> Option<String> {
+        self()
+    }
+}
+
+fn trim_line_ending(mut line: String) -> String {
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    line
+}
+
+/// Reads the process's stdin, one line at a time on a background thread
+#[derive(Default)]
+pub struct StdInput {
+    pending: Option<Receiver<Option<String>>>
+}
+
+impl StdInput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn pending(&mut self) -> &Receiver<Option<String>> {
+        self.pending.get_or_insert_with(|| {
+            let (sender, receiver) = channel();
+            thread::spawn(move || {
+                let mut line = String::new();
+                let line = match io::stdin().read_line(&mut line) {
+                    Ok(0) | Err(_) => None,
+                    Ok(_) => Some(trim_line_ending(line))
+                };
+                let _ = sender.send(line);
+            });
+            receiver
+        })
+    }
+}
+
+impl Input for StdInput {
+    fn read_line(&mut self) -> Option<String> {
+        let line = self.pending().recv().unwrap_or_default();
+        self.pending = None;
+        line
+    }
+
+    fn try_read_line(&mut self) -> Option<Option<String>> {
+        let line = match self.pending().try_recv() {
+            Ok(line) => line,
+            Err(TryRecvError::Disconnected) => None,
+            Err(TryRecvError::Empty) => return None
+        };
+        self.pending = None;
+        Some(line)
+    }
+}
+
+/// Lines given up front, e.g. by tests
+#[derive(Default)]
+pub struct Lines(VecDeque<String>);
+
+impl Lines {
+    pub fn new<S: Into<String>>(lines: impl IntoIterator<Item = S>) -> Self {
+        Self(lines.into_iter().map(|line| line.into()).collect())
+    }
+}
+
+impl Input for Lines {
+    fn read_line(&mut self) -> Option<String> {
+        self.0.pop_front()
+    }
+}
+
+/// Reads lines from any reader, such as a file of recorded input. Read errors end the input.
+pub struct Reader<R: BufRead>(pub R);
+
+impl<R: BufRead> Input for Reader<R> {
+    fn read_line(&mut self) -> Option<String> {
+        let mut line = String::new();
+        match self.0.read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(trim_line_ending(line))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{execute_with_options, Options};
+    use super::*;
+
+    #[test]
+    fn test_lines_feed_input() {
+        let options = Options { input: Box::new(Lines::new(["first", "second"])), ..Options::default() };
+        let result = execute_with_options("print input(); print input();", options).unwrap();
+
+        assert_eq!(result.output, vec!["first", "second"]);
+    }
+
+    #[test]
+    fn test_input_is_null_once_lines_run_out() {
+        let options = Options { input: Box::new(Lines::new(["only"])), ..Options::default() };
+        let result = execute_with_options("print input(); print input(); print is_null(input());", options).unwrap();
+
+        assert_eq!(result.output, vec!["only", "null", "true"]);
+    }
+
+    #[test]
+    fn test_closures_are_inputs() {
+        let mut count = 0;
+        let input = move || {
+            count += 1;
+            (count <= 2).then(|| count.to_string())
+        };
+        let options = Options { input: Box::new(input), ..Options::default() };
+        let result = execute_with_options("loop { var line = input(); if is_null(line) { break; } print line; }", options).unwrap();
+
+        assert_eq!(result.output, vec!["1", "2"]);
+    }
+}
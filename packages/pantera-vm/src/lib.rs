@@ -1,41 +1,73 @@
 mod gc;
+pub mod error;
 mod runtime_context;
+mod scheduler;
+pub mod clock;
+pub mod data;
+pub mod engine;
+pub mod input;
+pub mod limits;
+pub mod output;
 pub mod vm;
 
-use std::cell::RefCell;
-use std::rc::Rc;
-use pantera_compiler::compiler::Compiler;
-use pantera_heap::heap::HeapManager;
-use pantera_heap::stack::Stack;
-use pantera_parser::lexer::Lexer;
-use pantera_parser::parser::Parser;
-use pantera_std::init_vm_globals;
-use crate::gc::GC;
-use crate::vm::VM;
+use std::time::Instant;
+use pantera_heap::stats::HeapStats;
+pub use pantera_std::Capability;
+pub use crate::error::Error;
+pub use crate::gc::GcMode;
+use crate::clock::{Clock, SystemClock};
+use crate::engine::Engine;
+use crate::input::{Input, StdInput};
+use crate::limits::Interrupt;
+use crate::output::Output;
 
 pub struct Options {
-    pub max_heap_size: usize
+    pub max_heap_size: usize,
+    /// How many bytes the script allocates between two collections of the young values; 0
+    /// collects the whole heap every time
+    pub nursery_size: usize,
+    /// Whether collecting the whole heap pauses the script until it's done
+    pub gc_mode: GcMode,
+    pub clock: Box<dyn Clock>,
+    /// Where `input()` reads lines from
+    pub input: Box<dyn Input>,
+    /// Exposed to the script as the `args` global
+    pub args: Vec<String>,
+    /// Receives the output while the script runs. Without one, the output is collected
+    /// into `ExecutionResult::output`.
+    pub output: Option<Box<dyn Output>>,
+    /// Stops the script after this many instructions
+    pub max_instructions: Option<u64>,
+    /// Stops the script when a task nests more calls than this
+    pub max_call_depth: Option<usize>,
+    /// Stops the script once this instant has passed
+    pub deadline: Option<Instant>,
+    /// Stops the script when triggered, from any thread
+    pub interrupt: Option<Interrupt>,
+    /// The capabilities the script's std entries may use; `None` allows them all
+    pub capabilities: Option<Vec<Capability>>
 }
 
-pub fn execute(string: &str) -> Result<Vec<String>, String> {
-    let max_heap_size = 10 * 1024;
-    execute_with_options(string, Options { max_heap_size })
+pub struct ExecutionResult {
+    /// Everything the script printed, one entry per line. Empty when `Options::output` was set.
+    pub output: Vec<String>,
+    /// Set when the script stopped itself with `exit(code)`
+    pub exit_code: Option<i32>,
+    /// The heap's counters at the end of the script
+    pub gc_stats: HeapStats
 }
 
-pub fn execute_with_options(string: &str, options: Options) -> Result<Vec<String>, String> {
-    let lexer = Lexer::new(&string);
-    let parser = Parser::new(lexer.scan_tokens().unwrap());
+impl Default for Options {
+    /// A 10KB heap with a 2KB nursery collected stop-the-world, the system clock, stdin and buffered output
+    fn default() -> Self {
+        Self { max_heap_size: 10 * 1024, nursery_size: 2 * 1024, gc_mode: GcMode::StopTheWorld, clock: Box::new(SystemClock::new()), input: Box::new(StdInput::new()), args: vec![], output: None, max_instructions: None, max_call_depth: None, deadline: None, interrupt: None, capabilities: None }
+    }
+}
 
-    let heap_manager = Rc::new(RefCell::new(HeapManager::new(options.max_heap_size)));
+pub fn execute(string: &str) -> Result<ExecutionResult, Error> {
+    Engine::new().execute(string)
+}
 
-    let compiler = Compiler::new(Rc::clone(&heap_manager));
-    let code = compiler.compile(parser)?;
-    let mut execution_stack = Stack::init();
-    let mut globals = init_vm_globals();
-    let mut gc = GC {
-        heap_manager: Rc::clone(&heap_manager),
-        max_heap_size: options.max_heap_size
-    };
-    let mut vm = VM::new(code, &mut execution_stack, &mut globals, &mut gc, Rc::clone(&heap_manager));
-    vm.execute()
-}
\ No newline at end of file
+pub fn execute_with_options(string: &str, options: Options) -> Result<ExecutionResult, Error> {
+    Engine::new().execute_with_options(string, options)
+}
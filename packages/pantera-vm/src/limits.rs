@@ -0,0 +1,207 @@
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How many instructions run between two checks of the deadline and the interrupt flag
+const CHECK_INTERVAL: u64 = 256;
+/// The longest the scheduler waits on sleepers or input between two checks of the deadline and
+/// the interrupt flag
+const WAIT_SLICE: Duration = Duration::from_millis(10);
+
+/// Why the VM stopped a script. Scripts cannot catch these errors with `try`; the host gets
+/// them back from `execute` as `Error::Limit`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LimitExceeded {
+    Instructions,
+    CallDepth,
+    Deadline,
+    Interrupted
+}
+
+impl Display for LimitExceeded {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Instructions => "Instruction limit exceeded",
+            Self::CallDepth => "Call depth limit exceeded",
+            Self::Deadline => "Deadline exceeded",
+            Self::Interrupted => "Execution interrupted"
+        })
+    }
+}
+
+/// Stops a running script from another thread
+#[derive(Debug, Clone, Default)]
+pub struct Interrupt(Arc<AtomicBool>);
+
+impl Interrupt {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Limits {
+    max_instructions: Option<u64>,
+    max_call_depth: Option<usize>,
+    deadline: Option<Instant>,
+    interrupt: Option<Interrupt>,
+    instructions: u64,
+    /// Set once a limit is hit, so that the error goes past every `try`
+    pub exceeded: Option<LimitExceeded>
+}
+
+impl Limits {
+    pub fn new(max_instructions: Option<u64>, max_call_depth: Option<usize>, deadline: Option<Instant>, interrupt: Option<Interrupt>) -> Self {
+        Self { max_instructions, max_call_depth, deadline, interrupt, ..Self::default() }
+    }
+
+    /// Counts an instruction about to run
+    pub fn count_instruction(&mut self) -> Result<(), String> {
+        self.instructions += 1;
+        if self.max_instructions.is_some_and(|max| self.instructions > max) {
+            return self.exceed(LimitExceeded::Instructions);
+        }
+        if self.instructions.is_multiple_of(CHECK_INTERVAL) {
+            return self.check_stop();
+        }
+        Ok(())
+    }
+
+    /// Fails once the script is interrupted or past its deadline
+    pub fn check_stop(&mut self) -> Result<(), String> {
+        if self.interrupt.as_ref().is_some_and(|interrupt| interrupt.is_interrupted()) {
+            return self.exceed(LimitExceeded::Interrupted);
+        }
+        if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return self.exceed(LimitExceeded::Deadline);
+        }
+        Ok(())
+    }
+
+    /// Whether a deadline or an interrupt flag can stop the script while it waits
+    pub fn can_stop(&self) -> bool {
+        self.deadline.is_some() || self.interrupt.is_some()
+    }
+
+    /// How long to wait before calling `check_stop` again
+    pub fn wait_slice(&self) -> Duration {
+        match self.deadline {
+            Some(deadline) => WAIT_SLICE.min(deadline.saturating_duration_since(Instant::now())),
+            None => WAIT_SLICE
+        }
+    }
+
+    /// Checks a call that would make the current task `depth` frames deep
+    pub fn enter_call(&mut self, depth: usize) -> Result<(), String> {
+        if self.max_call_depth.is_some_and(|max| depth > max) {
+            return self.exceed(LimitExceeded::CallDepth);
+        }
+        Ok(())
+    }
+
+    fn exceed(&mut self, limit: LimitExceeded) -> Result<(), String> {
+        self.exceeded = Some(limit);
+        Err(limit.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+    use crate::error::Error;
+    use crate::input::Input;
+    use crate::{execute_with_options, Options};
+    use super::*;
+
+    /// Input that never has a line ready
+    struct Silent;
+
+    impl Input for Silent {
+        fn read_line(&mut self) -> Option<String> {
+            thread::sleep(Duration::from_secs(60));
+            None
+        }
+
+        fn try_read_line(&mut self) -> Option<Option<String>> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_instruction_limit() {
+        let options = Options { max_instructions: Some(1000), ..Options::default() };
+        let err = execute_with_options("loop { }", options).err().unwrap();
+
+        assert_eq!(err, Error::Limit(LimitExceeded::Instructions));
+    }
+
+    #[test]
+    fn test_call_depth_limit() {
+        let options = Options { max_call_depth: Some(50), ..Options::default() };
+        let err = execute_with_options("fun down(n) { return down(n + 1); } down(0);", options).err().unwrap();
+
+        assert_eq!(err, Error::Limit(LimitExceeded::CallDepth));
+    }
+
+    #[test]
+    fn test_deadline() {
+        let options = Options { deadline: Some(Instant::now() + Duration::from_millis(50)), ..Options::default() };
+        let err = execute_with_options("loop { }", options).err().unwrap();
+
+        assert_eq!(err, Error::Limit(LimitExceeded::Deadline));
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let interrupt = Interrupt::new();
+        interrupt.interrupt();
+        let options = Options { interrupt: Some(interrupt), ..Options::default() };
+        let err = execute_with_options("loop { }", options).err().unwrap();
+
+        assert_eq!(err, Error::Limit(LimitExceeded::Interrupted));
+    }
+
+    #[test]
+    fn test_limits_cannot_be_caught() {
+        let options = Options { max_instructions: Some(1000), ..Options::default() };
+        let err = execute_with_options("try { loop { } } catch err { print err; }", options).err().unwrap();
+
+        assert_eq!(err, Error::Limit(LimitExceeded::Instructions));
+    }
+
+    #[test]
+    fn test_deadline_stops_sleeping_scripts() {
+        let start = Instant::now();
+        let options = Options { deadline: Some(start + Duration::from_millis(100)), ..Options::default() };
+
+        let err = execute_with_options("sleep(5); print 1;", options).err().unwrap();
+
+        assert_eq!(err, Error::Limit(LimitExceeded::Deadline));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_interrupt_stops_scripts_waiting_for_input() {
+        let interrupt = Interrupt::new();
+        let trigger = interrupt.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            trigger.interrupt();
+        });
+        let options = Options { input: Box::new(Silent), interrupt: Some(interrupt), ..Options::default() };
+
+        let err = execute_with_options("print input();", options).err().unwrap();
+
+        assert_eq!(err, Error::Limit(LimitExceeded::Interrupted));
+    }
+}
@@ -0,0 +1,97 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// Where a script's output goes. `print` writes its value followed by a newline as soon
+/// as it runs. Closures taking a `&str` are outputs too.
+pub trait Output {
+    fn write(&mut self, text: &str);
+
+    /// Used by `eprint`
+    fn write_error(&mut self, text: &str) {
+        eprint!("{text}");
+    }
+}
+
+impl<F: FnMut(&str)> Output for F {
+    fn write(&mut self, text: &str) {
+        self(text)
+    }
+}
+
+pub struct StdOutput;
+
+impl Output for StdOutput {
+    fn write(&mut self, text: &str) {
+        let mut stdout = io::stdout().lock();
+        // a closed stdout (e.g. piping into `head`) shouldn't stop the script
+        let _ = stdout.write_all(text.as_bytes()).and_then(|_| stdout.flush());
+    }
+}
+
+/// Keeps the output in memory. Clones share the same buffer, so a clone can be handed to
+/// `Options` and the original read once the script is done.
+#[derive(Clone, Default)]
+pub struct Buffer {
+    text: Rc<RefCell<String>>
+}
+
+impl Buffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text(&self) -> String {
+        self.text.borrow().clone()
+    }
+
+    /// The output split into lines, without the newline that ends the last one
+    pub fn lines(&self) -> Vec<String> {
+        let text = self.text.borrow();
+        if text.is_empty() {
+            return vec![];
+        }
+        text.strip_suffix('\n').unwrap_or(&text).split('\n').map(|line| line.to_string()).collect()
+    }
+}
+
+impl Output for Buffer {
+    fn write(&mut self, text: &str) {
+        self.text.borrow_mut().push_str(text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{execute_with_options, Options};
+    use super::*;
+
+    #[test]
+    fn test_buffer_receives_output() {
+        let buffer = Buffer::new();
+        let options = Options { output: Some(Box::new(buffer.clone())), ..Options::default() };
+        let result = execute_with_options("write(\"a\"); write(1); print \"b\"; print [1, 2];", options).unwrap();
+
+        assert_eq!(buffer.text(), "a1b\n[ 1, 2 ]\n");
+        assert_eq!(buffer.lines(), vec!["a1b", "[ 1, 2 ]"]);
+        // the output went to the buffer instead
+        assert!(result.output.is_empty());
+    }
+
+    #[test]
+    fn test_buffer_lines_keep_an_unfinished_line() {
+        let buffer = Buffer::new();
+        let options = Options { output: Some(Box::new(buffer.clone())), ..Options::default() };
+        execute_with_options("print 1; write(2);", options).unwrap();
+
+        assert_eq!(buffer.lines(), vec!["1", "2"]);
+        assert!(Buffer::new().lines().is_empty());
+    }
+
+    #[test]
+    fn test_output_without_options_is_collected() {
+        let result = execute_with_options("print 1; write(\"x\"); print 2;", Options::default()).unwrap();
+
+        assert_eq!(result.output, vec!["1", "x2"]);
+    }
+}
@@ -1,8 +1,13 @@
 use std::collections::HashMap;
+use pantera_heap::arena::Handle;
+use pantera_heap::generator::Generator;
 use pantera_heap::stack::Stack;
 use pantera_heap::value::Value;
+use crate::scheduler::Scheduler;
 
 pub struct RuntimeContext<'a> {
     pub execution_stack: &'a mut Stack,
     pub globals: &'a mut HashMap<u16, Value>,
+    pub generators: &'a [(Handle<Generator>, usize)],
+    pub scheduler: &'a Scheduler,
 }
\ No newline at end of file
@@ -0,0 +1,295 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::thread;
+use pantera_heap::arena::Handle;
+use pantera_heap::generator::Generator;
+use pantera_heap::stack::Stack;
+use pantera_heap::value::Value;
+use crate::clock::Clock;
+use crate::input::Input;
+use crate::limits::Limits;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum TaskState {
+    Ready,
+    Running,
+    Sleeping(u64),
+    Receiving(usize),
+    Awaiting(usize),
+    Input,
+    Done
+}
+
+/// An active `try` block: where its handler starts and the frame to unwind to.
+pub(crate) struct Handler {
+    pub ip: usize,
+    pub stack_len: usize,
+    pub offset: usize,
+    pub generator_frames: usize,
+    pub call_depth: usize
+}
+
+/// A cooperative task. While a task runs, its stack, ip and generator frames live in the VM;
+/// they are swapped back in here whenever it suspends, along with its active `try` handlers.
+pub(crate) struct Task {
+    pub stack: Stack,
+    pub ip: usize,
+    pub generator_frames: Vec<(Handle<Generator>, usize)>,
+    pub call_depth: usize,
+    pub handlers: Vec<Handler>,
+    pub state: TaskState,
+    pub result: Value
+}
+
+impl Task {
+    fn new(stack: Stack, ip: usize, state: TaskState, call_depth: usize) -> Self {
+        Self {
+            stack,
+            ip,
+            generator_frames: vec![],
+            call_depth,
+            handlers: vec![],
+            state,
+            result: Value::Null
+        }
+    }
+}
+
+/// Tasks and channels are kept by id. Ids aren't reused, so a script holding on to the id of
+/// a task or channel that was freed can't reach another one by mistake.
+pub(crate) struct Scheduler {
+    /// Finished tasks stay until they're awaited, then their slot and result are freed
+    tasks: BTreeMap<usize, Task>,
+    /// Only channels with queued values, the others are dropped once drained
+    channels: HashMap<usize, VecDeque<Value>>,
+    next_task: usize,
+    next_channel: usize,
+    pub current: usize,
+    pub clock: Box<dyn Clock>,
+    pub input: Box<dyn Input>,
+    /// A line read for the tasks waiting on `input()`; `Some(None)` is the end of the input
+    line: Option<Option<String>>
+}
+
+impl Scheduler {
+    pub fn new(clock: Box<dyn Clock>, input: Box<dyn Input>) -> Self {
+        Self {
+            tasks: BTreeMap::from([(0, Task::new(Stack::init(), 0, TaskState::Running, 0))]),
+            channels: HashMap::new(),
+            next_task: 1,
+            next_channel: 0,
+            current: 0,
+            clock,
+            input,
+            line: None
+        }
+    }
+
+    /// Adds a task that starts inside the frame of the spawned function
+    pub fn spawn(&mut self, stack: Stack, ip: usize) -> usize {
+        let id = self.next_task;
+        self.next_task += 1;
+        self.tasks.insert(id, Task::new(stack, ip, TaskState::Ready, 1));
+        id
+    }
+
+    pub fn task(&self, id: usize) -> Option<&Task> {
+        self.tasks.get(&id)
+    }
+
+    pub fn task_mut(&mut self, id: usize) -> &mut Task {
+        self.tasks.get_mut(&id).expect("the tasks of the running script aren't freed")
+    }
+
+    /// The result of a finished task, for one of the tasks awaiting it. The task is freed
+    /// once no other task is waiting on it.
+    pub fn take_result(&mut self, id: usize) -> Value {
+        let awaited = self.tasks.values().any(|task| task.state == TaskState::Awaiting(id));
+        if awaited {
+            return self.tasks[&id].result.clone();
+        }
+        self.tasks.remove(&id).map_or(Value::Null, |task| task.result)
+    }
+
+    pub fn open_channel(&mut self) -> usize {
+        self.next_channel += 1;
+        self.next_channel - 1
+    }
+
+    pub fn send(&mut self, channel: usize, val: Value) {
+        self.channels.entry(channel).or_default().push_back(val);
+    }
+
+    pub fn receive(&mut self, channel: usize) -> Option<Value> {
+        let queue = self.channels.get_mut(&channel)?;
+        let val = queue.pop_front();
+        if queue.is_empty() {
+            self.channels.remove(&channel);
+        }
+        val
+    }
+
+    /// The line read for a task waiting on `input()`, `None` at the end of the input
+    pub fn take_line(&mut self) -> Option<String> {
+        self.line.take().flatten()
+    }
+
+    fn is_waiting_for_input(&self) -> bool {
+        self.tasks.values().any(|task| task.state == TaskState::Input)
+    }
+
+    fn poll_input(&mut self, block: bool) {
+        if self.line.is_some() || !self.is_waiting_for_input() {
+            return;
+        }
+        self.line = if block { Some(self.input.read_line()) } else { self.input.try_read_line() };
+    }
+
+    fn is_runnable(&self, id: usize) -> bool {
+        match self.tasks[&id].state {
+            TaskState::Ready | TaskState::Running => true,
+            TaskState::Sleeping(time) => self.clock.now() >= time,
+            TaskState::Receiving(channel) => self.channels.contains_key(&channel),
+            TaskState::Awaiting(task) => self.tasks.get(&task).is_none_or(|task| task.state == TaskState::Done),
+            TaskState::Input => self.line.is_some(),
+            TaskState::Done => false
+        }
+    }
+
+    /// Picks the next task to run, round-robin after the current one. When nothing is
+    /// runnable it waits for the earliest sleeper or for pending input, in slices when the
+    /// `limits` can stop the script meanwhile.
+    pub fn next_task(&mut self, limits: &mut Limits) -> Result<Option<usize>, String> {
+        loop {
+            self.poll_input(false);
+            let mut ids = self.tasks.range(self.current + 1..).chain(self.tasks.range(..=self.current)).map(|(id, _task)| *id);
+            if let Some(id) = ids.find(|id| self.is_runnable(*id)) {
+                return Ok(Some(id));
+            }
+
+            if self.tasks.values().all(|task| task.state == TaskState::Done) {
+                return Ok(None);
+            }
+
+            let wake_up = self.tasks.values().filter_map(|task| match task.state {
+                TaskState::Sleeping(time) => Some(time),
+                _ => None
+            }).min();
+            limits.check_stop()?;
+            if let Some(time) = wake_up {
+                self.clock.sleep_at_most(time, limits.wait_slice());
+            } else if self.is_waiting_for_input() {
+                if !limits.can_stop() {
+                    self.poll_input(true);
+                } else if self.line.is_none() {
+                    thread::sleep(limits.wait_slice());
+                }
+            } else {
+                return Err("Deadlock: every task is blocked".to_string());
+            }
+        }
+    }
+
+    pub fn roots(&self) -> Vec<Value> {
+        let mut roots = vec![];
+        self.tasks.values().for_each(|task| {
+            task.stack.elements.iter().for_each(|val| roots.push(val.clone()));
+            task.generator_frames.iter().for_each(|(ptr, _offset)| roots.push(Value::Generator(*ptr)));
+            roots.push(task.result.clone());
+        });
+        self.channels.values().for_each(|channel| channel.iter().for_each(|val| roots.push(val.clone())));
+
+        roots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::VirtualClock;
+    use crate::error::Error;
+    use crate::input::Lines;
+    use crate::{execute_with_options, Options};
+    use super::*;
+
+    fn scheduler() -> Scheduler {
+        Scheduler::new(Box::new(VirtualClock::new()), Box::new(Lines::new(Vec::<String>::new())))
+    }
+
+    fn rooted_numbers(scheduler: &Scheduler) -> Vec<f64> {
+        scheduler.roots().iter().filter_map(|val| match val {
+            Value::Number(num) => Some(*num),
+            _ => None
+        }).collect()
+    }
+
+    #[test]
+    fn test_awaited_tasks_are_freed() {
+        let mut scheduler = scheduler();
+        let id = scheduler.spawn(Stack::init(), 0);
+        let task = scheduler.task_mut(id);
+        task.state = TaskState::Done;
+        task.result = Value::Number(3f64);
+        assert_eq!(rooted_numbers(&scheduler), vec![3f64]);
+
+        assert!(matches!(scheduler.take_result(id), Value::Number(num) if num == 3f64));
+        assert!(scheduler.task(id).is_none());
+        assert!(rooted_numbers(&scheduler).is_empty());
+        assert_ne!(scheduler.spawn(Stack::init(), 0), id);
+    }
+
+    #[test]
+    fn test_tasks_awaited_together_get_the_same_result() {
+        let mut scheduler = scheduler();
+        let id = scheduler.spawn(Stack::init(), 0);
+        let waiting = scheduler.spawn(Stack::init(), 0);
+        scheduler.task_mut(waiting).state = TaskState::Awaiting(id);
+        let task = scheduler.task_mut(id);
+        task.state = TaskState::Done;
+        task.result = Value::Bool(true);
+
+        assert!(matches!(scheduler.take_result(id), Value::Bool(true)));
+        assert!(scheduler.task(id).is_some());
+        scheduler.task_mut(waiting).state = TaskState::Running;
+        assert!(matches!(scheduler.take_result(id), Value::Bool(true)));
+        assert!(scheduler.task(id).is_none());
+    }
+
+    #[test]
+    fn test_drained_channels_are_dropped() {
+        let mut scheduler = scheduler();
+        let channel = scheduler.open_channel();
+        scheduler.send(channel, Value::Number(1f64));
+        scheduler.send(channel, Value::Number(2f64));
+        assert_eq!(rooted_numbers(&scheduler), vec![1f64, 2f64]);
+
+        assert!(scheduler.receive(channel).is_some());
+        assert!(scheduler.receive(channel).is_some());
+        assert!(scheduler.receive(channel).is_none());
+        assert!(scheduler.channels.is_empty());
+        assert_ne!(scheduler.open_channel(), channel);
+    }
+
+    #[test]
+    fn test_spawning_in_a_loop_stays_within_the_heap() {
+        let source = "fun work(n) { return [n, n, n, n, n, n, n, n]; }
+            var total = 0;
+            loop 0..2000 as i {
+                var ch = channel();
+                send(ch, [i, i, i, i]);
+                receive(ch);
+                var task = spawn work(i);
+                var items = await task;
+                total = total + items's 0;
+            }
+            print total;";
+        let options = Options { max_heap_size: 8 * 1024, ..Options::default() };
+        let result = execute_with_options(source, options).unwrap();
+        assert_eq!(result.output, vec!["1999000"]);
+    }
+
+    #[test]
+    fn test_tasks_can_only_be_awaited_once() {
+        let source = "fun work(n) { return n; } var task = spawn work(1); print await task; print await task;";
+        let err = execute_with_options(source, Options::default()).err().unwrap();
+        assert!(matches!(&err, Error::Runtime(message) if message == "The task was already awaited"), "{err}");
+    }
+}
@@ -4,23 +4,49 @@ use std::rc::Rc;
 use pantera_compiler::bytecode::{Bytecode, OP_GET_GLOBAL};
 use pantera_compiler::compiler::Compiler;
 use pantera_heap::types::Type;
-use pantera_compiler::bytecode::{OP_PUSH, OP_MOD, OP_ALLOCATE_ARRAY, OP_ACCESS,OP_SET_PROPERTY, OP_ALLOCATE, OP_PRINT, OP_RETURN, OP_END_FUNCTION, OP_JUMP, OP_JUMP_IF_FALSE, OP_ADD, OP_SUB, OP_POP, OP_DIV, OP_MUL, OP_POW, OP_EQ, OP_NE, OP_AND, OP_SET, OP_SET_GLOBAL, OP_OR, OP_GE, OP_GR, OP_LE, OP_LS, OP_UNARY_NOT, OP_UNARY_SUB, OP_GET, OP_DECLARE, OP_DECLARE_GLOBAL, OP_CALL};
-use pantera_heap::heap::HeapManager;
+use pantera_compiler::bytecode::{OP_PUSH, OP_MOD, OP_ALLOCATE_ARRAY, OP_ACCESS,OP_SET_PROPERTY, OP_ALLOCATE, OP_PRINT, OP_RETURN, OP_END_FUNCTION, OP_JUMP, OP_JUMP_IF_FALSE, OP_ADD, OP_SUB, OP_POP, OP_DIV, OP_MUL, OP_POW, OP_EQ, OP_NE, OP_AND, OP_SET, OP_SET_GLOBAL, OP_OR, OP_GE, OP_GR, OP_LE, OP_LS, OP_UNARY_NOT, OP_UNARY_SUB, OP_GET, OP_DECLARE, OP_DECLARE_GLOBAL, OP_CALL, OP_GENERATOR, OP_YIELD, OP_ITERATOR, OP_ITER_NEXT, OP_ITER_CHECK, OP_SPAWN, OP_AWAIT, OP_RANGE, OP_IN, OP_TRY, OP_END_TRY};
+use pantera_heap::generator::{Generator, GeneratorState};
+use pantera_heap::arena::{Handle, HANDLE_BYTES};
+use pantera_heap::hash_table::HashTable;
+use pantera_heap::heap::{HeapError, HeapManager};
+use pantera_heap::range::RangeValue;
+use pantera_heap::runtime::{Extensions, Runtime};
 use pantera_heap::stack::Stack;
-use pantera_heap::value::{FunctionValue, Value};
+use pantera_heap::value::{FunctionValue, SchedulerCall, Value};
+use crate::engine::NativeFunction;
 use crate::gc::GC;
+use crate::limits::Limits;
+use crate::output::Output;
 use crate::runtime_context::RuntimeContext;
+use crate::scheduler::{Handler, Scheduler, TaskState};
 
-pub(crate) struct VM<'a> {
+pub(crate) struct VM {
     code: Vec<Bytecode>,
-    execution_stack: &'a mut Stack,
+    execution_stack: Stack,
     ip: usize,
-    globals: &'a mut HashMap<u16, Value>,
-    gc: &'a mut GC,
-    heap_manager: Rc<RefCell<HeapManager>>
+    globals: HashMap<u16, Value>,
+    gc: GC,
+    heap_manager: Rc<RefCell<HeapManager>>,
+    generator_frames: Vec<(Handle<Generator>, usize)>,
+    handlers: Vec<Handler>,
+    scheduler: Scheduler,
+    extensions: Extensions,
+    output: Box<dyn Output>,
+    /// Number of arguments passed to the builtin being called
+    arg_count: usize,
+    /// Host functions registered with the `Engine`, called through `FunctionValue::Native`
+    pub(crate) natives: Vec<NativeFunction>,
+    pub(crate) limits: Limits,
+    /// Frames entered by the current task
+    call_depth: usize,
+    /// The `next` key of iterator objects, looked up on every step of their loops
+    next_key: Handle<String>,
+    /// Set once the heap is full, so that the error goes past every `try`
+    pub(crate) out_of_memory: bool,
+    pub exit_code: Option<i32>
 }
 
-impl<'a> VM<'a> {
+impl VM {
     fn read_constant(&mut self) -> Value {
         let typ = Type::from(*self.peek().unwrap());
         self.advance();
@@ -32,754 +58,1463 @@ impl<'a> VM<'a> {
                 Value::Bool(Compiler::convert_bool_from_byte(val))
             },
             Type::Number => {
-                let mut bytes: [Bytecode; 4] = [0, 0, 0, 0];
-                for i in 0..4 {
-                    bytes[i] = *self.peek().unwrap();
-                    self.advance();
-                }
-                Value::Number(Compiler::convert_number_from_bytes(bytes))
+                Value::Number(f64::from_le_bytes(self.read_bytes()))
             },
             Type::Function => {
-                let mut bytes: [Bytecode; 4] = [0, 0, 0, 0];
-                for i in 0..4 {
-                    bytes[i] = *self.peek().unwrap();
-                    self.advance();
-                }
+                let ip = Compiler::convert_number_from_bytes(self.read_bytes()) as usize;
                 let arity = *self.peek().unwrap();
                 self.advance();
-                Value::Function(FunctionValue::UserDefined(Compiler::convert_number_from_bytes(bytes) as usize, arity))
+                Value::Function(FunctionValue::UserDefined(ip, arity))
             },
             Type::String => {
-                let mut bytes: [Bytecode; HeapManager::get_object_entry_size()] = [0;HeapManager::get_object_entry_size()];
-                for i in 0..HeapManager::get_object_entry_size() {
-                    bytes[i] = *self.peek().unwrap();
-                    self.advance();
-                }
-
-                let ptr = u64::from_le_bytes(bytes) as *mut u8;
-
-                Value::String(ptr)
+                Value::String(Handle::from_bytes(self.read_bytes::<HANDLE_BYTES>()))
             }
-            Type::Object => todo!(),
-            Type::Array => todo!(),
+            Type::Object | Type::Array | Type::Generator | Type::Task | Type::Channel | Type::Range | Type::Builtin | Type::Native => {
+                unreachable!("the compiler only emits null, bool, number, function and string constants")
+            },
             Type::Empty => panic!("Not a type")
         }
     }
 
-    fn pow_numbers(base: f32, pow: f32) -> f32 {
-        if pow.fract() == 0.0 {
-            let pw = pow as i32;
-            if pw < 0 {
-                return 1.0 / Self::power(base, (-pw) as u32);
-            }
-            Self::power(base, pw as u32)
-        } else {
-            panic!("Pow being a float number is not supported")
+    /// Negative indexes count from the end of the collection.
+    fn resolve_index(index: f64, len: usize) -> Option<usize> {
+        let index = if index < 0f64 { index + len as f64 } else { index };
+        if index < 0f64 || index as usize >= len {
+            return None;
         }
-    }
 
-    fn power(base: f32, pow: u32) -> f32 {
-        if pow == 0 {
-            return 1.0;
-        }
-        if pow == 1 {
-            return base;
-        }
-
-        if pow % 2 == 0 {
-            return Self::power(base, pow / 2) * Self::power(base, pow / 2);
-        }
+        Some(index as usize)
+    }
 
-        Self::power(base, pow / 2) * Self::power(base, pow / 2) * base
+    fn slice_positions(indexes: &RangeValue, len: usize) -> Vec<usize> {
+        let positions = RangeValue::new(0f64, len as f64, 1f64, false).slice(&indexes.normalized(len));
+        (0..positions.len()).map(|ind| positions.get(ind).unwrap() as usize).collect()
     }
 
     pub fn read_global(&mut self) -> u16 {
-        let mut var_key = [0u8; 2];
-        for i in 0..2 {
-            var_key[i] = *self.peek().unwrap();
-            self.advance();
+        u16::from_le_bytes(self.read_bytes())
+    }
+
+    pub fn execute(&mut self) -> Result<(), String> {
+        while self.schedule()? {
+            if let Err(err) = self.step() {
+                self.recover(err)?;
+            }
         }
 
-        u16::from_le_bytes(var_key)
+        Ok(())
     }
 
-    pub fn execute(&mut self) -> Result<Vec<String>, String> {
-        let mut string_result = vec![];
-        while !self.is_at_end() {
-            match *self.peek().unwrap() {
-                OP_PUSH => {
+    /// Runs a single instruction of the current task
+    fn step(&mut self) -> Result<(), String> {
+        self.limits.count_instruction()?;
+        match *self.peek().unwrap() {
+            OP_PUSH => {
+                self.advance();
+                let val = if *self.peek().unwrap() == OP_GET {
+                    self.advance();
+                    let var_key = *self.peek().unwrap() as usize;
+                    let value = self.execution_stack.get(var_key).unwrap().clone();
                     self.advance();
-                    let val = if *self.peek().unwrap() == OP_GET {
-                        self.advance();
-                        let var_key = *self.peek().unwrap() as usize;
-                        let value = self.execution_stack.get(var_key).unwrap().clone();
-                        self.advance();
-
-                        value
-
-                    } else if *self.peek().unwrap() == OP_GET_GLOBAL {
-                        self.advance();
-                        let var_key = self.read_global();
 
-                        self.globals.get(&var_key).unwrap_or_else(|| {panic!("Variable doesn't exist")}).clone()
-                    } else {
-                        self.read_constant()
-                    };
+                    value
 
-                    self.execution_stack.push(val);
-                },
-                OP_JUMP_IF_FALSE => {
+                } else if *self.peek().unwrap() == OP_GET_GLOBAL {
                     self.advance();
-                    let mut bytes: [u8;4] = [0;4];
-                    for i in 0..4 {
-                        bytes[i] = *self.peek().unwrap();
-                        self.advance();
-                    }
+                    let var_key = self.read_global();
 
-                    let num = Compiler::convert_number_from_bytes(bytes) as usize;
-                    let val = self.execution_stack.pop().unwrap();
-                    if let Value::Bool(false) = val {
-                        self.ip = num;
-                    }
-                },
-                OP_JUMP => {
-                    self.advance();
-                    let mut bytes: [u8;4] = [0;4];
-                    for i in 0..4 {
-                        bytes[i] = *self.peek().unwrap();
-                        self.advance();
-                    }
-                    let num = Compiler::convert_number_from_bytes(bytes) as usize;
+                    self.globals.get(&var_key).unwrap_or_else(|| {panic!("Variable doesn't exist")}).clone()
+                } else {
+                    self.read_constant()
+                };
+
+                self.execution_stack.push(val);
+            },
+            OP_JUMP_IF_FALSE => {
+                self.advance();
+                let num = self.read_address();
+                let val = self.execution_stack.pop().unwrap();
+                if let Value::Bool(false) = val {
                     self.ip = num;
                 }
-                OP_ADD => {
-                    self.advance();
-                    let val1 = self.execution_stack.pop().unwrap();
-                    let val2 = self.execution_stack.pop().unwrap();
-                    match val1 {
-                        Value::Number(num1) => {
-                            match val2 {
-                                Value::Number(num2) => {
-                                    self.execution_stack.push(Value::Number(num2 + num1));
-                                }
-                                _ => return Err("Addition of vairables of different types is not supported".to_string())
-                            }
-                        },
-                        Value::String(ptr1) => {
-                            match val2 {
-                                Value::String(ptr2) => {
-                                    self.execution_stack.push(Value::String(self.heap_manager.borrow_mut().concatenate_strings(ptr2, ptr1)));
-                                    self.gc.collect(&RuntimeContext {globals: self.globals, execution_stack: self.execution_stack});
-                                },
-                                _ => return Err("A string must only be added to another string".to_string())
-                            }
-                        },
-                        Value::Object(ptr1) => {
-                            match val2 {
-                                Value::Object(ptr2) => {
-                                    self.execution_stack.push(Value::Object(self.heap_manager.borrow_mut().concatenate_objects(ptr1, ptr2)));
-                                    self.gc.collect(&RuntimeContext {globals: self.globals, execution_stack: self.execution_stack});
-                                },
-                                _ => return Err("A string must only be added to another string".to_string())
-                            }
-                        },
-                        _ => {
-                            todo!()
+            },
+            OP_JUMP => {
+                self.advance();
+                self.ip = self.read_address();
+            }
+            OP_ADD => {
+                self.advance();
+                let val1 = self.execution_stack.pop().unwrap();
+                let val2 = self.execution_stack.pop().unwrap();
+                match val1 {
+                    Value::Number(num1) => {
+                        match val2 {
+                            Value::Number(num2) => {
+                                self.execution_stack.push(Value::Number(num2 + num1));
+                            }
+                            _ => return Err("Addition of variables of different types is not supported".to_string())
+                        }
+                    },
+                    Value::String(ptr1) => {
+                        match val2 {
+                            Value::String(ptr2) => {
+                                self.execution_stack.push(Value::String(self.heap_manager.borrow_mut().concatenate_strings(ptr2, ptr1)?));
+                                self.collect_garbage()?;
+                            },
+                            _ => return Err("A string must only be added to another string".to_string())
                         }
+                    },
+                    Value::Object(ptr1) => {
+                        match val2 {
+                            Value::Object(ptr2) => {
+                                self.execution_stack.push(Value::Object(self.heap_manager.borrow_mut().concatenate_objects(ptr1, ptr2)?));
+                                self.collect_garbage()?;
+                            },
+                            _ => return Err("An object must only be added to another object".to_string())
+                        }
+                    },
+                    _ => {
+                        return Err("Addition of anything but numbers, strings or objects is not supported".to_string())
                     }
-                },
-                OP_SUB => {
-                    self.advance();
-                    let val1 = self.execution_stack.pop().unwrap();
-                    let val2 = self.execution_stack.pop().unwrap();
-                    match val1 {
-                        Value::Number(num1) => {
-                            match val2 {
-                                Value::Number(num2) => {
-                                    self.execution_stack.push(Value::Number(num2 - num1));
-                                }
-                                _ => return Err("Addition of variables of different types is not supported".to_string())
+                }
+            },
+            OP_SUB => {
+                self.advance();
+                let val1 = self.execution_stack.pop().unwrap();
+                let val2 = self.execution_stack.pop().unwrap();
+                match val1 {
+                    Value::Number(num1) => {
+                        match val2 {
+                            Value::Number(num2) => {
+                                self.execution_stack.push(Value::Number(num2 - num1));
                             }
-                        },
-                        _ => {
-                            todo!()
+                            _ => return Err("Subtraction of variables of different types is not supported".to_string())
                         }
+                    },
+                    _ => {
+                        return Err("Subtraction of anything but numbers is not supported".to_string())
                     }
-                },
-                OP_MUL => {
-                    self.advance();
-                    let val1 = self.execution_stack.pop().unwrap();
-                    let val2 = self.execution_stack.pop().unwrap();
-                    match val1 {
-                        Value::Number(num1) => {
-                            match val2 {
-                                Value::Number(num2) => {
-                                    self.execution_stack.push(Value::Number(num2 * num1));
-                                }
-                                _ => return Err("Addition of vairables of different types is not supported".to_string())
+                }
+            },
+            OP_MUL => {
+                self.advance();
+                let val1 = self.execution_stack.pop().unwrap();
+                let val2 = self.execution_stack.pop().unwrap();
+                match val1 {
+                    Value::Number(num1) => {
+                        match val2 {
+                            Value::Number(num2) => {
+                                self.execution_stack.push(Value::Number(num2 * num1));
                             }
-                        },
-                        _ => {
-                            todo!()
+                            _ => return Err("Multiplication of variables of different types is not supported".to_string())
                         }
+                    },
+                    _ => {
+                        return Err("Multiplication of anything but numbers is not supported".to_string())
                     }
-                },
-                OP_DIV => {
-                    self.advance();
-                    let val1 = self.execution_stack.pop().unwrap();
-                    let val2 = self.execution_stack.pop().unwrap();
-                    match val1 {
-                        Value::Number(num1) => {
-                            match val2 {
-                                Value::Number(num2) => {
-                                    self.execution_stack.push(Value::Number(num2 / num1));
-                                }
-                                _ => return Err("Addition of vairables of different types is not supported".to_string())
+                }
+            },
+            OP_DIV => {
+                self.advance();
+                let val1 = self.execution_stack.pop().unwrap();
+                let val2 = self.execution_stack.pop().unwrap();
+                match val1 {
+                    Value::Number(num1) => {
+                        match val2 {
+                            Value::Number(num2) => {
+                                self.execution_stack.push(Value::Number(num2 / num1));
                             }
-                        },
-                        _ => {
-                            todo!()
+                            _ => return Err("Division of variables of different types is not supported".to_string())
                         }
+                    },
+                    _ => {
+                        return Err("Division of anything but numbers is not supported".to_string())
                     }
-                },
-                OP_MOD => {
-                    self.advance();
-                    let val1 = self.execution_stack.pop().unwrap();
-                    let val2 = self.execution_stack.pop().unwrap();
-
-                    match val1 {
-                        Value::Number(num1) => {
-                            match val2 {
-                                Value::Number(num2) => {
-                                    self.execution_stack.push(Value::Number(num2 % num1));
-                                }
-                                _ => return Err("Mod of variables of different types is not supported".to_string())
+                }
+            },
+            OP_MOD => {
+                self.advance();
+                let val1 = self.execution_stack.pop().unwrap();
+                let val2 = self.execution_stack.pop().unwrap();
+
+                match val1 {
+                    Value::Number(num1) => {
+                        match val2 {
+                            Value::Number(num2) => {
+                                self.execution_stack.push(Value::Number(num2 % num1));
                             }
-                        },
-                        _ => {
-                            todo!()
+                            _ => return Err("Mod of variables of different types is not supported".to_string())
                         }
+                    },
+                    _ => {
+                        return Err("Mod of anything but numbers is not supported".to_string())
                     }
                 }
-                OP_POW => {
-                    self.advance();
-                    let val1 = self.execution_stack.pop().unwrap();
-                    let val2 = self.execution_stack.pop().unwrap();
-                    match val1 {
-                        Value::Number(num1) => {
-                            match val2 {
-                                Value::Number(num2) => {
-                                    self.execution_stack.push(Value::Number(Self::pow_numbers(num2, num1)));
-                                }
-                                _ => return Err("Pow of variables of different types is not supported".to_string())
+            }
+            OP_POW => {
+                self.advance();
+                let val1 = self.execution_stack.pop().unwrap();
+                let val2 = self.execution_stack.pop().unwrap();
+                match val1 {
+                    Value::Number(num1) => {
+                        match val2 {
+                            Value::Number(num2) => {
+                                self.execution_stack.push(Value::Number(num2.powf(num1)));
                             }
-                        },
-                        _ => {
-                            return Err("Pow of anything but numbers is not supported".to_string())
+                            _ => return Err("Pow of variables of different types is not supported".to_string())
                         }
+                    },
+                    _ => {
+                        return Err("Pow of anything but numbers is not supported".to_string())
                     }
-                },
-                OP_EQ => {
-                    self.advance();
-                    let val1 = self.execution_stack.pop().unwrap();
-                    let val2 = self.execution_stack.pop().unwrap();
-                    match val1 {
-                        Value::Number(num1) => {
-                            match val2 {
-                                Value::Number(num2) => {
-                                    self.execution_stack.push(Value::Bool(num1 == num2));
-                                }
-                                _ => return Err("Equality of variables of different types is not supported".to_string())
+                }
+            },
+            OP_EQ => {
+                self.advance();
+                let val1 = self.execution_stack.pop().unwrap();
+                let val2 = self.execution_stack.pop().unwrap();
+                match val1 {
+                    Value::Number(num1) => {
+                        match val2 {
+                            Value::Number(num2) => {
+                                self.execution_stack.push(Value::Bool(num1 == num2));
                             }
-                        },
-                        Value::Bool(val1) => {
-                            match val2 {
-                                Value::Bool(val2) => {
-                                    self.execution_stack.push(Value::Bool(val1 == val2));
-                                }
-                                _ => return Err("Equality of variables of different types is not supported".to_string())
+                            _ => return Err("Equality of variables of different types is not supported".to_string())
+                        }
+                    },
+                    Value::Bool(val1) => {
+                        match val2 {
+                            Value::Bool(val2) => {
+                                self.execution_stack.push(Value::Bool(val1 == val2));
                             }
-                        },
-                        Value::Null => {
-                            match val2 {
-                                Value::Null => {
-                                    self.execution_stack.push(Value::Bool(true));
-                                }
-                                _ => {
-                                    self.execution_stack.push(Value::Bool(false));
-                                }
+                            _ => return Err("Equality of variables of different types is not supported".to_string())
+                        }
+                    },
+                    Value::Null => {
+                        match val2 {
+                            Value::Null => {
+                                self.execution_stack.push(Value::Bool(true));
                             }
-                        },
-                        Value::String(ptr) => {
-                            match val2 {
-                                Value::String(ptr2) => {
-                                    self.execution_stack.push(Value::Bool(HeapManager::compare_strings(ptr, ptr2)))
-                                },
-                                _ => {
-                                    self.execution_stack.push(Value::Bool(false))
-                                }
+                            _ => {
+                                self.execution_stack.push(Value::Bool(false));
                             }
-                        },
-                        Value::Function(fun) => {
-                            match val2 {
-                                Value::Function(fun2) => {
-                                    match fun {
-                                        FunctionValue::UserDefined(ip, _) => {
-                                            match fun2 {
-                                                FunctionValue::UserDefined(ip2, _) => {
-                                                    self.execution_stack.push(Value::Bool(ip == ip2));
-                                                },
-                                                _ => {
-                                                    self.execution_stack.push(Value::Bool(false));
-                                                }
+                        }
+                    },
+                    Value::String(ptr) => {
+                        match val2 {
+                            Value::String(ptr2) => {
+                                self.execution_stack.push(Value::Bool(HeapManager::compare_strings(ptr, ptr2)))
+                            },
+                            _ => {
+                                self.execution_stack.push(Value::Bool(false))
+                            }
+                        }
+                    },
+                    Value::Function(fun) => {
+                        match val2 {
+                            Value::Function(fun2) => {
+                                match fun {
+                                    FunctionValue::UserDefined(ip, _) => {
+                                        match fun2 {
+                                            FunctionValue::UserDefined(ip2, _) => {
+                                                self.execution_stack.push(Value::Bool(ip == ip2));
+                                            },
+                                            _ => {
+                                                self.execution_stack.push(Value::Bool(false));
                                             }
-                                        },
-                                        FunctionValue::Builtin(fun) => {
-                                            match fun2 {
-                                                FunctionValue::Builtin(fun2) => {
-                                                    self.execution_stack.push(Value::Bool(fun == fun2));
-                                                },
-                                                _ => {
-                                                    self.execution_stack.push(Value::Bool(false));
-                                                }
+                                        }
+                                    },
+                                    FunctionValue::Builtin(fun) => {
+                                        match fun2 {
+                                            FunctionValue::Builtin(fun2) => {
+                                                self.execution_stack.push(Value::Bool(fun == fun2));
+                                            },
+                                            _ => {
+                                                self.execution_stack.push(Value::Bool(false));
+                                            }
+                                        }
+                                    },
+                                    FunctionValue::Scheduler(call) => {
+                                        match fun2 {
+                                            FunctionValue::Scheduler(call2) => {
+                                                self.execution_stack.push(Value::Bool(call == call2));
+                                            },
+                                            _ => {
+                                                self.execution_stack.push(Value::Bool(false));
+                                            }
+                                        }
+                                    },
+                                    FunctionValue::Native(index) => {
+                                        match fun2 {
+                                            FunctionValue::Native(index2) => {
+                                                self.execution_stack.push(Value::Bool(index == index2));
+                                            },
+                                            _ => {
+                                                self.execution_stack.push(Value::Bool(false));
                                             }
                                         }
                                     }
-
-                                },
-                                _ => {
-                                    self.execution_stack.push(Value::Bool(false));
                                 }
+
+                            },
+                            _ => {
+                                self.execution_stack.push(Value::Bool(false));
                             }
                         }
-                        Value::Object(ptr) => {
-                            match val2 {
-                                Value::Object(ptr2) => {
-                                    self.execution_stack.push(Value::Bool(HeapManager::compare_objects(ptr, ptr2)))
-                                },
-                                _ => {
-                                    self.execution_stack.push(Value::Bool(false))
-                                }
+                    }
+                    Value::Object(ptr) => {
+                        match val2 {
+                            Value::Object(ptr2) => {
+                                self.execution_stack.push(Value::Bool(HeapManager::compare_objects(ptr, ptr2)))
+                            },
+                            _ => {
+                                self.execution_stack.push(Value::Bool(false))
                             }
-                        },
-                        Value::Array(ptr) => {
-                            match val2 {
-                                Value::Array(ptr2) => {
-                                    self.execution_stack.push(Value::Bool(HeapManager::compare_objects(ptr, ptr2)))
-                                },
-                                _ => {
-                                    self.execution_stack.push(Value::Bool(false))
-                                }
+                        }
+                    },
+                    Value::Array(ptr) => {
+                        match val2 {
+                            Value::Array(ptr2) => {
+                                self.execution_stack.push(Value::Bool(HeapManager::compare_arrays(ptr, ptr2)))
+                            },
+                            _ => {
+                                self.execution_stack.push(Value::Bool(false))
                             }
-                        },
-                    }
-                },
-                OP_NE => {
-                    self.advance();
-                    let val1 = self.execution_stack.pop().unwrap();
-                    let val2 = self.execution_stack.pop().unwrap();
-                    match val1 {
-                        Value::Number(num1) => {
-                            match val2 {
-                                Value::Number(num2) => {
-                                    self.execution_stack.push(Value::Bool(num1 != num2));
-                                }
-                                _ => return Err("Equality of variables of different types is not supported".to_string())
+                        }
+                    },
+                    Value::Generator(ptr) => {
+                        match val2 {
+                            Value::Generator(ptr2) => {
+                                self.execution_stack.push(Value::Bool(ptr == ptr2))
+                            },
+                            _ => {
+                                self.execution_stack.push(Value::Bool(false))
                             }
-                        },
-                        Value::Bool(val1) => {
-                            match val2 {
-                                Value::Bool(val2) => {
-                                    self.execution_stack.push(Value::Bool(val1 != val2));
-                                }
-                                _ => return Err("Equality of variables of different types is not supported".to_string())
+                        }
+                    },
+                    Value::Task(id) => {
+                        match val2 {
+                            Value::Task(id2) => {
+                                self.execution_stack.push(Value::Bool(id == id2))
+                            },
+                            _ => {
+                                self.execution_stack.push(Value::Bool(false))
                             }
-                        },
-                        Value::Null => {
-                            match val2 {
-                                Value::Null => {
-                                    self.execution_stack.push(Value::Bool(false));
-                                }
-                                _ => {
-                                    self.execution_stack.push(Value::Bool(true));
-                                }
+                        }
+                    },
+                    Value::Channel(id) => {
+                        match val2 {
+                            Value::Channel(id2) => {
+                                self.execution_stack.push(Value::Bool(id == id2))
+                            },
+                            _ => {
+                                self.execution_stack.push(Value::Bool(false))
+                            }
+                        }
+                    },
+                    Value::Range(ptr) => {
+                        match val2 {
+                            Value::Range(ptr2) => {
+                                self.execution_stack.push(Value::Bool(HeapManager::get_range(ptr)? == HeapManager::get_range(ptr2)?))
+                            },
+                            _ => {
+                                self.execution_stack.push(Value::Bool(false))
                             }
-                        },
-                        Value::Function(fun) => {
-                            match val2 {
-                                Value::Function(fun2) => {
-                                    match fun {
-                                        FunctionValue::UserDefined(ip, _) => {
-                                            match fun2 {
-                                                FunctionValue::UserDefined(ip2, _) => {
-                                                    self.execution_stack.push(Value::Bool(ip != ip2));
-                                                },
-                                                _ => {
-                                                    self.execution_stack.push(Value::Bool(true));
-                                                }
+                        }
+                    },
+                }
+            },
+            OP_NE => {
+                self.advance();
+                let val1 = self.execution_stack.pop().unwrap();
+                let val2 = self.execution_stack.pop().unwrap();
+                match val1 {
+                    Value::Number(num1) => {
+                        match val2 {
+                            Value::Number(num2) => {
+                                self.execution_stack.push(Value::Bool(num1 != num2));
+                            }
+                            _ => return Err("Equality of variables of different types is not supported".to_string())
+                        }
+                    },
+                    Value::Bool(val1) => {
+                        match val2 {
+                            Value::Bool(val2) => {
+                                self.execution_stack.push(Value::Bool(val1 != val2));
+                            }
+                            _ => return Err("Equality of variables of different types is not supported".to_string())
+                        }
+                    },
+                    Value::Null => {
+                        match val2 {
+                            Value::Null => {
+                                self.execution_stack.push(Value::Bool(false));
+                            }
+                            _ => {
+                                self.execution_stack.push(Value::Bool(true));
+                            }
+                        }
+                    },
+                    Value::Function(fun) => {
+                        match val2 {
+                            Value::Function(fun2) => {
+                                match fun {
+                                    FunctionValue::UserDefined(ip, _) => {
+                                        match fun2 {
+                                            FunctionValue::UserDefined(ip2, _) => {
+                                                self.execution_stack.push(Value::Bool(ip != ip2));
+                                            },
+                                            _ => {
+                                                self.execution_stack.push(Value::Bool(true));
+                                            }
+                                        }
+                                    },
+                                    FunctionValue::Builtin(fun) => {
+                                        match fun2 {
+                                            FunctionValue::Builtin(fun2) => {
+                                                self.execution_stack.push(Value::Bool(fun != fun2));
+                                            },
+                                            _ => {
+                                                self.execution_stack.push(Value::Bool(true));
                                             }
-                                        },
-                                        FunctionValue::Builtin(fun) => {
-                                            match fun2 {
-                                                FunctionValue::Builtin(fun2) => {
-                                                    self.execution_stack.push(Value::Bool(fun != fun2));
-                                                },
-                                                _ => {
-                                                    self.execution_stack.push(Value::Bool(true));
-                                                }
+                                        }
+                                    },
+                                    FunctionValue::Scheduler(call) => {
+                                        match fun2 {
+                                            FunctionValue::Scheduler(call2) => {
+                                                self.execution_stack.push(Value::Bool(call != call2));
+                                            },
+                                            _ => {
+                                                self.execution_stack.push(Value::Bool(true));
+                                            }
+                                        }
+                                    },
+                                    FunctionValue::Native(index) => {
+                                        match fun2 {
+                                            FunctionValue::Native(index2) => {
+                                                self.execution_stack.push(Value::Bool(index != index2));
+                                            },
+                                            _ => {
+                                                self.execution_stack.push(Value::Bool(true));
                                             }
                                         }
                                     }
-
-                                },
-                                _ => {
-                                    self.execution_stack.push(Value::Bool(true));
                                 }
+
+                            },
+                            _ => {
+                                self.execution_stack.push(Value::Bool(true));
                             }
                         }
-                        Value::String(ptr) => {
-                            match val2 {
-                                Value::String(ptr2) => {
-                                    self.execution_stack.push(Value::Bool(!HeapManager::compare_strings(ptr, ptr2)))
-                                },
-                                _ => {
-                                    self.execution_stack.push(Value::Bool(true))
-                                }
+                    }
+                    Value::String(ptr) => {
+                        match val2 {
+                            Value::String(ptr2) => {
+                                self.execution_stack.push(Value::Bool(!HeapManager::compare_strings(ptr, ptr2)))
+                            },
+                            _ => {
+                                self.execution_stack.push(Value::Bool(true))
                             }
-                        },
-                        Value::Object(ptr) => {
-                            match val2 {
-                                Value::Object(ptr2) => {
-                                    self.execution_stack.push(Value::Bool(!HeapManager::compare_objects(ptr, ptr2)))
-                                },
-                                _ => {
-                                    self.execution_stack.push(Value::Bool(true))
-                                }
+                        }
+                    },
+                    Value::Object(ptr) => {
+                        match val2 {
+                            Value::Object(ptr2) => {
+                                self.execution_stack.push(Value::Bool(!HeapManager::compare_objects(ptr, ptr2)))
+                            },
+                            _ => {
+                                self.execution_stack.push(Value::Bool(true))
                             }
-                        },
-                        Value::Array(ptr) => {
-                            match val2 {
-                                Value::Array(ptr2) => {
-                                    self.execution_stack.push(Value::Bool(!HeapManager::compare_objects(ptr, ptr2)))
-                                },
-                                _ => {
-                                    self.execution_stack.push(Value::Bool(false))
-                                }
+                        }
+                    },
+                    Value::Array(ptr) => {
+                        match val2 {
+                            Value::Array(ptr2) => {
+                                self.execution_stack.push(Value::Bool(!HeapManager::compare_arrays(ptr, ptr2)))
+                            },
+                            _ => {
+                                self.execution_stack.push(Value::Bool(false))
+                            }
+                        }
+                    },
+                    Value::Generator(ptr) => {
+                        match val2 {
+                            Value::Generator(ptr2) => {
+                                self.execution_stack.push(Value::Bool(ptr != ptr2))
+                            },
+                            _ => {
+                                self.execution_stack.push(Value::Bool(true))
                             }
-                        },
-                    }
-                },
-                OP_UNARY_NOT => {
-                    self.advance();
-                    let val1 = self.execution_stack.pop().unwrap();
-                    match val1 {
-                        Value::Bool(val1) => {
-                            self.execution_stack.push(Value::Bool(!val1));
                         }
-                        _ => {
-                            return Err("Notting a non-boolean value is not allowed".to_string());
+                    },
+                    Value::Task(id) => {
+                        match val2 {
+                            Value::Task(id2) => {
+                                self.execution_stack.push(Value::Bool(id != id2))
+                            },
+                            _ => {
+                                self.execution_stack.push(Value::Bool(true))
+                            }
                         }
-                    }
-                },
-                OP_UNARY_SUB => {
-                    self.advance();
-                    let val1 = self.execution_stack.pop().unwrap();
-                    match val1 {
-                        Value::Number(val1) => {
-                            self.execution_stack.push(Value::Number(-val1));
+                    },
+                    Value::Channel(id) => {
+                        match val2 {
+                            Value::Channel(id2) => {
+                                self.execution_stack.push(Value::Bool(id != id2))
+                            },
+                            _ => {
+                                self.execution_stack.push(Value::Bool(true))
+                            }
                         }
-                        _ => {
-                            return Err("Minusing a non-number value is not allowed".to_string());
+                    },
+                    Value::Range(ptr) => {
+                        match val2 {
+                            Value::Range(ptr2) => {
+                                self.execution_stack.push(Value::Bool(HeapManager::get_range(ptr)? != HeapManager::get_range(ptr2)?))
+                            },
+                            _ => {
+                                self.execution_stack.push(Value::Bool(true))
+                            }
                         }
+                    },
+                }
+            },
+            OP_UNARY_NOT => {
+                self.advance();
+                let val1 = self.execution_stack.pop().unwrap();
+                match val1 {
+                    Value::Bool(val1) => {
+                        self.execution_stack.push(Value::Bool(!val1));
+                    }
+                    _ => {
+                        return Err("Notting a non-boolean value is not allowed".to_string());
                     }
                 }
-                OP_AND => {
-                    self.advance();
-                    let val1 = self.execution_stack.pop().unwrap();
-                    let val2 = self.execution_stack.pop().unwrap();
-                    match val1 {
-                        Value::Bool(val1) => {
-                            match val2 {
-                                Value::Bool(val2) => {
-                                    self.execution_stack.push(Value::Bool(val1 && val2));
-                                }
-                                _ => return Err("And of vairables of different types is not supported".to_string())
+            },
+            OP_UNARY_SUB => {
+                self.advance();
+                let val1 = self.execution_stack.pop().unwrap();
+                match val1 {
+                    Value::Number(val1) => {
+                        self.execution_stack.push(Value::Number(-val1));
+                    }
+                    _ => {
+                        return Err("Minusing a non-number value is not allowed".to_string());
+                    }
+                }
+            }
+            OP_AND => {
+                self.advance();
+                let val1 = self.execution_stack.pop().unwrap();
+                let val2 = self.execution_stack.pop().unwrap();
+                match val1 {
+                    Value::Bool(val1) => {
+                        match val2 {
+                            Value::Bool(val2) => {
+                                self.execution_stack.push(Value::Bool(val1 && val2));
                             }
-                        },
-                        _ => {
-                            return Err("And of anything but boolean variables not supported".to_string())
+                            _ => return Err("And of vairables of different types is not supported".to_string())
                         }
+                    },
+                    _ => {
+                        return Err("And of anything but boolean variables not supported".to_string())
                     }
-                },
-                OP_OR => {
-                    self.advance();
-                    let val1 = self.execution_stack.pop().unwrap();
-                    let val2 = self.execution_stack.pop().unwrap();
-                    match val1 {
-                        Value::Bool(val1) => {
-                            match val2 {
-                                Value::Bool(val2) => {
-                                    self.execution_stack.push(Value::Bool(val1 || val2));
-                                }
-                                _ => return Err("And of vairables of different types is not supported".to_string())
+                }
+            },
+            OP_OR => {
+                self.advance();
+                let val1 = self.execution_stack.pop().unwrap();
+                let val2 = self.execution_stack.pop().unwrap();
+                match val1 {
+                    Value::Bool(val1) => {
+                        match val2 {
+                            Value::Bool(val2) => {
+                                self.execution_stack.push(Value::Bool(val1 || val2));
                             }
-                        },
-                        _ => {
-                            return Err("And of anything but boolean variables not supported".to_string())
+                            _ => return Err("And of vairables of different types is not supported".to_string())
                         }
+                    },
+                    _ => {
+                        return Err("And of anything but boolean variables not supported".to_string())
                     }
-                },
-                OP_GE => {
-                    self.advance();
-                    let val1 = self.execution_stack.pop().unwrap();
-                    let val2 = self.execution_stack.pop().unwrap();
-                    match val1 {
-                        Value::Number(val1) => {
-                            match val2 {
-                                Value::Number(val2) => {
-                                    self.execution_stack.push(Value::Bool(val2 >= val1));
-                                }
-                                _ => return Err("Comparison of vairables of different types is not supported".to_string())
+                }
+            },
+            OP_GE => {
+                self.advance();
+                let val1 = self.execution_stack.pop().unwrap();
+                let val2 = self.execution_stack.pop().unwrap();
+                match val1 {
+                    Value::Number(val1) => {
+                        match val2 {
+                            Value::Number(val2) => {
+                                self.execution_stack.push(Value::Bool(val2 >= val1));
                             }
-                        },
-                        _ => {
-                            return Err("Comparison of anything but numbers variables not supported".to_string())
+                            _ => return Err("Comparison of vairables of different types is not supported".to_string())
                         }
+                    },
+                    _ => {
+                        return Err("Comparison of anything but numbers variables not supported".to_string())
                     }
-                },
-                OP_GR => {
-                    self.advance();
-                    let val1 = self.execution_stack.pop().unwrap();
-                    let val2 = self.execution_stack.pop().unwrap();
-                    match val1 {
-                        Value::Number(val1) => {
-                            match val2 {
-                                Value::Number(val2) => {
-                                    self.execution_stack.push(Value::Bool(val2 > val1));
-                                }
-                                _ => return Err("Comparison of vairables of different types is not supported".to_string())
+                }
+            },
+            OP_GR => {
+                self.advance();
+                let val1 = self.execution_stack.pop().unwrap();
+                let val2 = self.execution_stack.pop().unwrap();
+                match val1 {
+                    Value::Number(val1) => {
+                        match val2 {
+                            Value::Number(val2) => {
+                                self.execution_stack.push(Value::Bool(val2 > val1));
                             }
-                        },
-                        _ => {
-                            return Err("Comparison of anything but numbers variables not supported".to_string())
+                            _ => return Err("Comparison of vairables of different types is not supported".to_string())
                         }
+                    },
+                    _ => {
+                        return Err("Comparison of anything but numbers variables not supported".to_string())
                     }
-                },
-                OP_LE => {
-                    self.advance();
-                    let val1 = self.execution_stack.pop().unwrap();
-                    let val2 = self.execution_stack.pop().unwrap();
-                    match val1 {
-                        Value::Number(val1) => {
-                            match val2 {
-                                Value::Number(val2) => {
-                                    self.execution_stack.push(Value::Bool(val2 <= val1));
-                                }
-                                _ => return Err("Comparison of vairables of different types is not supported".to_string())
+                }
+            },
+            OP_LE => {
+                self.advance();
+                let val1 = self.execution_stack.pop().unwrap();
+                let val2 = self.execution_stack.pop().unwrap();
+                match val1 {
+                    Value::Number(val1) => {
+                        match val2 {
+                            Value::Number(val2) => {
+                                self.execution_stack.push(Value::Bool(val2 <= val1));
                             }
-                        },
-                        _ => {
-                            return Err("Comparison of anything but numbers variables not supported".to_string())
+                            _ => return Err("Comparison of vairables of different types is not supported".to_string())
                         }
+                    },
+                    _ => {
+                        return Err("Comparison of anything but numbers variables not supported".to_string())
                     }
-                },
-                OP_LS => {
-                    self.advance();
-                    let val1 = self.execution_stack.pop().unwrap();
-                    let val2 = self.execution_stack.pop().unwrap();
-                    match val1 {
-                        Value::Number(val1) => {
-                            match val2 {
-                                Value::Number(val2) => {
-                                    self.execution_stack.push(Value::Bool(val2 < val1));
-                                }
-                                _ => return Err("Comparison of vairables of different types is not supported".to_string())
+                }
+            },
+            OP_LS => {
+                self.advance();
+                let val1 = self.execution_stack.pop().unwrap();
+                let val2 = self.execution_stack.pop().unwrap();
+                match val1 {
+                    Value::Number(val1) => {
+                        match val2 {
+                            Value::Number(val2) => {
+                                self.execution_stack.push(Value::Bool(val2 < val1));
                             }
-                        },
-                        _ => {
-                            return Err("Comparison of anything but numbers variables not supported".to_string())
+                            _ => return Err("Comparison of vairables of different types is not supported".to_string())
                         }
+                    },
+                    _ => {
+                        return Err("Comparison of anything but numbers variables not supported".to_string())
                     }
-                },
-                OP_POP => {
-                    self.advance();
-                    self.execution_stack.pop();
-                },
-                OP_DECLARE => {
-                    self.advance();
-                    self.execution_stack.push(Value::Null);
-                },
-                OP_DECLARE_GLOBAL => {
-                    self.advance();
-                    let var_key = self.read_global();
-                    let val = self.execution_stack.pop().unwrap();
+                }
+            },
+            OP_POP => {
+                self.advance();
+                self.execution_stack.pop();
+            },
+            OP_DECLARE => {
+                self.advance();
+                self.execution_stack.push(Value::Null);
+            },
+            OP_DECLARE_GLOBAL => {
+                self.advance();
+                let var_key = self.read_global();
+                let val = self.execution_stack.pop().unwrap();
 
-                    self.globals.insert(var_key, val);
+                self.globals.insert(var_key, val);
+            }
+            OP_PRINT => {
+                self.advance();
+                let val = self.execution_stack.pop().unwrap();
+                self.output.write(&format!("{val}\n"));
+            },
+            OP_SET => {
+                self.advance();
+                let val = self.execution_stack.pop().unwrap();
+                let var = *self.peek().unwrap();
+                self.advance();
+                self.execution_stack.push(val.clone());
+                self.execution_stack.set(var as i32, val);
+            },
+            OP_RETURN => {
+                self.advance();
+                let val = self.execution_stack.pop().unwrap();
+                self.execution_stack.set(-2, val);
+            },
+            OP_CALL => {
+                self.advance();
+                let arg_count = *self.peek().unwrap() as usize;
+                self.advance();
+                let callee = self.execution_stack.pop().unwrap();
+                self.call(callee, arg_count)?;
+            },
+            OP_END_FUNCTION => {
+                if self.is_in_generator_frame() {
+                    let (ptr, _offset) = self.generator_frames.pop().unwrap();
+                    HeapManager::update_generator(ptr, |generator| {
+                        generator.state = GeneratorState::Done;
+                        generator.locals.clear();
+                    })?;
+                    // A generator's return value is never an item, next() gives null once it's done
+                    self.execution_stack.set(-2, Value::Null);
+                }
+                self.end_frame()?;
+            },
+            OP_SPAWN => {
+                self.advance();
+                let Value::Function(FunctionValue::UserDefined(ip, ar)) = self.execution_stack.pop().unwrap() else {return Err("Only user defined functions can be spawned".to_string());};
+                let mut args = vec![];
+                for _ in 0..ar {
+                    args.push(self.execution_stack.pop().unwrap());
                 }
-                OP_PRINT => {
-                    self.advance();
-                    let val = self.execution_stack.pop().unwrap();
-                    string_result.push(format!("{val}"));
-                },
-                OP_SET => {
-                    self.advance();
-                    let val = self.execution_stack.pop().unwrap();
-                    let var = *self.peek().unwrap();
-                    self.advance();
-                    self.execution_stack.push(val.clone());
-                    self.execution_stack.set(var as i32, val);
-                },
-                OP_RETURN => {
-                    self.advance();
-                    let val = self.execution_stack.pop().unwrap();
-                    self.execution_stack.set(-2, val);
-                },
-                OP_CALL => {
-                    self.advance();
-                    let Value::Function(func_val) = self.execution_stack.pop().unwrap() else {return Err("Wrong architecture".to_string());};
-                    match func_val {
-                        FunctionValue::UserDefined(ip, ar) => {
-                            let mut args = vec![];
-                            for _ in 0..ar {
-                                args.push(self.execution_stack.pop().unwrap());
-                            }
-                            self.execution_stack.push(Value::Null);
-                            self.execution_stack.push(Value::Number(self.ip as f32));
 
-                            let old_offset = self.execution_stack.offset;
-                            self.execution_stack.offset = self.execution_stack.real_len();
+                let mut stack = Stack::init();
+                stack.push(Value::Null);
+                stack.push(Value::Number(self.code.len() as f64));
+                stack.offset = stack.real_len();
+                stack.push(Value::Number(0f64));
+                args.reverse();
+                args.into_iter().for_each(|arg| stack.push(arg));
 
-                            self.execution_stack.push(Value::Number(old_offset as f32));
+                let id = self.scheduler.spawn(stack, ip);
+                self.execution_stack.push(Value::Task(id));
+            },
+            OP_AWAIT => {
+                self.advance();
+                let Value::Task(id) = self.execution_stack.pop().unwrap() else {return Err("Only tasks can be awaited".to_string());};
+                match self.scheduler.task(id).map(|task| task.state == TaskState::Done) {
+                    None => return Err("The task was already awaited".to_string()),
+                    Some(true) => {
+                        let val = self.scheduler.take_result(id);
+                        self.execution_stack.push(val);
+                    },
+                    Some(false) => self.scheduler.task_mut(self.scheduler.current).state = TaskState::Awaiting(id)
+                }
+            },
+            OP_RANGE => {
+                self.advance();
+                let inclusive = Compiler::convert_bool_from_byte(*self.peek().unwrap());
+                self.advance();
+                let Value::Number(step) = self.execution_stack.pop().unwrap() else {return Err("Range step must be a number".to_string());};
+                let Value::Number(end) = self.execution_stack.pop().unwrap() else {return Err("Range bounds must be numbers".to_string());};
+                let Value::Number(start) = self.execution_stack.pop().unwrap() else {return Err("Range bounds must be numbers".to_string());};
+                if step == 0f64 {
+                    return Err("Range step cannot be zero".to_string());
+                }
 
-                            args.reverse();
-                            args.into_iter().for_each(|arg| self.execution_stack.push(arg));
+                let ptr = self.heap_manager.borrow_mut().allocate_range(RangeValue::new(start, end, step, inclusive));
+                self.execution_stack.push(Value::Range(ptr));
+                self.collect_garbage()?;
+            },
+            OP_IN => {
+                self.advance();
+                let container = self.execution_stack.pop().unwrap();
+                let val = self.execution_stack.pop().unwrap();
+                let found = match container {
+                    Value::Range(ptr) => matches!(val, Value::Number(num) if HeapManager::get_range(ptr)?.contains(num)),
+                    Value::Array(ptr) => HeapManager::get_array(ptr)?.iter().any(|element| element.is_same(&val)),
+                    Value::Object(ptr) => match val {
+                        Value::String(key) => HeapManager::get_object(ptr)?.keys().any(|obj_key| HeapManager::compare_strings(*obj_key, key)),
+                        _ => false
+                    },
+                    _ => return Err("Membership can only be tested on ranges, arrays and objects".to_string())
+                };
+                self.execution_stack.push(Value::Bool(found));
+            },
+            OP_GENERATOR => {
+                self.advance();
+                let locals = self.frame_locals();
+                let ptr = self.heap_manager.borrow_mut().allocate_generator(self.ip, locals);
+                self.execution_stack.set(-2, Value::Generator(ptr));
+                self.collect_garbage()?;
 
-                            self.ip = ip;
+                self.end_frame()?;
+            },
+            OP_YIELD => {
+                self.advance();
+                if !self.is_in_generator_frame() {
+                    return Err("Cannot yield outside a generator".to_string());
+                }
+                let val = self.execution_stack.pop().unwrap();
+                let (ptr, _offset) = self.generator_frames.pop().unwrap();
+                let locals = self.frame_locals();
+                HeapManager::update_generator(ptr, |generator| {
+                    generator.locals = locals;
+                    generator.ip = self.ip;
+                    generator.state = GeneratorState::Suspended;
+                })?;
+
+                self.execution_stack.set(-2, val);
+                self.end_frame()?;
+            },
+            OP_ITERATOR => {
+                self.advance();
+                let reverse = Compiler::convert_bool_from_byte(*self.peek().unwrap());
+                self.advance();
+                let iterable = self.execution_stack.elements[self.execution_stack.real_len() - 1].clone();
+                match iterable {
+                    Value::Array(_) | Value::Range(_) => {},
+                    Value::Object(ptr) => {
+                        if reverse && self.get_next_function(ptr)?.is_some() {
+                            return Err("Cannot iterate an iterator object in reverse".to_string());
                         }
-                        FunctionValue::Builtin(func) => {
-                            func(&mut self.execution_stack, Rc::clone(&self.heap_manager));
+                    },
+                    Value::Generator(_) => {
+                        if reverse {
+                            return Err("Cannot iterate a generator in reverse".to_string());
                         }
-                    }
+                    },
+                    _ => return Err("Type of object is not iterable".to_string())
+                }
 
-                },
-                OP_END_FUNCTION => {
-                    self.execution_stack.reset_to(1usize);
-                    let Value::Number(off) = self.execution_stack.pop().unwrap() else {return Err("Wrong architecture".to_string());};
-                    self.execution_stack.offset = off as usize;
-                    let Value::Number(ip) = self.execution_stack.pop().unwrap() else {return Err("Wrong architecture".to_string());};
-                    self.ip = ip as usize;
-                },
-                OP_SET_GLOBAL => {
-                    self.advance();
-                    let val = self.execution_stack.pop().unwrap();
-                    let var = self.read_global();
-                    self.execution_stack.push(val.clone());
-                    self.globals.insert(var, val);
-                },
-                OP_ALLOCATE => {
-                    self.advance();
-                    let Value::Number(len) = self.execution_stack.pop().unwrap() else {return Err("Compiling failed".to_string())};
-                    let mut values = vec![];
-                    let mut obj = HashMap::new();
-                    for _i in 0..(len as usize) {
-                        values.push(self.execution_stack.pop().unwrap());
-                    }
-                    let mut values_iter = values.into_iter();
-                    for _i in 0..(len as usize) {
-                        let Value::String(str_ptr) = self.execution_stack.pop().unwrap() else {return Err("Compiling failed".to_string())};
-                        obj.insert(str_ptr, values_iter.next().unwrap());
-                    }
+                self.execution_stack.push(Value::Number(0f64));
+            },
+            OP_ITER_NEXT => {
+                self.advance();
+                let slot = *self.peek().unwrap() as usize;
+                self.advance();
+                let reverse = Compiler::convert_bool_from_byte(*self.peek().unwrap());
+                self.advance();
+                let exit = self.read_address();
 
-                    let obj_ptr = self.heap_manager.borrow_mut().allocate_object(obj).unwrap();
-                    self.gc.collect(&RuntimeContext {globals: self.globals, execution_stack: self.execution_stack});
+                let iterable = self.execution_stack.get(slot).unwrap().clone();
+                let Value::Number(cursor) = *self.execution_stack.get(slot + 1).unwrap() else {return Err("Wrong architecture".to_string());};
+                let cursor = cursor as usize;
+                match iterable {
+                    Value::Array(ptr) => {
+                        let len = HeapManager::get_array_len(ptr)?;
+                        if cursor >= len {
+                            self.ip = exit;
+                            return Ok(());
+                        }
+                        let index = if reverse { len - 1 - cursor } else { cursor };
+                        let val = self.heap_manager.borrow().get_property_from_array_num(ptr, index)?;
+                        self.execution_stack.push(val);
+                    },
+                    Value::Range(ptr) => {
+                        let range = HeapManager::get_range(ptr)?;
+                        let len = range.len();
+                        if cursor >= len {
+                            self.ip = exit;
+                            return Ok(());
+                        }
+                        let index = if reverse { len - 1 - cursor } else { cursor };
+                        self.execution_stack.push(Value::Number(range.get(index).unwrap()));
+                    },
+                    Value::Object(ptr) => {
+                        if let Some(next) = self.get_next_function(ptr)? {
+                            let takes_iterator = matches!(next, FunctionValue::UserDefined(_, 1) | FunctionValue::Builtin(_));
+                            if takes_iterator {
+                                self.execution_stack.push(Value::Object(ptr));
+                            }
+                            self.call(Value::Function(next), takes_iterator as usize)?;
+                            return Ok(());
+                        }
 
-                    self.execution_stack.push(Value::Object(obj_ptr));
-                },
-                OP_ALLOCATE_ARRAY => {
-                    self.advance();
-                    let Value::Number(len) = self.execution_stack.pop().unwrap() else {return Err("Compiling failed".to_string())};
-                    let mut values = vec![];
-                    for _i in 0..(len as usize) {
-                        values.push(self.execution_stack.pop().unwrap());
+                        let len = HeapManager::get_object_len(ptr)?;
+                        if cursor >= len {
+                            self.ip = exit;
+                            return Ok(());
+                        }
+                        let index = if reverse { len - 1 - cursor } else { cursor };
+                        let (key, val) = HeapManager::get_object_entry(ptr, index)?;
+                        let arr_ptr = self.heap_manager.borrow_mut().allocate_array(vec![val, Value::String(key)]);
+                        self.execution_stack.push(Value::Array(arr_ptr));
+                        self.collect_garbage()?;
+                    },
+                    Value::Generator(ptr) => {
+                        self.resume_generator(ptr)?;
+                        return Ok(());
+                    },
+                    _ => return Err("Type of object is not iterable".to_string())
+                }
+
+                self.execution_stack.set((slot + 1) as i32, Value::Number((cursor + 1) as f64));
+            },
+            OP_ITER_CHECK => {
+                self.advance();
+                let slot = *self.peek().unwrap() as usize;
+                self.advance();
+                let exit = self.read_address();
+
+                let finished = match self.execution_stack.get(slot).unwrap().clone() {
+                    Value::Generator(ptr) => HeapManager::with_generator(ptr, |generator| generator.state == GeneratorState::Done)?,
+                    Value::Object(ptr) => self.get_next_function(ptr)?.is_some()
+                        && matches!(self.execution_stack.elements[self.execution_stack.real_len() - 1], Value::Null),
+                    _ => false
+                };
+                if finished {
+                    self.execution_stack.pop();
+                    self.ip = exit;
+                }
+            },
+            OP_SET_GLOBAL => {
+                self.advance();
+                let val = self.execution_stack.pop().unwrap();
+                let var = self.read_global();
+                self.execution_stack.push(val.clone());
+                self.globals.insert(var, val);
+            },
+            OP_ALLOCATE => {
+                self.advance();
+                let Value::Number(len) = self.execution_stack.pop().unwrap() else {return Err("Compiling failed".to_string())};
+                let mut values = vec![];
+                let mut obj = HashMap::new();
+                for _i in 0..(len as usize) {
+                    values.push(self.execution_stack.pop().unwrap());
+                }
+                let mut values_iter = values.into_iter();
+                for _i in 0..(len as usize) {
+                    let Value::String(str_ptr) = self.execution_stack.pop().unwrap() else {return Err("Compiling failed".to_string())};
+                    obj.insert(str_ptr, values_iter.next().unwrap());
+                }
+
+                let obj_ptr = self.heap_manager.borrow_mut().allocate_object(obj);
+                self.execution_stack.push(Value::Object(obj_ptr));
+                self.collect_garbage()?;
+            },
+            OP_ALLOCATE_ARRAY => {
+                self.advance();
+                let Value::Number(len) = self.execution_stack.pop().unwrap() else {return Err("Compiling failed".to_string())};
+                let mut values = vec![];
+                for _i in 0..(len as usize) {
+                    values.push(self.execution_stack.pop().unwrap());
+                }
+
+                let obj_ptr = self.heap_manager.borrow_mut().allocate_array(values);
+                self.execution_stack.push(Value::Array(obj_ptr));
+                self.collect_garbage()?;
+            }
+            OP_ACCESS => {
+                self.advance();
+                match self.execution_stack.pop().unwrap() {
+                    Value::Object(obj) => {
+                        let Value::String(key) = self.execution_stack.pop().unwrap() else {return Err("Not a valid key".to_string());};
+                        let val = self.heap_manager.borrow().get_property_from_object(obj, &key)?;
+                        self.execution_stack.push(val);
                     }
+                    Value::Array(arr) => {
+                        let accessor = self.execution_stack.pop().unwrap();
+                        let len = HeapManager::get_array_len(arr)?;
+                        let val = match accessor {
+                            Value::String(key) => self.heap_manager.borrow().get_property_from_array(arr, key)?,
+                            Value::Number(num) => match Self::resolve_index(num, len) {
+                                Some(index) => self.heap_manager.borrow().get_property_from_array_num(arr, index)?,
+                                None => Value::Null
+                            },
+                            Value::Range(indexes) => {
+                                let values = Self::slice_positions(&HeapManager::get_range(indexes)?, len).into_iter().rev()
+                                    .map(|index| self.heap_manager.borrow().get_property_from_array_num(arr, index))
+                                    .collect::<Result<Vec<Value>, _>>()?;
+                                Value::Array(self.heap_manager.borrow_mut().allocate_array(values))
+                            },
+                            _ => return Err("Not a valid key".to_string())
+                        };
+
+                        self.execution_stack.push(val);
+                        self.collect_garbage()?;
+                    },
+                    Value::String(str_ptr) => {
+                        let chars = HeapManager::get_string(str_ptr)?.chars().collect::<Vec<char>>();
+                        let val = match self.execution_stack.pop().unwrap() {
+                            Value::Number(num) => match Self::resolve_index(num, chars.len()) {
+                                Some(index) => Value::String(self.heap_manager.borrow_mut().allocate_string(chars[index].to_string())),
+                                None => Value::Null
+                            },
+                            Value::Range(indexes) => {
+                                let substring = Self::slice_positions(&HeapManager::get_range(indexes)?, chars.len()).into_iter()
+                                    .map(|index| chars[index])
+                                    .collect::<String>();
+                                Value::String(self.heap_manager.borrow_mut().allocate_string(substring))
+                            },
+                            _ => return Err("Not a valid key".to_string())
+                        };
 
-                    let obj_ptr = self.heap_manager.borrow_mut().allocate_array(values).unwrap();
-                    self.gc.collect(&RuntimeContext {globals: self.globals, execution_stack: self.execution_stack});
+                        self.execution_stack.push(val);
+                        self.collect_garbage()?;
+                    },
+                    Value::Range(ptr) => {
+                        let range = HeapManager::get_range(ptr)?;
+                        let val = match self.execution_stack.pop().unwrap() {
+                            Value::Number(num) => match Self::resolve_index(num, range.len()) {
+                                Some(index) => Value::Number(range.get(index).unwrap()),
+                                None => Value::Null
+                            },
+                            Value::Range(indexes) => {
+                                let slice = range.slice(&HeapManager::get_range(indexes)?.normalized(range.len()));
+                                let slice_ptr = self.heap_manager.borrow_mut().allocate_range(slice);
+                                Value::Range(slice_ptr)
+                            },
+                            _ => return Err("Not a valid key".to_string())
+                        };
 
-                    self.execution_stack.push(Value::Array(obj_ptr));
+                        self.execution_stack.push(val);
+                        self.collect_garbage()?;
+                    },
+                    Value::Generator(ptr) => {
+                        let Value::String(key) = self.execution_stack.pop().unwrap() else {return Err("Not a valid key".to_string());};
+                        let val = match HeapManager::get_string(key)?.as_str() {
+                            "next" => Value::Generator(ptr),
+                            "done" => Value::Bool(HeapManager::with_generator(ptr, |generator| generator.state == GeneratorState::Done)?),
+                            _ => return Err("Generators only expose next and done".to_string())
+                        };
+                        self.execution_stack.push(val);
+                    },
+                    _ => return Err("Not an accessible object".to_string())
                 }
-                OP_ACCESS => {
-                    self.advance();
-                    match self.execution_stack.pop().unwrap() {
-                        Value::Object(obj) => {
-                            let Value::String(key) = self.execution_stack.pop().unwrap() else {return Err("Not a valid key".to_string());};
-                            let val = self.heap_manager.borrow().get_property_from_object(obj, &key);
-                            self.execution_stack.push(val);
-                        }
-                        Value::Array(arr) => {
-                            let accessor = self.execution_stack.pop().unwrap();
-                            let val = match accessor {
-                                Value::String(key) => self.heap_manager.borrow().get_property_from_array(arr, key),
-                                Value::Number(num) => self.heap_manager.borrow().get_property_from_array_num(arr, num as usize),
-                                _ => return Err("Not a valid key".to_string())
-                            };
-
-                            self.execution_stack.push(val);
-                        },
-                        _ => return Err("Not an accessible object".to_string())
+            },
+            OP_SET_PROPERTY => {
+                self.advance();
+                let object_key = self.execution_stack.pop().unwrap();
+                match self.execution_stack.pop().unwrap() {
+                    Value::Object(obj) => {
+                        let Value::String(str_key) = object_key else { return Err("Not a valid key".to_string()); };
+                        let val_to_set = self.execution_stack.pop().unwrap();
+                        let cloned_val_to_set = val_to_set.clone();
+
+                        self.heap_manager.borrow_mut().set_property_for_object(obj, str_key, val_to_set)?;
+                        self.execution_stack.push(cloned_val_to_set);
                     }
-                },
-                OP_SET_PROPERTY => {
-                    self.advance();
-                    let object_key = self.execution_stack.pop().unwrap();
-                    match self.execution_stack.pop().unwrap() {
-                        Value::Object(obj) => {
-                            let Value::String(str_key) = object_key else { return Err("Not a valid key".to_string()); };
-                            let val_to_set = self.execution_stack.pop().unwrap();
-                            let cloned_val_to_set = val_to_set.clone();
-
-                            self.heap_manager.borrow_mut().set_property_for_object(obj, str_key, val_to_set);
-                            self.execution_stack.push(cloned_val_to_set);
-                        }
-                        Value::Array(arr) => {
-                            let val_to_set = self.execution_stack.pop().unwrap();
-                            let cloned_val_to_set = val_to_set.clone();
-
-                            match object_key {
-                                Value::String(str_key) => {
-                                    self.heap_manager.borrow_mut().set_property_for_array(arr, str_key, val_to_set);
-                                },
-                                Value::Number(num_key) => {
-                                    self.heap_manager.borrow_mut().set_property_for_array_num(arr, num_key as usize, val_to_set);
-                                }
-                                _ => { return Err("Not a valid key".to_string()); }
-                            }
+                    Value::Array(arr) => {
+                        let val_to_set = self.execution_stack.pop().unwrap();
+                        let cloned_val_to_set = val_to_set.clone();
 
-                            self.execution_stack.push(cloned_val_to_set);
-                        },
-                        _ => {
-                            return Err("Not an indexable object".to_string());
+                        match object_key {
+                            Value::String(str_key) => {
+                                self.heap_manager.borrow_mut().set_property_for_array(arr, str_key, val_to_set)?;
+                            },
+                            Value::Number(num_key) => {
+                                let len = HeapManager::get_array_len(arr)?;
+                                let index = Self::resolve_index(num_key, len).ok_or(format!("Index {num_key} out of range"))?;
+                                self.heap_manager.borrow_mut().set_property_for_array_num(arr, index, val_to_set)?;
+                            },
+                            Value::Range(_) => { return Err("Cannot assign to a slice".to_string()); }
+                            _ => { return Err("Not a valid key".to_string()); }
                         }
+
+                        self.execution_stack.push(cloned_val_to_set);
+                    },
+                    _ => {
+                        return Err("Not an indexable object".to_string());
                     }
                 }
-                _ => {
-                    todo!();
-                }
+            }
+            OP_TRY => {
+                self.advance();
+                let ip = self.read_address();
+                self.handlers.push(Handler {
+                    ip,
+                    stack_len: self.execution_stack.real_len(),
+                    offset: self.execution_stack.offset,
+                    generator_frames: self.generator_frames.len(),
+                    call_depth: self.call_depth
+                });
+            },
+            OP_END_TRY => {
+                self.advance();
+                self.handlers.pop();
+            },
+            op => {
+                unreachable!("Unknown opcode {op}, the compiler emitted bytecode this VM can't run");
             }
         }
 
-        Ok(string_result)
+        Ok(())
     }
 
-    pub fn new(code: Vec<Bytecode>, execution_stack:  &'a mut Stack, globals: &'a mut HashMap<u16, Value>, gc: &'a mut GC, heap_manager: Rc<RefCell<HeapManager>>) -> Self {
+    pub fn new(code: Vec<Bytecode>, globals: HashMap<u16, Value>, gc: GC, heap_manager: Rc<RefCell<HeapManager>>, scheduler: Scheduler, output: Box<dyn Output>) -> Self {
+        let next_key = heap_manager.borrow_mut().allocate_compiled_string("next".to_string());
         Self {
             code,
-            execution_stack,
+            execution_stack: Stack::init(),
             ip: 0usize,
             globals,
             gc,
-            heap_manager
+            heap_manager,
+            generator_frames: vec![],
+            handlers: vec![],
+            scheduler,
+            extensions: Extensions::default(),
+            output,
+            arg_count: 0,
+            natives: vec![],
+            limits: Limits::default(),
+            call_depth: 0,
+            next_key,
+            out_of_memory: false,
+            exit_code: None
+        }
+    }
+
+    fn call(&mut self, callee: Value, arg_count: usize) -> Result<(), String> {
+        match callee {
+            Value::Function(FunctionValue::UserDefined(_ip, ar)) if ar as usize != arg_count => {
+                return Err(format!("Function expects {ar} arguments but got {arg_count}"));
+            },
+            Value::Function(FunctionValue::UserDefined(ip, ar)) => {
+                let mut args = vec![];
+                for _ in 0..ar {
+                    args.push(self.execution_stack.pop().unwrap());
+                }
+                self.push_frame()?;
+
+                args.reverse();
+                args.into_iter().for_each(|arg| self.execution_stack.push(arg));
+
+                self.ip = ip;
+            },
+            Value::Function(FunctionValue::Builtin(func)) => {
+                self.arg_count = arg_count;
+                func(self)?;
+                self.collect_garbage()?;
+            },
+            Value::Function(FunctionValue::Native(index)) => {
+                self.arg_count = arg_count;
+                let native = Rc::clone(&self.natives[index]);
+                (native.borrow_mut())(self)?;
+                self.collect_garbage()?;
+            },
+            Value::Function(FunctionValue::Scheduler(call)) => {
+                self.arg_count = arg_count;
+                self.call_scheduler(call)?;
+            },
+            Value::Generator(ptr) => {
+                self.resume_generator(ptr)?;
+            },
+            _ => return Err("Wrong architecture".to_string())
+        }
+
+        Ok(())
+    }
+
+    /// Name and number of arguments of a scheduler builtin
+    fn scheduler_signature(call: &SchedulerCall) -> (&'static str, usize) {
+        match call {
+            SchedulerCall::Sleep => ("sleep", 1),
+            SchedulerCall::Input => ("input", 0),
+            SchedulerCall::Channel => ("channel", 0),
+            SchedulerCall::Send => ("send", 2),
+            SchedulerCall::Receive => ("receive", 1),
+            SchedulerCall::Exit => ("exit", 1),
+            SchedulerCall::Now => ("now", 0),
+            SchedulerCall::Clock => ("clock", 0)
+        }
+    }
+
+    fn call_scheduler(&mut self, call: SchedulerCall) -> Result<(), String> {
+        let (name, count) = Self::scheduler_signature(&call);
+        if self.arg_count != count {
+            // The arguments are dropped like those of a mismatched function call, so none
+            // of them is mistaken for a value of the caller
+            for _ in 0..self.arg_count {
+                self.execution_stack.pop();
+            }
+            return match count {
+                1 => Err(format!("{name} expects 1 argument")),
+                _ => Err(format!("{name} expects {count} arguments"))
+            };
+        }
+        let current = self.scheduler.current;
+        match call {
+            SchedulerCall::Sleep => {
+                let Value::Number(seconds) = self.execution_stack.pop().unwrap() else {return Err("Wrong argument to sleep function".to_string());};
+                let wake_up = self.scheduler.clock.now() + (seconds * 1000f64) as u64;
+                self.scheduler.task_mut(current).state = TaskState::Sleeping(wake_up);
+            },
+            SchedulerCall::Input => {
+                self.scheduler.task_mut(current).state = TaskState::Input;
+            },
+            SchedulerCall::Channel => {
+                let channel = self.scheduler.open_channel();
+                self.execution_stack.push(Value::Channel(channel));
+            },
+            SchedulerCall::Send => {
+                let val = self.execution_stack.pop().unwrap();
+                let Value::Channel(channel) = self.execution_stack.pop().unwrap() else {return Err("Values can only be sent to a channel".to_string());};
+                self.scheduler.send(channel, val);
+                self.execution_stack.push(Value::Null);
+            },
+            SchedulerCall::Exit => {
+                let Value::Number(code) = self.execution_stack.pop().unwrap() else {return Err("exit expects a number argument".to_string());};
+                if code.fract() != 0f64 {
+                    return Err("exit expects an integer exit code".to_string());
+                }
+                self.exit_code = Some(code as i32);
+            },
+            SchedulerCall::Now => {
+                let now = self.scheduler.clock.epoch();
+                self.execution_stack.push(Value::Number(now as f64));
+            },
+            SchedulerCall::Clock => {
+                let elapsed = self.scheduler.clock.elapsed();
+                self.execution_stack.push(Value::Number(elapsed));
+            },
+            SchedulerCall::Receive => {
+                let Value::Channel(channel) = self.execution_stack.pop().unwrap() else {return Err("Values can only be received from a channel".to_string());};
+                match self.scheduler.receive(channel) {
+                    Some(val) => self.execution_stack.push(val),
+                    None => self.scheduler.task_mut(current).state = TaskState::Receiving(channel)
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether the current task can run its next instruction. When the task has
+    /// finished or blocked, its state is saved and the scheduler switches to the next one.
+    fn schedule(&mut self) -> Result<bool, String> {
+        if self.exit_code.is_some() {
+            return Ok(false);
+        }
+        let current = self.scheduler.current;
+        let at_end = self.is_at_end();
+        if !at_end && self.scheduler.task_mut(current).state == TaskState::Running {
+            return Ok(true);
+        }
+
+        let task = self.scheduler.task_mut(current);
+        if at_end {
+            task.result = self.execution_stack.pop().unwrap_or(Value::Null);
+            task.state = TaskState::Done;
+        }
+        std::mem::swap(&mut self.execution_stack, &mut task.stack);
+        task.ip = self.ip;
+        task.generator_frames = std::mem::take(&mut self.generator_frames);
+        task.call_depth = self.call_depth;
+        task.handlers = std::mem::take(&mut self.handlers);
+        if at_end {
+            task.stack = Stack::init();
+        }
+
+        let Some(next) = self.scheduler.next_task(&mut self.limits)? else {
+            return Ok(false);
+        };
+
+        let task = self.scheduler.task_mut(next);
+        std::mem::swap(&mut self.execution_stack, &mut task.stack);
+        self.ip = task.ip;
+        self.generator_frames = std::mem::take(&mut task.generator_frames);
+        self.call_depth = task.call_depth;
+        self.handlers = std::mem::take(&mut task.handlers);
+        let state = std::mem::replace(&mut task.state, TaskState::Running);
+        self.scheduler.current = next;
+
+        match state {
+            TaskState::Sleeping(_) => self.execution_stack.push(Value::Null),
+            TaskState::Receiving(channel) => {
+                let val = self.scheduler.receive(channel).unwrap();
+                self.execution_stack.push(val);
+            },
+            TaskState::Awaiting(id) => {
+                let val = self.scheduler.take_result(id);
+                self.execution_stack.push(val);
+            },
+            TaskState::Input => match self.scheduler.take_line() {
+                Some(line) => {
+                    let ptr = self.heap_manager.borrow_mut().allocate_string(line);
+                    self.execution_stack.push(Value::String(ptr));
+                    self.collect_garbage()?;
+                },
+                None => self.execution_stack.push(Value::Null)
+            },
+            _ => {}
+        }
+
+        Ok(true)
+    }
+
+    /// Unwinds the current task to the innermost `try` and jumps to its handler with the
+    /// error message on the stack. Errors with no enclosing `try` are returned as is.
+    fn recover(&mut self, err: String) -> Result<(), String> {
+        if self.limits.exceeded.is_some() || self.out_of_memory {
+            return Err(err);
         }
+        let Some(handler) = self.handlers.pop() else {
+            return Err(err);
+        };
+        self.unwind(&handler)?;
+
+        let ptr = self.heap_manager.borrow_mut().allocate_string(err);
+        self.execution_stack.push(Value::String(ptr));
+        self.collect_garbage()?;
+
+        Ok(())
+    }
+
+    /// Drops the frames and generators entered since `handler` was saved and resumes at its ip
+    fn unwind(&mut self, handler: &Handler) -> Result<(), HeapError> {
+        while self.generator_frames.len() > handler.generator_frames {
+            let (ptr, _offset) = self.generator_frames.pop().unwrap();
+            HeapManager::update_generator(ptr, |generator| {
+                generator.state = GeneratorState::Done;
+                generator.locals.clear();
+            })?;
+        }
+        self.execution_stack.offset = handler.offset;
+        self.execution_stack.reset_to(handler.stack_len - handler.offset);
+        self.ip = handler.ip;
+        self.call_depth = handler.call_depth;
+        Ok(())
+    }
+
+    /// Calls `callee` from a builtin and runs it to completion, without switching tasks.
+    /// Errors the callee doesn't catch itself are returned to the builtin, with the stack
+    /// as it was before the call.
+    fn call_nested(&mut self, callee: Value, args: Vec<Value>) -> Result<Value, String> {
+        if self.exit_code.is_some() {
+            return Ok(Value::Null);
+        }
+        if matches!(callee, Value::Generator(_)) && !args.is_empty() {
+            return Err("Generators cannot be called with arguments".to_string());
+        }
+        let saved = Handler {
+            ip: self.ip,
+            stack_len: self.execution_stack.real_len(),
+            offset: self.execution_stack.offset,
+            generator_frames: self.generator_frames.len(),
+            call_depth: self.call_depth
+        };
+        let handlers = self.handlers.len();
+        let current = self.scheduler.current;
+
+        let arg_count = args.len();
+        args.into_iter().for_each(|arg| self.execution_stack.push(arg));
+        let mut result = self.call(callee.clone(), arg_count);
+        if result.is_ok() && matches!(callee, Value::Function(FunctionValue::UserDefined(..)) | Value::Generator(_)) {
+            while self.execution_stack.offset != saved.offset && self.exit_code.is_none() {
+                result = self.step();
+                if let Err(err) = result {
+                    if self.handlers.len() == handlers {
+                        result = Err(err);
+                        break;
+                    }
+                    result = self.recover(err);
+                    if result.is_err() {
+                        break;
+                    }
+                }
+                if self.scheduler.task_mut(current).state != TaskState::Running {
+                    self.scheduler.task_mut(current).state = TaskState::Running;
+                    result = Err("A task cannot be suspended inside a callback".to_string());
+                    break;
+                }
+            }
+        } else if result.is_ok() && self.scheduler.task_mut(current).state != TaskState::Running {
+            self.scheduler.task_mut(current).state = TaskState::Running;
+            result = Err("A task cannot be suspended inside a callback".to_string());
+        }
+
+        match result {
+            Ok(()) if self.exit_code.is_some() => Ok(Value::Null),
+            Ok(()) => Ok(self.execution_stack.pop().unwrap_or(Value::Null)),
+            Err(err) => {
+                self.handlers.truncate(handlers);
+                self.unwind(&saved)?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Calls a global function from the host once the script has run. It runs to completion in
+    /// the finished main task, so it cannot suspend.
+    pub(crate) fn call_global(&mut self, key: u16, args: Vec<Value>) -> Result<Value, String> {
+        let Some(callee @ Value::Function(_)) = self.globals.get(&key).cloned() else {
+            return Err("Only functions can be called from the host".to_string());
+        };
+        let current = self.scheduler.current;
+        let state = std::mem::replace(&mut self.scheduler.task_mut(current).state, TaskState::Running);
+        let result = self.call_nested(callee, args);
+        self.scheduler.task_mut(current).state = state;
+        result
+    }
+
+    /// Collects what's due, then fails if the live values still don't fit in the heap.
+    /// Like the limits, running out of memory goes past every `try`.
+    fn collect_garbage(&mut self) -> Result<(), String> {
+        self.gc.collect(&RuntimeContext {globals: &mut self.globals, execution_stack: &mut self.execution_stack, generators: &self.generator_frames, scheduler: &self.scheduler});
+        let oom = self.heap_manager.borrow().check_oom();
+        if let Err(err) = oom {
+            self.out_of_memory = true;
+            return Err(err.to_string());
+        }
+        Ok(())
+    }
+
+    fn resume_generator(&mut self, ptr: Handle<Generator>) -> Result<(), String> {
+        let (state, ip, locals) = HeapManager::with_generator(ptr, |generator| (generator.state.clone(), generator.ip, generator.locals.clone()))?;
+        match state {
+            GeneratorState::Running => return Err("Generator is already running".to_string()),
+            GeneratorState::Done => {
+                self.execution_stack.push(Value::Null);
+                return Ok(());
+            },
+            GeneratorState::Suspended => {}
+        }
+
+        self.push_frame()?;
+        locals.into_iter().for_each(|val| self.execution_stack.push(val));
+        HeapManager::update_generator(ptr, |generator| generator.state = GeneratorState::Running)?;
+        self.generator_frames.push((ptr, self.execution_stack.offset));
+        self.ip = ip;
+
+        Ok(())
+    }
+
+    fn push_frame(&mut self) -> Result<(), String> {
+        self.limits.enter_call(self.call_depth + 1)?;
+        self.call_depth += 1;
+        self.execution_stack.push(Value::Null);
+        self.execution_stack.push(Value::Number(self.ip as f64));
+
+        let old_offset = self.execution_stack.offset;
+        self.execution_stack.offset = self.execution_stack.real_len();
+
+        self.execution_stack.push(Value::Number(old_offset as f64));
+        Ok(())
+    }
+
+    fn end_frame(&mut self) -> Result<(), String> {
+        while self.handlers.last().is_some_and(|handler| handler.offset == self.execution_stack.offset) {
+            self.handlers.pop();
+        }
+        self.execution_stack.reset_to(1usize);
+        let Value::Number(off) = self.execution_stack.pop().unwrap() else {return Err("Wrong architecture".to_string());};
+        self.execution_stack.offset = off as usize;
+        let Value::Number(ip) = self.execution_stack.pop().unwrap() else {return Err("Wrong architecture".to_string());};
+        self.ip = ip as usize;
+        self.call_depth -= 1;
+
+        Ok(())
+    }
+
+    fn is_in_generator_frame(&self) -> bool {
+        self.generator_frames.last().is_some_and(|(_ptr, offset)| *offset == self.execution_stack.offset)
+    }
+
+    fn frame_locals(&self) -> Vec<Value> {
+        self.execution_stack.elements[self.execution_stack.offset + 1..self.execution_stack.real_len()].to_vec()
+    }
+
+    fn get_next_function(&self, obj_ptr: Handle<HashTable>) -> Result<Option<FunctionValue>, HeapError> {
+        match self.heap_manager.borrow().get_property_from_object(obj_ptr, &self.next_key)? {
+            Value::Function(func) => Ok(Some(func)),
+            _ => Ok(None)
+        }
+    }
+
+    fn read_address(&mut self) -> usize {
+        Compiler::convert_number_from_bytes(self.read_bytes()) as usize
+    }
+
+    /// The next `N` bytes of the code, as one operand
+    fn read_bytes<const N: usize>(&mut self) -> [Bytecode; N] {
+        let bytes = self.code[self.ip..self.ip + N].try_into().expect("the slice is N bytes long");
+        self.ip += N;
+        bytes
     }
 
     fn peek(&self) -> Option<&Bytecode> {
@@ -793,4 +1528,222 @@ impl<'a> VM<'a> {
     fn is_at_end(&self) -> bool {
         self.ip == self.code.len()
     }
-}
\ No newline at end of file
+}
+
+impl Runtime for VM {
+    fn stack(&mut self) -> &mut Stack {
+        &mut self.execution_stack
+    }
+
+    fn heap(&self) -> Rc<RefCell<HeapManager>> {
+        Rc::clone(&self.heap_manager)
+    }
+
+    fn extensions(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+
+    fn arg_count(&self) -> usize {
+        self.arg_count
+    }
+
+    fn call(&mut self, callee: Value, args: Vec<Value>) -> Result<Value, String> {
+        let arg_count = self.arg_count;
+        let result = self.call_nested(callee, args);
+        self.arg_count = arg_count;
+        result
+    }
+
+    fn write(&mut self, text: &str) {
+        self.output.write(text);
+    }
+
+    fn write_error(&mut self, text: &str) {
+        self.output.write_error(text);
+    }
+
+    fn read_line(&mut self) -> Option<String> {
+        self.scheduler.input.read_line()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::{execute_with_options, Options};
+
+    #[test]
+    fn test_array_assignment_out_of_range() {
+        for index in ["2", "-3", "100"] {
+            let source = format!("var arr = [1, 2]; arr[{index}] = 3;");
+            let err = execute_with_options(&source, Options::default()).err().unwrap();
+            assert!(matches!(err, Error::Runtime(message) if message.contains("out of range")));
+        }
+    }
+
+    #[test]
+    fn test_array_assignment_from_the_end() {
+        let source = "var arr = [1, 2]; arr[-1] = 3; print arr[1];";
+        let result = execute_with_options(source, Options::default()).unwrap();
+        assert_eq!(result.output, vec!["3"]);
+    }
+
+    #[test]
+    fn test_arithmetic_type_errors() {
+        for (source, expected) in [
+            ("var a = 1; var b = true; print a + b;", "Addition of anything but numbers, strings or objects"),
+            ("var a = 1; var b = \"a\"; print a - b;", "Subtraction of anything but numbers"),
+            ("var a = 1; var b = null; print a * b;", "Multiplication of anything but numbers"),
+            ("var a = 1; var b = [1]; print a / b;", "Division of anything but numbers"),
+            ("var a = \"a\"; var b = 1; print a mod b;", "Mod of variables of different types")
+        ] {
+            let err = execute_with_options(source, Options::default()).err().unwrap();
+            assert!(matches!(&err, Error::Runtime(message) if message.contains(expected)), "{source}: {err}");
+        }
+    }
+
+    #[test]
+    fn test_powers() {
+        let source = "var two = 2; var minus_two = 0 - 2; print two ^ 10; print two ^ minus_two; print minus_two ^ 3; print 4 ^ 0.5; print two ^ 1000000; print 1 ^ 10000000000; print 0.5 ^ 1000000;";
+        let result = execute_with_options(source, Options::default()).unwrap();
+        assert_eq!(result.output, vec!["1024", "0.25", "-8", "2", "inf", "1", "0"]);
+    }
+
+    #[test]
+    fn test_arithmetic_type_errors_can_be_caught() {
+        let source = "var a = true; try { print a - 1; } catch err { print \"caught\"; }";
+        let result = execute_with_options(source, Options::default()).unwrap();
+        assert_eq!(result.output, vec!["caught"]);
+    }
+
+    #[test]
+    fn test_scheduler_builtins_check_their_arguments() {
+        for (call, expected) in [
+            ("sleep()", "sleep expects 1 argument"),
+            ("sleep(1, 2)", "sleep expects 1 argument"),
+            ("input(1)", "input expects 0 arguments"),
+            ("channel(5)", "channel expects 0 arguments"),
+            ("send(ch)", "send expects 2 arguments"),
+            ("send(ch, 1, 2)", "send expects 2 arguments"),
+            ("receive()", "receive expects 1 argument"),
+            ("receive(ch, 1)", "receive expects 1 argument"),
+            ("now(1)", "now expects 0 arguments"),
+            ("clock(ch)", "clock expects 0 arguments")
+        ] {
+            let source = format!("var ch = channel(); print {call};");
+            let err = execute_with_options(&source, Options::default()).err().unwrap();
+            assert!(matches!(&err, Error::Runtime(message) if message == expected), "{call}: {err}");
+        }
+    }
+
+    #[test]
+    fn test_exit_stops_the_script_with_its_code() {
+        let source = "print \"before\"; exit(3); print \"after\";";
+        let result = execute_with_options(source, Options::default()).unwrap();
+        assert_eq!(result.output, vec!["before"]);
+        assert_eq!(result.exit_code, Some(3));
+
+        let result = execute_with_options("print 1;", Options::default()).unwrap();
+        assert_eq!(result.exit_code, None);
+    }
+
+    #[test]
+    fn test_exit_checks_its_argument() {
+        for (call, expected) in [
+            ("exit()", "exit expects 1 argument"),
+            ("exit(1, 2)", "exit expects 1 argument"),
+            ("exit(\"1\")", "exit expects a number argument"),
+            ("exit(1.5)", "exit expects an integer exit code")
+        ] {
+            let err = execute_with_options(&format!("{call};"), Options::default()).err().unwrap();
+            assert!(matches!(&err, Error::Runtime(message) if message == expected), "{call}: {err}");
+        }
+    }
+
+    #[test]
+    fn test_args_are_the_scripts_arguments() {
+        let source = "print len(args); loop args as arg { print arg; }";
+        let options = Options { args: vec!["first".to_string(), "--second".to_string()], ..Options::default() };
+        let result = execute_with_options(source, options).unwrap();
+        assert_eq!(result.output, vec!["2", "first", "--second"]);
+
+        let result = execute_with_options("print len(args);", Options::default()).unwrap();
+        assert_eq!(result.output, vec!["0"]);
+    }
+
+    #[test]
+    fn test_scheduler_arity_errors_keep_the_callers_values() {
+        let source = "fun wait(seconds) {
+            var local = \"kept\";
+            try { sleep(); } catch err { print err; }
+            try { channel(5); } catch err { print err; }
+            print local;
+            print seconds;
+        }
+        wait(3);";
+        let result = execute_with_options(source, Options::default()).unwrap();
+        assert_eq!(result.output, vec!["sleep expects 1 argument", "channel expects 0 arguments", "kept", "3"]);
+    }
+
+    #[test]
+    fn test_generator_return_values_are_not_items() {
+        let source = "fun pair(last) { yield 1; yield 2; return last; }
+        loop pair(3) as n { print n; }
+        var gen = pair(3);
+        print gen's next();
+        print gen's next();
+        print gen's next();
+        print gen's next();";
+        let result = execute_with_options(source, Options::default()).unwrap();
+        assert_eq!(result.output, vec!["1", "2", "1", "2", "null", "null"]);
+    }
+
+    #[test]
+    fn test_generator_exhaustion_is_distinct_from_yielded_null() {
+        let source = "fun nulls(n) { yield null; yield null; }
+        var gen = nulls(0);
+        print gen's done;
+        print gen's next();
+        print gen's done;
+        print gen's next();
+        print gen's done;
+        print gen's next();
+        print gen's done;";
+        let result = execute_with_options(source, Options::default()).unwrap();
+        assert_eq!(result.output, vec!["false", "null", "false", "null", "false", "null", "true"]);
+    }
+
+    #[test]
+    fn test_iterator_objects_across_collections() {
+        let source = "fun step(it) {
+            if it's count is it's limit { return null; }
+            it's count = it's count + 1;
+            return [it's count];
+        }
+        var counter = {count: 0, limit: 50, next: step};
+        var total = 0;
+        loop counter as item { total = total + 1; }
+        print total;";
+        let options = Options { max_heap_size: 4096, nursery_size: 0, ..Options::default() };
+        let result = execute_with_options(source, options).unwrap();
+        assert_eq!(result.output, vec!["50"]);
+    }
+
+    #[test]
+    fn test_callbacks_can_replace_the_items_they_walk() {
+        // the replaced items are garbage as soon as the array lets go of them, so the
+        // collections the callbacks trigger would free them under a copy of the array
+        let source = "fun garbage { var count = 0; loop { if count is 100 { break; } var item = [count]; count = count + 1; } }
+            var arr = [[1], [2], [3]];
+            fun clear(item) { arr[1] = 0; arr[2] = 0; garbage(); return item; }
+            print map(arr, clear);
+
+            arr = [[3], [1], [2]];
+            fun order(first, second) { arr[0] = 0; arr[1] = 0; arr[2] = 0; garbage(); return first[0] - second[0]; }
+            print sort(arr, order);
+            print arr;";
+        let options = Options { max_heap_size: 4096, nursery_size: 0, ..Options::default() };
+        let result = execute_with_options(source, options).unwrap();
+        assert_eq!(result.output, vec!["[ [ 1 ], 0, 0 ]", "[ [ 1 ], [ 2 ], [ 3 ] ]", "[ 0, 0, 0 ]"]);
+    }
+}